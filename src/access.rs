@@ -0,0 +1,101 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tantivy::Score;
+
+/// The most documents the log remembers. Once it's full, the documents opened least recently are
+/// forgotten first.
+const MAX_ENTRIES: usize = 1000;
+
+/// The time it takes for the boost from opening a document to fall by half.
+const HALF_LIFE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A record of the documents opened through search, stored alongside the index as `access.json`.
+///
+/// Each document opened gets a count of the times it has been opened and the time it was last
+/// opened, from which `boost` works out how much to favor it in search results.
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    path: PathBuf,
+    entries: HashMap<String, Access>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Access {
+    count: u32,
+    last: SystemTime,
+}
+
+impl AccessLog {
+    pub fn open(path: &Path) -> io::Result<AccessLog> {
+        let path = path.join("access.json");
+        if !path.exists() {
+            return Ok(AccessLog {
+                path,
+                ..Default::default()
+            });
+        }
+
+        let text = fs::read_to_string(&path)?;
+        let entries = serde_json::from_str(&text)?;
+        Ok(AccessLog { path, entries })
+    }
+
+    pub fn record<'a>(&mut self, paths: impl IntoIterator<Item = &'a str>) {
+        let now = SystemTime::now();
+        for path in paths {
+            let entry = self.entries.entry(path.to_owned()).or_insert(Access {
+                count: 0,
+                last: now,
+            });
+            entry.count = entry.count.saturating_add(1);
+            entry.last = now;
+        }
+    }
+
+    /// Returns the paths in the log along with the boost for each.
+    ///
+    /// Documents opened often get a larger boost, growing with the log of the number of times
+    /// they've been opened, and the boost fades as time passes since they were last opened. A
+    /// document that isn't in the log gets a boost of 1, which leaves its score unchanged.
+    pub fn boosts(&self) -> impl Iterator<Item = (&str, Score)> {
+        let now = SystemTime::now();
+        self.entries.iter().map(move |(path, access)| {
+            let age = now.duration_since(access.last).unwrap_or_default();
+            let decay = 0.5f32.powf(age.as_secs_f32() / HALF_LIFE.as_secs_f32());
+            let boost = 1.0 + (access.count as Score).ln_1p() * decay;
+            (path.as_str(), boost)
+        })
+    }
+
+    /// Forgets documents that no longer exist and those last opened before `cutoff`, returning
+    /// the number of documents forgotten.
+    pub fn prune(&mut self, cutoff: Option<SystemTime>) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|path, access| {
+            Path::new(path).exists() && cutoff.is_none_or(|cutoff| access.last >= cutoff)
+        });
+        before - self.entries.len()
+    }
+
+    /// Writes the log, forgetting the documents opened least recently if it's over capacity.
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.entries.len() > MAX_ENTRIES {
+            let mut entries: Vec<_> = self.entries.drain().collect();
+            entries.sort_unstable_by_key(|(_, access)| Reverse(access.last));
+            entries.truncate(MAX_ENTRIES);
+            self.entries = entries.into_iter().collect();
+        }
+
+        let temp = self.path.with_extension("json.tmp");
+        serde_json::to_writer(&mut File::create(&temp)?, &self.entries)?;
+        fs::rename(temp, &self.path)
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tantivy::{Opstamp, Score};
+
+/// Search results saved from earlier runs, stored alongside the index as `cache.json`.
+///
+/// Each entry records the opstamp of the last commit to the index at the time it was saved. Any
+/// commit (an update, a sync, a merge) changes the opstamp and so invalidates every entry, and
+/// rebuilding an index removes the cache along with it. Changes to the files themselves go
+/// unnoticed until the index is updated, and entries older than the ttl are ignored regardless.
+#[derive(Debug, Default)]
+pub struct ResultCache {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Entry {
+    opstamp: Opstamp,
+    created: SystemTime,
+    total: usize,
+    hits: Vec<(Score, String)>,
+}
+
+impl ResultCache {
+    pub fn open(path: &Path) -> io::Result<ResultCache> {
+        let path = path.join("cache.json");
+        if !path.exists() {
+            return Ok(ResultCache {
+                path,
+                ..Default::default()
+            });
+        }
+
+        // The cache is only ever an optimization, so one that can't be read is simply replaced.
+
+        let text = fs::read_to_string(&path)?;
+        let entries = serde_json::from_str(&text).unwrap_or_default();
+        Ok(ResultCache { path, entries })
+    }
+
+    pub fn get(
+        &self,
+        key: &str,
+        opstamp: Opstamp,
+        ttl: Duration,
+    ) -> Option<(Vec<(Score, String)>, usize)> {
+        let entry = self.entries.get(key)?;
+        if entry.is_fresh(opstamp, ttl) {
+            Some((entry.hits.clone(), entry.total))
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        key: String,
+        opstamp: Opstamp,
+        total: usize,
+        hits: Vec<(Score, String)>,
+    ) {
+        let entry = Entry {
+            opstamp,
+            created: SystemTime::now(),
+            total,
+            hits,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    /// Writes the cache, dropping any entries that could no longer be used.
+    pub fn save(&mut self, opstamp: Opstamp, ttl: Duration) -> io::Result<()> {
+        self.entries.retain(|_, entry| entry.is_fresh(opstamp, ttl));
+
+        let temp = self.path.with_extension("json.tmp");
+        serde_json::to_writer(&mut File::create(&temp)?, &self.entries)?;
+        fs::rename(temp, &self.path)
+    }
+}
+
+impl Entry {
+    fn is_fresh(&self, opstamp: Opstamp, ttl: Duration) -> bool {
+        let age = self.created.elapsed().unwrap_or(Duration::MAX);
+        self.opstamp == opstamp && age <= ttl
+    }
+}
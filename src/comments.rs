@@ -0,0 +1,225 @@
+use std::path::Path;
+
+/// How comments are written in a family of languages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// `// line` and `/* block */` comments, as in C, C++, Rust, Go, Java and JavaScript
+    CFamily,
+
+    /// `# line` comments and triple-quoted docstrings
+    Python,
+}
+
+static EXTENSIONS: &[(&str, Syntax)] = &[
+    ("c", Syntax::CFamily),
+    ("h", Syntax::CFamily),
+    ("cc", Syntax::CFamily),
+    ("cpp", Syntax::CFamily),
+    ("cxx", Syntax::CFamily),
+    ("hpp", Syntax::CFamily),
+    ("rs", Syntax::CFamily),
+    ("go", Syntax::CFamily),
+    ("java", Syntax::CFamily),
+    ("js", Syntax::CFamily),
+    ("ts", Syntax::CFamily),
+    ("cs", Syntax::CFamily),
+    ("swift", Syntax::CFamily),
+    ("kt", Syntax::CFamily),
+    ("scala", Syntax::CFamily),
+    ("py", Syntax::Python),
+    ("pyi", Syntax::Python),
+];
+
+/// Returns the comment syntax of a source file, judging by its extension.
+pub fn syntax_for(path: &Path) -> Option<Syntax> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|&(_, syntax)| syntax)
+}
+
+/// Extracts the text of every comment in a source file, one comment per line, dropping the code.
+///
+/// This is a scanner rather than a parser: it knows enough about string literals not to mistake
+/// their contents for comments, and no more. Python docstrings are taken to be any
+/// triple-quoted string.
+pub fn extract(source: &str, syntax: Syntax) -> String {
+    let mut comments = Vec::new();
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        let (comment, remainder) = match syntax {
+            Syntax::CFamily => scan_c(rest, c),
+            Syntax::Python => scan_python(rest, c),
+        };
+
+        if let Some(comment) = comment.map(str::trim).filter(|s| !s.is_empty()) {
+            comments.push(comment);
+        }
+        rest = remainder;
+    }
+
+    comments.join("\n")
+}
+
+/// Scans one token of C-family source, returning the text of the comment it forms, if any, and
+/// the source following it.
+fn scan_c(rest: &str, c: char) -> (Option<&str>, &str) {
+    if let Some(line) = rest.strip_prefix("//") {
+        let (comment, rest) = split_line(line);
+        return (Some(comment.trim_start_matches(['/', '!'])), rest);
+    }
+
+    if let Some(block) = rest.strip_prefix("/*") {
+        // Rust allows block comments to nest, and nesting does no harm elsewhere.
+        let mut depth = 1;
+        let mut idx = 0;
+        while depth > 0 && idx < block.len() {
+            if block[idx..].starts_with("/*") {
+                depth += 1;
+                idx += 2;
+            } else if block[idx..].starts_with("*/") {
+                depth -= 1;
+                idx += 2;
+            } else {
+                idx += block[idx..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+
+        let comment = block[..idx].strip_suffix("*/").unwrap_or(&block[..idx]);
+        return (Some(comment.trim_start_matches(['*', '!'])), &block[idx..]);
+    }
+
+    match c {
+        '"' => (None, skip_string(&rest[1..], "\"")),
+
+        // A quote may begin a character literal or, in Rust, a lifetime. Only the former has a
+        // closing quote close behind.
+        '\'' => {
+            let mut chars = rest[1..].char_indices();
+            let end = match chars.next() {
+                Some((_, '\\')) => rest[1..].find('\'').filter(|&end| end > 1),
+                Some(_) => chars.next().filter(|&(_, c)| c == '\'').map(|(end, _)| end),
+                None => None,
+            };
+            match end {
+                Some(end) => (None, &rest[end + 2..]),
+                None => (None, &rest[1..]),
+            }
+        }
+
+        c => (None, &rest[c.len_utf8()..]),
+    }
+}
+
+/// Scans one token of Python source, returning the text of the comment it forms, if any, and
+/// the source following it.
+fn scan_python(rest: &str, c: char) -> (Option<&str>, &str) {
+    if let Some(line) = rest.strip_prefix('#') {
+        let (comment, rest) = split_line(line);
+        return (Some(comment), rest);
+    }
+
+    for quote in ["\"\"\"", "'''"] {
+        if let Some(string) = rest.strip_prefix(quote) {
+            return match string.find(quote) {
+                Some(end) => (Some(&string[..end]), &string[end + quote.len()..]),
+                None => (Some(string), ""),
+            };
+        }
+    }
+
+    match c {
+        '"' | '\'' => (None, skip_string(&rest[1..], &rest[..1])),
+        c => (None, &rest[c.len_utf8()..]),
+    }
+}
+
+/// Skips past the end of a string literal, given the text following its opening quote.
+fn skip_string<'a>(rest: &'a str, quote: &str) -> &'a str {
+    let mut chars = rest.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return &rest[idx..],
+            c if quote.starts_with(c) => return &rest[idx + 1..],
+            _ => {}
+        }
+    }
+    ""
+}
+
+fn split_line(text: &str) -> (&str, &str) {
+    match text.find('\n') {
+        Some(end) => (&text[..end], &text[end..]),
+        None => (text, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(source: &str) -> String {
+        extract(source, Syntax::CFamily)
+    }
+
+    #[test]
+    fn line_and_doc_comments() {
+        let source = "// one\nlet x = 1; // two\n/// three\n//! four\n";
+        assert_eq!(c(source), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        let source = "a /* outer /* inner */ still outer */ b /** doc */ c";
+        assert_eq!(c(source), "outer /* inner */ still outer\ndoc");
+    }
+
+    #[test]
+    fn strings_containing_comment_markers() {
+        let source = r#"let url = "http://example.com"; let s = "/* no */"; // yes"#;
+        assert_eq!(c(source), "yes");
+
+        let source = r#"let s = "say \"//\" twice"; // after"#;
+        assert_eq!(c(source), "after");
+    }
+
+    #[test]
+    fn rust_lifetimes_and_character_literals() {
+        let source = "fn f<'a>(x: &'a str) -> char { '/' } // lifetime\n\
+                      let q = '\"'; let e = '\\''; let u = 'é'; // chars\n";
+        assert_eq!(c(source), "lifetime\nchars");
+    }
+
+    #[test]
+    fn python_comments_and_docstrings() {
+        let source = r##"
+def f(x):
+    """Docstring for f."""
+    s = "# not a comment"
+    t = '\'# nor this'
+    return x  # trailing
+'''
+block docstring
+'''
+"##;
+        assert_eq!(
+            extract(source, Syntax::Python),
+            "Docstring for f.\ntrailing\nblock docstring"
+        );
+    }
+
+    #[test]
+    fn unterminated_comments_and_strings() {
+        assert_eq!(c("code /* never closed"), "never closed");
+        assert_eq!(c("\"never closed // still string\n// comment"), "comment");
+        assert_eq!(
+            extract("\"\"\"open docstring", Syntax::Python),
+            "open docstring"
+        );
+    }
+}
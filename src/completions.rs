@@ -0,0 +1,219 @@
+use clap::{Arg, Command, ValueEnum};
+
+/// The shells completion scripts are written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Arguments whose values are the names of registered libraries.
+const LIBRARY_ARGS: &[&str] = &[
+    "index",
+    "name",
+    "old",
+    "library",
+    "libraries",
+    "source",
+    "into",
+];
+
+/// Commands whose library name is a new one, which there's no point completing.
+const NEW_LIBRARY_COMMANDS: &[&str] = &["create-index", "unpack"];
+
+/// Returns the script that hooks completion for `bin` into a shell.
+///
+/// The scripts don't know anything about the command line themselves: each asks the binary for
+/// the completions of the words typed so far (see [`complete`]), so they never go stale and can
+/// offer the names of libraries registered since the script was installed.
+pub fn script(shell: Shell, bin: &str) -> String {
+    let script = match shell {
+        Shell::Bash => {
+            r#"_BIN() {
+    local IFS=$'\n'
+    COMPREPLY=($(BIN completions --complete -- "${COMP_WORDS[@]:1:COMP_CWORD}"))
+}
+complete -o default -F _BIN BIN
+"#
+        }
+        Shell::Zsh => {
+            r#"#compdef BIN
+
+_BIN() {
+    local -a candidates
+    candidates=("${(@f)$(BIN completions --complete -- "${(@)words[2,CURRENT]}")}")
+    if [[ -n ${candidates[1]} ]]; then
+        compadd -a candidates
+    else
+        _files
+    fi
+}
+
+compdef _BIN BIN
+"#
+        }
+        Shell::Fish => {
+            r#"complete -c BIN -f -a '(BIN completions --complete -- (commandline -opc)[2..-1] (commandline -ct))'
+"#
+        }
+        Shell::Powershell => {
+            r#"Register-ArgumentCompleter -Native -CommandName BIN -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object { $_.ToString() })
+    if ($wordToComplete -eq '') { $words += '""' }
+    BIN completions --complete -- @words | ForEach-Object {
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }
+}
+"#
+        }
+    };
+
+    script.replace("BIN", bin)
+}
+
+/// Returns the completions of the last of `words`, the arguments typed so far.
+///
+/// Subcommands are followed down from the top, so what's offered is whatever the innermost
+/// command accepts: its subcommands or positional values, its options after a dash, or the
+/// values of the option before. Anything else, such as a path, is left to the shell.
+pub fn complete(
+    mut command: Command,
+    words: &[String],
+    libraries: impl Fn() -> Vec<String>,
+) -> Vec<String> {
+    let Some((current, before)) = words.split_last() else {
+        return Vec::new();
+    };
+
+    command.build();
+    let mut command = &command;
+    let mut pending: Option<&Arg> = None;
+    let mut positionals = 0;
+    let mut options_ended = false;
+
+    for word in before {
+        // Bash splits --index=name into three words.
+        if word == "=" && pending.is_some() {
+            continue;
+        }
+        if pending.take().is_some() {
+            continue;
+        }
+
+        if !options_ended && word == "--" {
+            options_ended = true;
+        } else if !options_ended && word.starts_with('-') && word.len() > 1 {
+            // An option given its value in the same word, as in --index=name or -iname, needs
+            // nothing more.
+            pending = find_option(command, word).filter(|arg| {
+                takes_value(arg)
+                    && !word.contains('=')
+                    && (word.starts_with("--") || word.len() == 2)
+            });
+        } else if let Some(sub) = command.find_subcommand(word.as_str()) {
+            command = sub;
+            positionals = 0;
+        } else {
+            positionals += 1;
+        }
+    }
+
+    let is_library = |arg: &Arg| {
+        LIBRARY_ARGS.contains(&arg.get_id().as_str())
+            && !NEW_LIBRARY_COMMANDS.contains(&command.get_name())
+    };
+
+    // An option given its value in the same word is completed as one.
+    let (prefix, current) = match current.split_once('=') {
+        Some((option, value)) if !options_ended && option.starts_with("--") => {
+            pending = find_option(command, option).filter(|arg| takes_value(arg));
+            (format!("{option}="), value)
+        }
+        _ if current == "=" && pending.is_some() => (String::new(), ""),
+        _ => (String::new(), current.as_str()),
+    };
+
+    let candidates: Vec<String> = if let Some(arg) = pending {
+        if is_library(arg) {
+            libraries()
+        } else {
+            possible_values(arg)
+        }
+    } else if current.starts_with('-') && !options_ended {
+        command
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+            .flat_map(|arg| {
+                let long = arg.get_long().map(|long| format!("--{long}"));
+                let short = arg.get_short().map(|short| format!("-{short}"));
+                long.into_iter().chain(short)
+            })
+            .collect()
+    } else {
+        // A positional argument that collects several values, like a query, takes every word
+        // from there on.
+        let arg = command
+            .get_positionals()
+            .enumerate()
+            .find(|&(index, arg)| index == positionals || (index < positionals && multiple(arg)))
+            .map(|(_, arg)| arg);
+
+        let mut candidates = match arg {
+            Some(arg) if is_library(arg) => libraries(),
+            Some(arg) => possible_values(arg),
+            None => Vec::new(),
+        };
+        if positionals == 0 {
+            candidates.extend(
+                command
+                    .get_subcommands()
+                    .filter(|sub| !sub.is_hide_set())
+                    .map(|sub| sub.get_name().to_owned()),
+            );
+        }
+        candidates
+    };
+
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(current))
+        .map(|candidate| format!("{prefix}{candidate}"))
+        .collect()
+}
+
+fn find_option<'a>(command: &'a Command, word: &str) -> Option<&'a Arg> {
+    if let Some(long) = word.strip_prefix("--") {
+        let long = long.split('=').next().unwrap_or(long);
+        command.get_arguments().find(|arg| {
+            arg.get_long() == Some(long)
+                || arg
+                    .get_all_aliases()
+                    .is_some_and(|aliases| aliases.contains(&long))
+        })
+    } else {
+        let short = word.chars().nth(1)?;
+        command
+            .get_arguments()
+            .find(|arg| arg.get_short() == Some(short))
+    }
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|range| range.takes_values())
+}
+
+fn multiple(arg: &Arg) -> bool {
+    arg.get_num_args()
+        .is_some_and(|range| range.max_values() > 1)
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_owned())
+        .collect()
+}
@@ -0,0 +1,65 @@
+use std::{fs, io, num::NonZeroUsize, path::Path, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::{get_config_path, parse_extension, Format};
+
+/// Defaults for options usually given on the command line, as read from `config.toml` in the
+/// config directory. Options given on the command line win.
+///
+/// ```toml
+/// take = 25
+/// format = "json"
+/// extensions = ["md", "txt"]
+/// ```
+///
+/// `extensions` replaces the built-in set of extensions indexed by libraries created without
+/// --ext.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub take: Option<NonZeroUsize>,
+    pub skip: Option<usize>,
+    pub format: Option<Format>,
+    pub extensions: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Returns the config, reading it the first time it's asked for. A config that can't be read
+    /// is reported and ignored, leaving the built-in defaults.
+    pub fn get() -> &'static Config {
+        static CONFIG: OnceLock<Config> = OnceLock::new();
+        CONFIG.get_or_init(|| {
+            let config = get_config_path().and_then(|path| Config::from_path(&path));
+            config.unwrap_or_else(|e| {
+                eprintln!("warning: using the built-in defaults: {e}");
+                Config::default()
+            })
+        })
+    }
+
+    pub fn from_path(path: &Path) -> io::Result<Config> {
+        let path = path.join("config.toml");
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        let invalid = |e: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unable to read {}: {e}", path.display()),
+            )
+        };
+
+        let text = fs::read_to_string(&path)?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        if let Some(extensions) = &mut config.extensions {
+            *extensions = extensions
+                .iter()
+                .map(|extension| parse_extension(extension))
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    str::FromStr,
+    thread,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The address the daemon listens on unless told otherwise.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7451";
+
+/// A search sent to the daemon, written as a single line of json.
+///
+/// The daemon answers each request with a single line of json: either the results, in the same
+/// form as `--format json`, or an object with an `error` describing what went wrong.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Request {
+    pub library: String,
+    pub query: String,
+    pub limit: usize,
+}
+
+/// Where the daemon listens: a TCP address, or a unix socket written as `unix:<path>`.
+#[derive(Clone, Debug)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(_) if !cfg!(unix) => Err(String::from("unix sockets aren't supported here")),
+            Some(path) => Ok(Address::Unix(PathBuf::from(path))),
+            None => Ok(Address::Tcp(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(address) => f.write_str(address),
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub fn bind(address: &Address) -> io::Result<Self> {
+        match address {
+            Address::Tcp(address) => Ok(Listener::Tcp(TcpListener::bind(address)?)),
+
+            // A socket left behind by a daemon that didn't shut down cleanly would otherwise
+            // keep us from binding.
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                if UnixStream::connect(path).is_err() && path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    fn accept(&self) -> io::Result<Box<dyn Stream>> {
+        match self {
+            Listener::Tcp(listener) => Ok(Box::new(listener.accept()?.0)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(Box::new(listener.accept()?.0)),
+        }
+    }
+}
+
+pub fn connect(address: &Address) -> io::Result<Box<dyn Stream>> {
+    match address {
+        Address::Tcp(address) => Ok(Box::new(TcpStream::connect(address)?)),
+        #[cfg(unix)]
+        Address::Unix(path) => Ok(Box::new(UnixStream::connect(path)?)),
+        #[cfg(not(unix))]
+        Address::Unix(_) => Err(io::ErrorKind::Unsupported.into()),
+    }
+}
+
+/// Answers requests until the process is killed, each connection on its own thread.
+///
+/// `handle` turns a request into the json to send back, or an error message.
+pub fn serve<F>(listener: Listener, handle: F) -> io::Result<()>
+where
+    F: Fn(Request) -> Result<String, String> + Sync,
+{
+    thread::scope(|scope| loop {
+        match listener.accept() {
+            Ok(stream) => {
+                let handle = &handle;
+                scope.spawn(move || {
+                    if let Err(e) = answer(stream, handle) {
+                        eprintln!("connection closed: {e}");
+                    }
+                });
+            }
+            Err(e) => eprintln!("unable to accept connection: {e}"),
+        }
+    })
+}
+
+fn answer<F>(stream: Box<dyn Stream>, handle: &F) -> io::Result<()>
+where
+    F: Fn(Request) -> Result<String, String>,
+{
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    while reader.read_line(&mut line)? > 0 {
+        let response = serde_json::from_str(&line)
+            .map_err(|e| format!("invalid request: {e}"))
+            .and_then(handle)
+            .unwrap_or_else(|e| json!({ "error": e }).to_string());
+
+        let stream = reader.get_mut();
+        stream.write_all(response.as_bytes())?;
+        stream.write_all(b"\n")?;
+        stream.flush()?;
+        line.clear();
+    }
+
+    Ok(())
+}
+
+/// Sends a request to the daemon, returning its response.
+pub fn ask(address: &Address, request: &Request) -> io::Result<serde_json::Value> {
+    let mut stream = connect(address).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("unable to reach the daemon at {address}: {e}"),
+        )
+    })?;
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::zip::{self, Archive};
+
+/// A Word document that couldn't be read: it isn't a zip archive we understand, or it has no
+/// document inside.
+#[derive(Clone, Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unable to read docx: {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<zip::Error> for Error {
+    fn from(e: zip::Error) -> Self {
+        Error(e.to_string())
+    }
+}
+
+/// Reads a Word document (Office Open XML) as a single html document: a head holding the title
+/// from its core properties, if it has one, followed by a paragraph for each of its paragraphs,
+/// so that it can be indexed like any other html.
+pub fn extract(data: &[u8]) -> Result<String, Error> {
+    let archive = Archive::new(data)?;
+    let document = archive.text("word/document.xml")?;
+
+    // The title is optional, and copied as it stands, entities and all, since it ends up in
+    // html anyway.
+
+    let title = archive
+        .text("docProps/core.xml")
+        .ok()
+        .and_then(|core| {
+            Regex::new(r"<dc:title\b[^>]*>([^<]*)<")
+                .expect("valid regex")
+                .captures(&core)
+                .map(|captures| captures[1].trim().to_owned())
+        })
+        .unwrap_or_default();
+
+    // The text of a paragraph is split across runs, each with its text in <w:t> elements. Tabs
+    // and breaks are elements of their own, which would otherwise run words together.
+
+    let pieces = Regex::new(r"<w:t(?:\s[^>]*)?>([^<]*)</w:t>|<w:(?:tab|br|cr)\b[^>]*>|</w:p>")
+        .expect("valid regex");
+
+    let mut html = format!("<html><head><title>{title}</title></head><body>");
+    let mut paragraph = String::new();
+    for piece in pieces.captures_iter(&document) {
+        match piece.get(1) {
+            Some(text) => paragraph += text.as_str(),
+            None if piece[0].starts_with("</") => {
+                html += &format!("<p>{paragraph}</p>\n");
+                paragraph.clear();
+            }
+            None => paragraph += " ",
+        }
+    }
+    html += "</body></html>";
+
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::tests::archive;
+
+    static DOCUMENT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:r><w:t>Split </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">across</w:t></w:r><w:r><w:t> runs</w:t></w:r></w:p>
+<w:p><w:r><w:t>tab</w:t><w:tab/><w:t>and</w:t><w:br/><w:t>break</w:t></w:r></w:p>
+</w:body></w:document>"#;
+
+    static CORE: &[u8] =
+        br#"<cp:coreProperties><dc:title> Fish &amp; Chips </dc:title></cp:coreProperties>"#;
+
+    #[test]
+    fn paragraphs_and_title() {
+        let data = archive(&[
+            ("word/document.xml", 0, DOCUMENT),
+            ("docProps/core.xml", 0, CORE),
+        ]);
+        assert_eq!(
+            extract(&data).unwrap(),
+            "<html><head><title>Fish &amp; Chips</title></head><body>\
+             <p>Split across runs</p>\n<p>tab and break</p>\n</body></html>"
+        );
+    }
+
+    #[test]
+    fn untitled() {
+        let data = archive(&[("word/document.xml", 0, DOCUMENT)]);
+        assert!(extract(&data)
+            .unwrap()
+            .starts_with("<html><head><title></title></head><body><p>Split"));
+    }
+
+    #[test]
+    fn unreadable_documents() {
+        assert!(extract(b"").is_err());
+        assert!(extract(&archive(&[("docProps/core.xml", 0, CORE)])).is_err());
+
+        let data = archive(&[("word/document.xml", 0, DOCUMENT)]);
+        for len in 0..data.len() {
+            assert!(extract(&data[..len]).is_err(), "{len}");
+        }
+    }
+}
@@ -0,0 +1,220 @@
+/// How much of an html document is searched for a declared encoding, as in browsers.
+const PRESCAN: usize = 1024;
+
+/// How much of a file is examined when guessing whether it's UTF-16 without a byte order mark.
+const SAMPLE: usize = 4096;
+
+/// The encodings files are read in.
+///
+/// This covers what turns up in practice outside East Asia: UTF-8, UTF-16 and the Western
+/// single-byte encodings. Latin-1 is read as windows-1252, which agrees with it everywhere but
+/// the C1 controls, as browsers do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+/// The characters windows-1252 puts where Latin-1 has C1 controls (0x80 to 0x9f). The five
+/// bytes windows-1252 leaves undefined are read as the controls, as in Latin-1.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Decodes a file's contents as text.
+///
+/// The encoding is taken from a byte order mark if there is one, then from an html document's
+/// `<meta charset>`, and is otherwise guessed from the contents. Anything that can't be
+/// placed is decoded as UTF-8, with invalid bytes replaced.
+pub fn decode(data: &[u8], html: bool) -> String {
+    let (encoding, data) = match data {
+        [0xef, 0xbb, 0xbf, rest @ ..] => (Some(Encoding::Utf8), rest),
+        [0xff, 0xfe, rest @ ..] => (Some(Encoding::Utf16Le), rest),
+        [0xfe, 0xff, rest @ ..] => (Some(Encoding::Utf16Be), rest),
+        _ => (None, data),
+    };
+
+    let encoding = encoding
+        .or_else(|| html.then(|| declared(data)).flatten())
+        .or_else(|| detect(data));
+
+    match encoding {
+        Some(Encoding::Utf16Le) => decode_utf16(data, u16::from_le_bytes),
+        Some(Encoding::Utf16Be) => decode_utf16(data, u16::from_be_bytes),
+        Some(Encoding::Windows1252) => data
+            .iter()
+            .map(|&byte| match byte {
+                0x80..=0x9f => WINDOWS_1252[usize::from(byte - 0x80)],
+                byte => char::from(byte),
+            })
+            .collect(),
+        Some(Encoding::Utf8) | None => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+fn decode_utf16(data: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = data.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Finds the encoding an html document declares in a `<meta charset>` or in the content type
+/// of a `<meta http-equiv>`, if it's one we read.
+fn declared(data: &[u8]) -> Option<Encoding> {
+    let head = String::from_utf8_lossy(&data[..data.len().min(PRESCAN)]).to_ascii_lowercase();
+
+    head.match_indices("<meta").find_map(|(start, _)| {
+        let tag = &head[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        let value = tag[tag.find("charset")? + "charset".len()..].trim_start();
+        let value = value.strip_prefix('=')?.trim_start();
+        let value = value.trim_start_matches(['"', '\'']);
+        let end = value
+            .find(|c: char| matches!(c, '"' | '\'' | ';' | '/') || c.is_whitespace())
+            .unwrap_or(value.len());
+        from_label(&value[..end])
+    })
+}
+
+fn from_label(label: &str) -> Option<Encoding> {
+    match label {
+        "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(Encoding::Utf8),
+
+        // A document that can declare its encoding in ascii isn't UTF-16, whatever it says.
+        "utf-16" | "utf-16le" | "utf-16be" => Some(Encoding::Utf8),
+
+        "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "iso_8859-1"
+        | "latin1" | "l1" | "cp819" | "ibm819" | "csisolatin1" | "iso-ir-100" | "us-ascii"
+        | "ascii" => Some(Encoding::Windows1252),
+        _ => None,
+    }
+}
+
+/// Guesses the encoding of a file that doesn't say.
+fn detect(data: &[u8]) -> Option<Encoding> {
+    // Text in UTF-16 is mostly ascii in most languages written with the Latin alphabet, which
+    // leaves every other byte zero. That is checked first, because it's also valid UTF-8.
+
+    let sample = &data[..data.len().min(SAMPLE) & !1];
+    let pairs = sample.len() / 2;
+    if pairs > 0 {
+        let zeros = |offset| {
+            sample
+                .iter()
+                .skip(offset)
+                .step_by(2)
+                .filter(|&&b| b == 0)
+                .count()
+        };
+        let (even, odd) = (zeros(0), zeros(1));
+        if odd * 10 >= pairs * 4 && even * 10 < pairs {
+            return Some(Encoding::Utf16Le);
+        }
+        if even * 10 >= pairs * 4 && odd * 10 < pairs {
+            return Some(Encoding::Utf16Be);
+        }
+    }
+
+    if std::str::from_utf8(data).is_ok() {
+        return Some(Encoding::Utf8);
+    }
+
+    // Control characters other than whitespace don't appear in text, so a file with them is
+    // left to the fallback rather than read as windows-1252.
+
+    let binary = data
+        .iter()
+        .any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c));
+    (!binary).then_some(Encoding::Windows1252)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, unit: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        text.encode_utf16().flat_map(unit).collect()
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let mut le = vec![0xff, 0xfe];
+        le.extend(utf16("naïve café", u16::to_le_bytes));
+        assert_eq!(decode(&le, false), "naïve café");
+
+        let mut be = vec![0xfe, 0xff];
+        be.extend(utf16("naïve café", u16::to_be_bytes));
+        assert_eq!(decode(&be, false), "naïve café");
+
+        assert_eq!(decode(b"\xef\xbb\xbfplain", false), "plain");
+
+        // A byte order mark outranks what an html document declares.
+        let mut html = vec![0xff, 0xfe];
+        html.extend(utf16("<meta charset=windows-1252>é", u16::to_le_bytes));
+        assert_eq!(decode(&html, true), "<meta charset=windows-1252>é");
+    }
+
+    #[test]
+    fn utf16_without_a_byte_order_mark() {
+        let text = "a plain letter, written in UTF-16";
+        assert_eq!(decode(&utf16(text, u16::to_le_bytes), false), text);
+        assert_eq!(decode(&utf16(text, u16::to_be_bytes), false), text);
+    }
+
+    #[test]
+    fn latin1() {
+        assert_eq!(
+            decode(b"caf\xe9 cr\xe8me br\xfbl\xe9e", false),
+            "café crème brûlée"
+        );
+
+        // The C1 range is read as windows-1252, apart from the bytes it leaves undefined.
+        assert_eq!(
+            decode(b"\x93quoted\x94 \x80 \x81", false),
+            "\u{201c}quoted\u{201d} \u{20ac} \u{81}"
+        );
+    }
+
+    #[test]
+    fn html_declarations() {
+        // This is valid UTF-8, which a document declaring windows-1252 is still read as.
+        let data = b"<html><head><meta charset=\"windows-1252\"></head>caf\xc3\xa9";
+        assert!(decode(data, true).ends_with("caf\u{c3}\u{a9}"));
+        assert!(decode(data, false).ends_with("café"));
+
+        let data =
+            b"<META HTTP-EQUIV='Content-Type' CONTENT='text/html; charset=ISO-8859-1'>\xc3\xa9";
+        assert!(decode(data, true).ends_with("\u{c3}\u{a9}"));
+
+        let data = b"<meta charset=\"utf-8\">caf\xe9";
+        assert!(decode(data, true).ends_with("caf\u{fffd}"));
+
+        // A document that claims to be UTF-16 in ascii is read as UTF-8.
+        let data = "<meta charset=\"utf-16\">café".as_bytes();
+        assert!(decode(data, true).ends_with("café"));
+
+        // Unknown encodings, and declarations past the prescan, are ignored.
+        let data = b"<meta charset=\"koi8-r\">caf\xe9";
+        assert!(decode(data, true).ends_with("café"));
+        let mut data = vec![b' '; PRESCAN];
+        data.extend(b"<meta charset=\"windows-1252\">caf\xc3\xa9");
+        assert!(decode(&data, true).ends_with("café"));
+    }
+
+    #[test]
+    fn lossy_fallback() {
+        // Control characters rule out windows-1252, so the invalid byte is replaced.
+        assert_eq!(decode(b"abc\x01\xffdef", false), "abc\u{1}\u{fffd}def");
+
+        // An unpaired surrogate in UTF-16 is replaced too.
+        let mut data = vec![0xff, 0xfe];
+        data.extend([0x00, 0xd8, b'a', 0x00]);
+        assert_eq!(decode(&data, false), "\u{fffd}a");
+    }
+}
@@ -0,0 +1,188 @@
+use std::{io, path::PathBuf};
+
+use clap::Parser;
+use tantivy::{schema::Value, Index, IndexReader, ReloadPolicy, Score};
+
+use crate::{
+    build_query, build_schema, build_search_parser, get_path, get_storage_path, not_registered,
+    open_index, prepare_library, Args, IndexArgs, IndexConfig, Libraries, SearchFields,
+    WriterOptions,
+};
+
+/// A library opened for searching, for programs that embed the search engine rather than running
+/// the binary.
+///
+/// Queries are parsed and ranked as a plain search on the command line parses and ranks them, so
+/// the daemon finds what the binary finds. The reader picks up changes to the index shortly after they're committed, so an engine
+/// may be kept open across updates.
+pub struct SearchEngine {
+    name: String,
+    index: Index,
+    reader: IndexReader,
+    fields: SearchFields,
+    config: IndexConfig,
+}
+
+/// A document found by a search.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub path: PathBuf,
+    pub score: Score,
+
+    /// an html document's title or first heading, or otherwise the file's name
+    pub title: Option<String>,
+}
+
+/// What to index and what to call it, for [`SearchEngine::build_index`].
+///
+/// This is create-index with its defaults: files are indexed the way the binary indexes them
+/// unless told otherwise, and nothing is printed while indexing.
+#[derive(Clone, Debug)]
+pub struct IndexOptions {
+    pub name: String,
+    pub root: PathBuf,
+
+    /// replace any existing library of the same name, or registered to the same root
+    pub force: bool,
+
+    writer: WriterOptions,
+}
+
+impl IndexOptions {
+    pub fn new(name: impl Into<String>, root: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            root: root.into(),
+            force: false,
+            writer: WriterOptions::default(),
+        }
+    }
+}
+
+impl IndexArgs for IndexOptions {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn roots(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(vec![self.root.clone()])
+    }
+
+    fn force(&self) -> bool {
+        self.force
+    }
+
+    fn strict(&self) -> bool {
+        false
+    }
+
+    fn quiet(&self) -> bool {
+        true
+    }
+
+    fn require_docs(&self) -> bool {
+        false
+    }
+
+    fn writer_options(&self) -> &WriterOptions {
+        &self.writer
+    }
+
+    fn config(&self) -> IndexConfig {
+        IndexConfig::default()
+    }
+}
+
+impl SearchEngine {
+    /// Opens a registered library, rebuilding its index first if it was evicted.
+    pub fn open(name: &str) -> anyhow::Result<Self> {
+        let storage_path = get_storage_path()?;
+        let libraries = Libraries::from_path(&storage_path)?;
+        if libraries.get_root(name).is_none() {
+            return Err(not_registered(name).into());
+        }
+        prepare_library(&storage_path, &libraries, name)?;
+
+        let data_path = storage_path.join(name);
+        let index = open_index(&data_path)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommitWithDelay)
+            .try_into()?;
+
+        let (_schema, fields) = build_schema();
+        let config = IndexConfig::from_path(&data_path)?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            index,
+            reader,
+            fields,
+            config,
+        })
+    }
+
+    /// Builds an index of a directory and registers it as a library, as create-index does.
+    pub fn build_index(options: &IndexOptions) -> anyhow::Result<()> {
+        crate::build_index(options)
+    }
+
+    /// The names of every registered library, in order.
+    pub fn libraries() -> anyhow::Result<Vec<String>> {
+        let libraries = Libraries::from_path(&get_storage_path()?)?;
+        Ok(libraries
+            .names()
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a page of the documents matching a query, best first.
+    pub fn search(
+        &self,
+        query: &str,
+        skip: usize,
+        take: usize,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let (results, _) = self.search_with_total(query, skip, take)?;
+        Ok(results)
+    }
+
+    /// Returns a page of the documents matching a query, along with the number of matches.
+    pub(crate) fn search_with_total(
+        &self,
+        query: &str,
+        skip: usize,
+        take: usize,
+    ) -> anyhow::Result<(Vec<SearchResult>, usize)> {
+        // The query goes through the same pipeline as the command line's, so that chunks of a
+        // document are counted and shown once and priorities are applied.
+        let mut args = Args::try_parse_from(["search", "--", query])?;
+        args.skip_take.skip = Some(skip);
+        args.skip_take.take = Some(take.max(1));
+
+        let searcher = self.reader.searcher();
+        let parser = build_search_parser(&self.index, &self.fields, &self.config, &args)?;
+        let query = build_query(&self.index, &parser, &self.fields, &self.config, &args)?;
+        let (docs, total) = crate::search(&searcher, &parser, &*query, &self.fields, &args, None)?;
+
+        let results = docs
+            .into_iter()
+            .take(take)
+            .filter_map(|(score, doc)| {
+                let title = doc.get_first(self.fields.title).and_then(|x| x.as_str());
+                Some(SearchResult {
+                    path: PathBuf::from(get_path(&doc, self.fields.path)?),
+                    score,
+                    title: title.map(ToOwned::to_owned),
+                })
+            })
+            .collect();
+
+        Ok((results, total))
+    }
+}
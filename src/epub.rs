@@ -0,0 +1,192 @@
+use std::{collections::HashMap, fmt};
+
+use regex::Regex;
+
+use crate::zip::{self, Archive};
+
+/// An epub that couldn't be read: it isn't a zip archive we understand, or the package inside
+/// doesn't say where its chapters are.
+#[derive(Clone, Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unable to read epub: {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<zip::Error> for Error {
+    fn from(e: zip::Error) -> Self {
+        Error(e.to_string())
+    }
+}
+
+/// Reads an epub as a single html document: a head holding the book's title, followed by each
+/// chapter in reading order, so that it can be indexed like any other html.
+pub fn extract(data: &[u8]) -> Result<String, Error> {
+    let archive = Archive::new(data)?;
+
+    // The container names the package file, which lists the book's files and the order in
+    // which they're read. Paths within the package are relative to it.
+
+    let container = archive.text("META-INF/container.xml")?;
+    let package_path = tags(&container, "rootfile")
+        .into_iter()
+        .find_map(|tag| attribute(tag, "full-path"))
+        .ok_or_else(|| Error("the container names no package".into()))?;
+    let package = archive.text(&package_path)?;
+    let base = package_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    let items: HashMap<_, _> = tags(&package, "item")
+        .into_iter()
+        .filter_map(|tag| Some((attribute(tag, "id")?, attribute(tag, "href")?)))
+        .collect();
+
+    // The title is copied as it stands, entities and all, since it ends up in html anyway.
+
+    let title = Regex::new(r"<(?:\w+:)?title\b[^>]*>([^<]*)<")
+        .expect("valid regex")
+        .captures(&package)
+        .map(|captures| captures[1].trim().to_owned())
+        .unwrap_or_default();
+
+    let mut html = format!("<html><head><title>{title}</title></head><body>");
+    let spine = tags(&package, "itemref");
+    for id in spine.into_iter().filter_map(|tag| attribute(tag, "idref")) {
+        let href = items
+            .get(&id)
+            .ok_or_else(|| Error(format!("the spine names unknown item {id:?}")))?;
+        html += &archive.text(&resolve(base, href))?;
+        html += "\n";
+    }
+    html += "</body></html>";
+
+    Ok(html)
+}
+
+/// Finds the opening tags of the elements with a name, whatever their namespace prefix.
+fn tags<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    Regex::new(&format!(r"<(?:\w+:)?{name}\b[^>]*>"))
+        .expect("valid regex")
+        .find_iter(xml)
+        .map(|found| found.as_str())
+        .collect()
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    Regex::new(&format!(r#"\s{name}\s*=\s*(?:"([^"]*)"|'([^']*)')"#))
+        .expect("valid regex")
+        .captures(tag)
+        .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .map(|value| value.as_str().to_owned())
+}
+
+/// Resolves a link in the package to the name of a file in the archive.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href);
+
+    let mut parts: Vec<&str> = base.split('/').filter(|part| !part.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = tail
+            .get(..2)
+            .filter(|_| byte == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::tests::archive;
+
+    static CONTAINER: &[u8] = br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+
+    static PACKAGE: &[u8] = br#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title> A Tale </dc:title></metadata>
+  <manifest>
+    <item id="one" href="text/chapter%20one.xhtml" media-type="application/xhtml+xml"/>
+    <item href='../two.xhtml#start' id='two' media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="two"/><itemref idref="one"/></spine>
+</package>"#;
+
+    fn book() -> Vec<u8> {
+        archive(&[
+            ("mimetype", 0, b"application/epub+zip"),
+            ("META-INF/container.xml", 0, CONTAINER),
+            ("OEBPS/content.opf", 0, PACKAGE),
+            ("OEBPS/text/chapter one.xhtml", 0, b"<p>first</p>"),
+            ("two.xhtml", 0, b"<p>second</p>"),
+        ])
+    }
+
+    #[test]
+    fn chapters_in_reading_order() {
+        assert_eq!(
+            extract(&book()).unwrap(),
+            "<html><head><title>A Tale</title></head><body><p>second</p>\n<p>first</p>\n</body></html>"
+        );
+    }
+
+    #[test]
+    fn unreadable_books() {
+        let data = book();
+        for len in 0..data.len() {
+            assert!(extract(&data[..len]).is_err(), "{len}");
+        }
+
+        // No container, no package, and a spine naming a missing item or file.
+        let container = ("META-INF/container.xml", 0, CONTAINER);
+        assert!(extract(&archive(&[("OEBPS/content.opf", 0, PACKAGE)])).is_err());
+        assert!(extract(&archive(&[container])).is_err());
+        assert!(extract(&archive(&[container, ("OEBPS/content.opf", 0, PACKAGE)])).is_err());
+
+        let package = br#"<package><spine><itemref idref="gone"/></spine></package>"#;
+        assert!(extract(&archive(&[container, ("OEBPS/content.opf", 0, package)])).is_err());
+    }
+
+    #[test]
+    fn resolved_links() {
+        assert_eq!(resolve("OEBPS", "text/a.xhtml#note"), "OEBPS/text/a.xhtml");
+        assert_eq!(resolve("OEBPS/text", "../b%20c.xhtml"), "OEBPS/b c.xhtml");
+        assert_eq!(resolve("", "./d.xhtml"), "d.xhtml");
+        assert_eq!(resolve("OEBPS", "../../e.xhtml"), "e.xhtml");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+}
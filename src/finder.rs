@@ -0,0 +1,288 @@
+use std::io::{self, Read, Write};
+
+/// Something the finder can search as the user types.
+pub trait Source {
+    /// Returns the items matching a query, best first.
+    fn search(&mut self, query: &str) -> Vec<String>;
+
+    /// Returns lines of text describing an item returned by the last search.
+    fn preview(&mut self, item: &str) -> Vec<String>;
+}
+
+/// The state of the finder between keystrokes.
+#[derive(Debug, Default)]
+struct State {
+    query: String,
+    items: Vec<String>,
+    selected: usize,
+    preview: Vec<String>,
+}
+
+enum Key {
+    Char(char),
+    Backspace,
+    ClearQuery,
+    Up,
+    Down,
+    Enter,
+    Quit,
+}
+
+/// Runs a full-screen, fzf-style finder over a source until the user picks an item or gives up,
+/// returning the item picked.
+///
+/// The query is searched again after every keystroke that changes it. The screen is drawn with
+/// plain ANSI escapes: the query on the first line, the matching items beneath it and a preview
+/// of the selected item below those.
+pub fn run(source: &mut impl Source) -> io::Result<Option<String>> {
+    let _terminal = RawTerminal::enter()?;
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut state = State::default();
+    let mut buf = [0; 64];
+
+    loop {
+        draw(&mut stdout, &state)?;
+
+        let len = stdin.read(&mut buf)?;
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let (mut searched, mut moved) = (false, false);
+        for key in parse_keys(&String::from_utf8_lossy(&buf[..len])) {
+            match key {
+                Key::Char(c) => {
+                    state.query.push(c);
+                    searched = true;
+                }
+                Key::Backspace => searched |= state.query.pop().is_some(),
+                Key::ClearQuery => {
+                    searched |= !state.query.is_empty();
+                    state.query.clear();
+                }
+                Key::Up => {
+                    moved |= state.selected > 0;
+                    state.selected = state.selected.saturating_sub(1);
+                }
+                Key::Down => {
+                    if state.selected + 1 < state.items.len() {
+                        state.selected += 1;
+                        moved = true;
+                    }
+                }
+                Key::Enter => return Ok(state.items.get(state.selected).cloned()),
+                Key::Quit => return Ok(None),
+            }
+        }
+
+        if searched {
+            state.items = source.search(&state.query);
+            state.selected = 0;
+        }
+
+        if searched || moved {
+            state.preview = match state.items.get(state.selected) {
+                Some(item) => source.preview(item),
+                None => Vec::new(),
+            };
+        }
+    }
+}
+
+fn parse_keys(input: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let key = match c {
+            // Arrow keys arrive as escape sequences, in either normal (`ESC [ A`) or application
+            // (`ESC O A`) mode. Anything else beginning with an escape is taken to be the escape
+            // key itself.
+            '\x1b' => match chars.peek() {
+                Some('[' | 'O') => {
+                    chars.next();
+                    match chars.next() {
+                        Some('A') => Key::Up,
+                        Some('B') => Key::Down,
+                        _ => continue,
+                    }
+                }
+                _ => Key::Quit,
+            },
+            '\x03' | '\x04' | '\x07' => Key::Quit,
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x15' => Key::ClearQuery,
+            '\x10' => Key::Up,
+            '\x0e' => Key::Down,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+
+    keys
+}
+
+fn draw(out: &mut impl Write, state: &State) -> io::Result<()> {
+    let (rows, cols) = terminal_size();
+    let list_rows = rows.saturating_sub(3) / 2;
+    let offset = (state.selected + 1).saturating_sub(list_rows.max(1));
+
+    let mut screen = String::from("\x1b[?25l\x1b[H");
+    let mut line = |text: &str| {
+        screen += &fit(text, cols);
+        screen += "\x1b[K\r\n";
+    };
+
+    line(&format!("> {}", state.query));
+    line(&format!("  {} results", state.items.len()));
+
+    for row in 0..list_rows {
+        let idx = offset + row;
+        match state.items.get(idx) {
+            Some(item) if idx == state.selected => line(&format!("\x1b[7m> {item}")),
+            Some(item) => line(&format!("  {item}")),
+            None => line(""),
+        }
+    }
+
+    line(&"─".repeat(cols));
+
+    let preview = state
+        .preview
+        .iter()
+        .flat_map(|text| wrap(text, cols).into_iter().chain([String::new()]));
+
+    // Every line ends in a newline, so the last row of the screen is left empty to keep the
+    // screen from scrolling.
+
+    for text in preview.take(rows.saturating_sub(list_rows + 4)) {
+        line(&text);
+    }
+
+    // The cursor is left at the end of the query, where the next character typed will appear.
+
+    let column = state.query.chars().count() + 3;
+    screen += &format!("\x1b[0m\x1b[J\x1b[1;{column}H\x1b[?25h");
+    out.write_all(screen.as_bytes())?;
+    out.flush()
+}
+
+/// Cuts text down to the width of the screen, replacing anything that would upset the layout.
+///
+/// Widths are measured in characters, so text containing wide characters may still wrap. Any
+/// escape sequence must come first, since it isn't counted as taking up space.
+fn fit(text: &str, cols: usize) -> String {
+    let (escape, text) = match text.strip_prefix("\x1b[7m") {
+        Some(text) => ("\x1b[7m", text),
+        None => ("", text),
+    };
+
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(cols)
+        .collect();
+    let padding = if escape.is_empty() {
+        0
+    } else {
+        cols - text.chars().count()
+    };
+    format!("{escape}{text}{}\x1b[0m", " ".repeat(padding))
+}
+
+fn wrap(text: &str, cols: usize) -> Vec<String> {
+    let chars: Vec<_> = text.chars().collect();
+    chars
+        .chunks(cols.max(1))
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+#[cfg(unix)]
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+    // SAFETY: TIOCGWINSZ writes a winsize through the pointer, which is valid for the duration of
+    // the call.
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_row > 0 && size.ws_col > 0 {
+        (size.ws_row.into(), size.ws_col.into())
+    } else {
+        (24, 80)
+    }
+}
+
+#[cfg(not(unix))]
+fn terminal_size() -> (usize, usize) {
+    (24, 80)
+}
+
+/// Puts the terminal into raw mode on the alternate screen for as long as it lives, restoring
+/// the terminal as it was when dropped.
+struct RawTerminal {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    #[cfg(unix)]
+    fn enter() -> io::Result<Self> {
+        // SAFETY: isatty only inspects the descriptor.
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "find must be run in a terminal",
+            ));
+        }
+
+        // SAFETY: termios is plain data, and tcgetattr fills it in before we read it.
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Keys are read one at a time, without echo, and without the terminal acting on ctrl-c
+        // or ctrl-s itself.
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        // SAFETY: raw is a valid termios copied from the one the terminal gave us.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h")?;
+        stdout.flush()?;
+        Ok(Self { original })
+    }
+
+    #[cfg(not(unix))]
+    fn enter() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "find is only supported on unix",
+        ))
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+
+        // SAFETY: original is the termios the terminal gave us on entry.
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
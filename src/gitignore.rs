@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use regex::Regex;
+
+/// The patterns of one `.gitignore` file.
+///
+/// This covers the patterns people actually write: comments, negation with `!`, patterns that
+/// only match directories (a trailing `/`), patterns anchored to the file's directory (any other
+/// `/`), and the wildcards `*`, `?`, `**` and `[...]`.
+struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Gitignore {
+    fn parse(text: &str) -> Self {
+        let rules = text.lines().filter_map(Rule::parse).collect();
+        Self { rules }
+    }
+
+    /// Decides whether a path, relative to the directory holding the `.gitignore`, is ignored.
+    /// Returns `None` if no pattern matches. As in git, the last pattern to match wins.
+    fn matched(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| is_dir || !rule.dir_only)
+            .find(|rule| rule.regex.is_match(relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+
+        // A pattern with a slash anywhere but the end is relative to the directory holding the
+        // .gitignore; one without matches at any depth.
+        let (anchored, pattern) = match pattern.strip_prefix('/') {
+            Some(pattern) => (true, pattern),
+            None => (pattern.contains('/'), pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let prefix = if anchored { "" } else { "(?:.*/)?" };
+        let regex = Regex::new(&format!("^{prefix}{}$", translate(pattern))).ok()?;
+        Some(Self {
+            regex,
+            negated,
+            dir_only,
+        })
+    }
+}
+
+/// Translates a glob into a regular expression.
+fn translate(pattern: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.next_if_eq(&'/').is_some() {
+                    regex += "(?:.*/)?";
+                } else {
+                    regex += ".*";
+                }
+            }
+            '*' => regex += "[^/]*",
+            '?' => regex += "[^/]",
+            '[' => {
+                // The class is read ahead, so that an unclosed `[` leaves the rest of the
+                // pattern to be read as it would be without it.
+                let mut ahead = chars.clone();
+                let mut class = String::from("[");
+                if ahead.next_if(|&c| c == '!' || c == '^').is_some() {
+                    class.push('^');
+                }
+                let mut closed = false;
+                for c in ahead.by_ref() {
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        class.push('\\');
+                    }
+                    class.push(c);
+                }
+
+                if closed {
+                    chars = ahead;
+                    regex += &class;
+                    regex.push(']');
+                } else {
+                    regex += &regex::escape("[");
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    regex += &regex::escape(&c.to_string());
+                }
+            }
+            c => regex += &regex::escape(&c.to_string()),
+        }
+    }
+
+    regex
+}
+
+/// Decides which paths beneath a root are ignored by the `.gitignore` files between them and the
+/// root, reading each file once.
+pub struct Ignores {
+    root: PathBuf,
+    files: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl Ignores {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Returns true if a path is ignored. Paths are checked from the top down, so a file inside an
+    /// ignored directory is never asked about.
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let components: Vec<_> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+
+        // Deeper .gitignore files take precedence, so each directory's verdict, if it has one,
+        // replaces the last.
+
+        let mut ignored = false;
+        let mut dir = self.root.clone();
+        for depth in 0..components.len() {
+            let gitignore = self.files.entry(dir.clone()).or_insert_with(|| {
+                Some(Gitignore::parse(
+                    &fs::read_to_string(dir.join(".gitignore")).ok()?,
+                ))
+            });
+
+            if let Some(gitignore) = gitignore {
+                if let Some(verdict) = gitignore.matched(&components[depth..].join("/"), is_dir) {
+                    ignored = verdict;
+                }
+            }
+            dir.push(&*components[depth]);
+        }
+
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn matched(patterns: &str, relative: &str, is_dir: bool) -> Option<bool> {
+        Gitignore::parse(patterns).matched(relative, is_dir)
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        assert!(Gitignore::parse("# build output\n\n   \n").rules.is_empty());
+        assert_eq!(matched("\\#notes", "#notes", false), Some(true));
+    }
+
+    #[test]
+    fn negation() {
+        let patterns = "*.log\n!keep.log\n";
+        assert_eq!(matched(patterns, "debug.log", false), Some(true));
+        assert_eq!(matched(patterns, "keep.log", false), Some(false));
+        assert_eq!(matched(patterns, "logs/keep.log", false), Some(false));
+        assert_eq!(matched(patterns, "notes.txt", false), None);
+
+        // The last pattern to match wins, so a negation can itself be undone.
+        assert_eq!(matched("!keep.log\n*.log\n", "keep.log", false), Some(true));
+    }
+
+    #[test]
+    fn anchored_patterns() {
+        assert_eq!(matched("/build", "build", true), Some(true));
+        assert_eq!(matched("/build", "src/build", true), None);
+
+        // A slash in the middle anchors a pattern as well as a leading one.
+        assert_eq!(matched("docs/out", "docs/out", true), Some(true));
+        assert_eq!(matched("docs/out", "site/docs/out", true), None);
+
+        assert_eq!(matched("target", "target", true), Some(true));
+        assert_eq!(matched("target", "crates/core/target", true), Some(true));
+    }
+
+    #[test]
+    fn dir_only_patterns() {
+        assert_eq!(matched("logs/", "logs", true), Some(true));
+        assert_eq!(matched("logs/", "app/logs", true), Some(true));
+        assert_eq!(matched("logs/", "logs", false), None);
+
+        // A later file-or-directory pattern still applies to files.
+        assert_eq!(matched("logs/\n!logs\n", "logs", false), Some(false));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_eq!(matched("*.tmp", "a.tmp", false), Some(true));
+        assert_eq!(matched("/*.tmp", "dir/a.tmp", false), None);
+        assert_eq!(matched("file?.txt", "file1.txt", false), Some(true));
+        assert_eq!(matched("file?.txt", "file10.txt", false), None);
+        assert_eq!(matched("[ab].txt", "b.txt", false), Some(true));
+        assert_eq!(matched("[!ab].txt", "b.txt", false), None);
+        assert_eq!(matched("[!ab].txt", "c.txt", false), Some(true));
+        assert_eq!(matched("[ab.txt", "[ab.txt", false), Some(true));
+    }
+
+    #[test]
+    fn double_stars() {
+        assert_eq!(matched("**/cache", "cache", true), Some(true));
+        assert_eq!(matched("**/cache", "a/b/cache", true), Some(true));
+
+        assert_eq!(matched("docs/**/*.md", "docs/intro.md", false), Some(true));
+        assert_eq!(
+            matched("docs/**/*.md", "docs/a/b/intro.md", false),
+            Some(true)
+        );
+        assert_eq!(matched("docs/**/*.md", "site/docs/intro.md", false), None);
+
+        assert_eq!(matched("out/**", "out/a/b.txt", false), Some(true));
+        assert_eq!(matched("out/**", "out", true), None);
+    }
+
+    #[test]
+    fn deeper_files_take_precedence() {
+        let root = env::temp_dir().join(format!("search-gitignore-test-{}", process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("sub/.gitignore"), "!keep.log\n").unwrap();
+
+        let mut ignores = Ignores::new(&root);
+        let ignored = [
+            ignores.is_ignored(&root.join("debug.log"), false),
+            ignores.is_ignored(&root.join("sub/debug.log"), false),
+            ignores.is_ignored(&root.join("sub/keep.log"), false),
+            ignores.is_ignored(&root.join("keep.log"), false),
+            ignores.is_ignored(&root.join("sub/notes.txt"), false),
+            ignores.is_ignored(Path::new("/elsewhere/debug.log"), false),
+        ];
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(ignored, [true, true, false, true, false, false]);
+    }
+}
@@ -0,0 +1,380 @@
+use std::fmt;
+
+/// The most a single stream may inflate to, which keeps a malicious file from exhausting memory.
+const MAX_OUTPUT: usize = 256 << 20;
+
+/// Lengths of the codes of the code length alphabet, in the order they're stored.
+static CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+static LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+
+static LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+static DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+static DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+#[derive(Clone, Debug)]
+pub struct Error(&'static str);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Inflates zlib-wrapped deflate data (RFC 1950 and 1951), as found in FlateDecode streams.
+///
+/// The checksum isn't verified: a stream that inflates cleanly is taken at its word.
+pub fn zlib(data: &[u8]) -> Result<Vec<u8>> {
+    match data {
+        [cmf, flg, rest @ ..]
+            if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 =>
+        {
+            if flg & 0x20 != 0 {
+                return Err(Error("preset dictionaries aren't supported"));
+            }
+            inflate(rest)
+        }
+        _ => Err(Error("invalid zlib header")),
+    }
+}
+
+/// Inflates raw deflate data.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut bits = Bits::new(data);
+    let mut out = Vec::with_capacity(data.len() * 4);
+
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, &mut out)?,
+            1 => {
+                let (lengths, distances) = fixed();
+                codes(&mut bits, &mut out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic(&mut bits)?;
+                codes(&mut bits, &mut out, &lengths, &distances)?;
+            }
+            _ => return Err(Error("invalid block type")),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buf: 0,
+            count: 0,
+        }
+    }
+
+    /// Reads `n` bits, least significant first.
+    fn bits(&mut self, n: u32) -> Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or(Error("unexpected end of data"))?;
+            self.buf |= u32::from(byte) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Discards what's left of the current byte.
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols in
+/// code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    /// Decodes one symbol, a bit at a time, as codes are stored most significant bit first.
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return self
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied()
+                    .ok_or(Error("invalid code"));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error("invalid code"))
+    }
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>) -> Result<()> {
+    bits.align();
+    let header = bits
+        .data
+        .get(bits.pos..bits.pos + 4)
+        .ok_or(Error("unexpected end of data"))?;
+    let len = usize::from(u16::from_le_bytes([header[0], header[1]]));
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != usize::from(!nlen) {
+        return Err(Error("stored block length doesn't match its complement"));
+    }
+
+    bits.pos += 4;
+    let block = bits
+        .data
+        .get(bits.pos..bits.pos + len)
+        .ok_or(Error("unexpected end of data"))?;
+    out.extend_from_slice(block);
+    bits.pos += len;
+    Ok(())
+}
+
+fn fixed() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic(bits: &mut Bits) -> Result<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+    if literals > 286 || distances > 30 {
+        return Err(Error("too many codes"));
+    }
+
+    let mut lengths = [0u8; 19];
+    for &idx in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[idx] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (len, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or(Error("repeat with no previous length"))?;
+                (previous, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+
+        if lengths.len() + repeat as usize > literals + distances {
+            return Err(Error("too many code lengths"));
+        }
+        lengths.extend((0..repeat).map(|_| len));
+    }
+
+    if lengths[256] == 0 {
+        return Err(Error("no end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, lengths: &Huffman, distances: &Huffman) -> Result<()> {
+    loop {
+        let symbol = usize::from(lengths.decode(bits)?);
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let idx = symbol - 257;
+                if idx >= LENGTH_BASE.len() {
+                    return Err(Error("invalid length code"));
+                }
+                let len = usize::from(LENGTH_BASE[idx])
+                    + bits.bits(u32::from(LENGTH_EXTRA[idx]))? as usize;
+
+                let idx = usize::from(distances.decode(bits)?);
+                if idx >= DISTANCE_BASE.len() {
+                    return Err(Error("invalid distance code"));
+                }
+                let distance = usize::from(DISTANCE_BASE[idx])
+                    + bits.bits(u32::from(DISTANCE_EXTRA[idx]))? as usize;
+
+                if distance > out.len() {
+                    return Err(Error("distance reaches before the start of the data"));
+                }
+                if out.len() + len > MAX_OUTPUT {
+                    return Err(Error("stream is too large"));
+                }
+
+                // The copy may overlap what it's copying, so it goes a byte at a time.
+                let start = out.len() - distance;
+                for idx in start..start + len {
+                    out.push(out[idx]);
+                }
+            }
+        }
+
+        if out.len() > MAX_OUTPUT {
+            return Err(Error("stream is too large"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello hello hello" as a single fixed Huffman block.
+    static FIXED: &[u8] = &[203, 72, 205, 201, 201, 87, 200, 64, 144, 0];
+
+    static DYNAMIC_TEXT: &[u8] = b"to be or not to be, that is the question: whether tis nobler";
+
+    /// `DYNAMIC_TEXT` as a single dynamic Huffman block.
+    static DYNAMIC: &[u8] = &[
+        21, 137, 193, 9, 192, 48, 16, 195, 86, 241, 0, 157, 160, 219, 36, 96, 72, 32, 156, 233,
+        197, 161, 235, 247, 250, 18, 146, 44, 116, 66, 137, 144, 225, 95, 46, 120, 52, 99, 238, 34,
+        241, 28, 110, 79, 197, 141, 119, 176, 66, 194, 117, 66, 125, 49, 63,
+    ];
+
+    #[test]
+    fn stored_block() {
+        let data = b"\x01\x06\x00\xf9\xffstored";
+        assert_eq!(inflate(data).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(inflate(FIXED).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(inflate(DYNAMIC).unwrap(), DYNAMIC_TEXT);
+    }
+
+    #[test]
+    fn several_blocks() {
+        let mut data = b"\x00\x04\x00\xfb\xffsay ".to_vec();
+        data.extend_from_slice(FIXED);
+        assert_eq!(inflate(&data).unwrap(), b"say hello hello hello");
+    }
+
+    #[test]
+    fn zlib_wrapper() {
+        let mut data = vec![0x78, 0x9c];
+        data.extend_from_slice(FIXED);
+        data.extend_from_slice(&[0x3a, 0x2e, 0x06, 0x7d]);
+        assert_eq!(zlib(&data).unwrap(), b"hello hello hello");
+
+        assert!(zlib(FIXED).is_err());
+        assert!(zlib(&[0x78, 0xbb, 0, 0]).is_err(), "preset dictionary");
+        assert!(zlib(&[]).is_err());
+    }
+
+    #[test]
+    fn truncated_streams() {
+        for data in [&b"\x01\x06\x00\xf9\xffstored"[..], FIXED, DYNAMIC] {
+            for len in 0..data.len() {
+                assert!(inflate(&data[..len]).is_err(), "{len} of {}", data.len());
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_streams() {
+        assert!(inflate(&[0x07]).is_err(), "reserved block type");
+        assert!(
+            inflate(b"\x01\x06\x00\xf9\xfestored").is_err(),
+            "bad complement"
+        );
+
+        // A fixed block whose first symbol is a match, with nothing before it to copy.
+        assert!(inflate(&[0x03, 0x02]).is_err(), "distance before start");
+
+        // Whatever a damaged stream decodes to, decoding it mustn't panic.
+        for data in [FIXED, DYNAMIC] {
+            for idx in 0..data.len() {
+                for bit in 0..8 {
+                    let mut data = data.to_vec();
+                    data[idx] ^= 1 << bit;
+                    let _ = inflate(&data);
+                }
+            }
+        }
+    }
+}
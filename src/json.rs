@@ -0,0 +1,113 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use tantivy::Score;
+
+/// The version of the json output format.
+///
+/// Any change to the meaning of an existing field, or the removal of one, bumps the version.
+/// Adding a field does not.
+pub const VERSION: u32 = 1;
+
+/// Search results as written by `--format json`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Envelope<'a> {
+    pub version: u32,
+    pub query: &'a str,
+    pub total: usize,
+    pub results: Vec<JsonResult<'a>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct JsonResult<'a> {
+    pub path: &'a str,
+    pub score: Score,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<&'a str>,
+}
+
+impl<'a> Envelope<'a> {
+    /// Builds the envelope for a page of hits, given the title of each hit where it has one.
+    pub fn new(
+        query: &'a str,
+        total: usize,
+        hits: &[(Score, &'a str)],
+        titles: &'a [Option<String>],
+    ) -> Self {
+        Self {
+            version: VERSION,
+            query,
+            total,
+            results: hits
+                .iter()
+                .zip(titles)
+                .map(|(&(score, path), title)| JsonResult {
+                    path,
+                    score,
+                    title: title.as_deref(),
+                    library: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Names the library each result came from, for searches of more than one.
+    pub fn with_libraries(mut self, libraries: &[&'a str]) -> Self {
+        for (result, &library) in self.results.iter_mut().zip(libraries) {
+            result.library = Some(library);
+        }
+        self
+    }
+}
+
+/// Describes the json output format as a JSON Schema document.
+pub fn schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "search results",
+        "type": "object",
+        "required": ["version", "query", "total", "results"],
+        "properties": {
+            "version": {
+                "description": "output format version",
+                "const": VERSION
+            },
+            "query": {
+                "description": "the query as given on the command line",
+                "type": "string"
+            },
+            "total": {
+                "description": "the number of documents matching the query, including those not shown",
+                "type": "integer",
+                "minimum": 0
+            },
+            "results": {
+                "description": "the results shown, best first",
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["path", "score"],
+                    "properties": {
+                        "path": {
+                            "description": "the path of the document",
+                            "type": "string"
+                        },
+                        "score": {
+                            "description": "the document's score, normalized if --normalize-scores was given",
+                            "type": "number"
+                        },
+                        "title": {
+                            "description": "the document's title, absent for libraries built without titles",
+                            "type": "string"
+                        },
+                        "library": {
+                            "description": "the library the document is in, present only when searching more than one",
+                            "type": "string"
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,6374 @@
+mod access;
+mod cache;
+mod comments;
+mod completions;
+mod config;
+mod daemon;
+mod docx;
+mod encoding;
+mod engine;
+mod epub;
+mod finder;
+mod gitignore;
+mod inflate;
+mod json;
+mod markdown;
+mod mime;
+mod opener;
+mod pack;
+mod pdf;
+mod preview;
+mod profile;
+mod progress;
+mod querylog;
+mod refine;
+mod snippet;
+mod spec;
+#[cfg(test)]
+mod tests;
+mod tokenizer;
+mod zip;
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env, fmt,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, BufRead, IsTerminal, Write},
+    num::NonZeroUsize,
+    ops::{Deref, Range},
+    path::{self, Component, Path, PathBuf},
+    process,
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use directories::ProjectDirs;
+use rayon::prelude::*;
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use tantivy::{
+    collector::{Collector, Count, DocSetCollector, FilterCollector, TopDocs},
+    directory::{MmapDirectory, OwnedBytes},
+    doc,
+    fastfield::{write_alive_bitset, AliveBitSet},
+    index::SegmentComponent,
+    indexer::{
+        merge_filtered_segments, IndexWriterOptions, LogMergePolicy, MergePolicy, NoMergePolicy,
+    },
+    query::{
+        AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, FuzzyTermQuery, Occur, Query,
+        QueryParser, RegexQuery, TermQuery, TermSetQuery,
+    },
+    schema::{
+        self, Field, FieldType, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value,
+    },
+    tokenizer::TokenStream,
+    DocAddress, DocId, DocSet, Index, IndexWriter, Score, Searcher, SegmentReader, TantivyDocument,
+    Term, TERMINATED,
+};
+use tantivy_common::BitSet;
+
+pub use crate::engine::{IndexOptions, SearchEngine, SearchResult};
+
+use crate::{
+    access::AccessLog,
+    cache::ResultCache,
+    config::Config,
+    daemon::Address,
+    gitignore::Ignores,
+    opener::{OpenOptions, OpenProfile, Opener},
+    preview::PreviewCommand,
+    profile::Profiles,
+    progress::Progress,
+    querylog::QueryLog,
+    refine::ResultSet,
+    snippet::Snippets,
+};
+
+// The command line of the search binary, public so that main.rs can parse it.
+#[derive(Clone, Debug, Parser)]
+#[clap(version, subcommand_negates_reqs(true))]
+pub struct Args {
+    #[clap(required_unless_present_any = [
+        "by_id", "json_schema", "list", "sort", "query_spec", "interactive"
+    ])]
+    /// the query
+    ///
+    /// Words match the text, title and directory names of each document. A word may be limited
+    /// to one field by prefixing it with the field's name, as in `title:rust AND text:async`.
+    /// The searchable fields are `text`, `title`, `path_terms` (the directories between the
+    /// library root and the file), `tags` and `mime` (each matched exactly, as in
+    /// `mime:application/pdf`), `path` (the file's full path, matched exactly) and, in stemmed
+    /// libraries, `exact` (the text without stemming). A word ending in `*`, as in `config*`,
+    /// matches every word starting with it; give at least three characters before the `*`.
+    query: Vec<String>,
+
+    #[clap(short, long)]
+    open: bool,
+
+    /// skip results that are already open
+    ///
+    /// When opening results, skip files that have already been opened during this session or
+    /// that another process appears to have open. Detecting files open in other applications is
+    /// only supported on Linux, and only catches applications that keep their files open (many
+    /// editors do not).
+    #[clap(long, requires = "open")]
+    open_new_only: bool,
+
+    /// wait for each opened application to exit
+    ///
+    /// When opening results, wait until the application showing each file has exited before
+    /// opening the next. This works on macOS and, for applications that don't hand files off to
+    /// an instance that is already running, on Windows. Elsewhere it depends on the desktop
+    /// environment's opener, which usually only waits for terminal applications.
+    #[clap(long, requires = "open")]
+    open_wait: bool,
+
+    /// open html results at the best match
+    ///
+    /// Open html results in the browser scrolled to their best match, using a text fragment
+    /// (`#:~:text=`) located by way of the source offsets recorded at index time. Offsets are only
+    /// recorded for runs of text that appear verbatim in the source, so text containing entities
+    /// can't be targeted, and the offsets go stale if a file changes after it was indexed. Other
+    /// results, and browsers without text fragment support, fall back to opening the file.
+    #[clap(long, requires = "open")]
+    open_highlight: bool,
+
+    /// milliseconds to wait between opening results
+    ///
+    /// When opening several results, pause this long before each after the first, so that
+    /// applications have time to come up one at a time. 0 opens them all at once.
+    #[clap(long, value_name = "MS", default_value_t = 500, requires = "open")]
+    open_delay: u64,
+
+    /// open results with the applications in open.toml
+    ///
+    /// When opening results, open each file with the application mapped to its extension in
+    /// open.toml in the config directory (e.g. `pdf = "zathura"`). Files with other extensions
+    /// open with their default application. Applications that don't detach from the terminal
+    /// are waited on before the next result opens.
+    #[clap(long, requires = "open")]
+    open_with_profile: bool,
+
+    /// open results with this command
+    ///
+    /// When opening results, run the command with each file's path as its last argument instead
+    /// of opening the file with its default application (e.g. `--open-with "code --new-window"`).
+    /// The command is split at whitespace, without any shell quoting. Applications that run in
+    /// the terminal, like vim, need --open-wait so that they take turns.
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        value_parser = parse_command,
+        requires = "open",
+        conflicts_with_all = ["open_with_profile", "open_background"]
+    )]
+    open_with: Option<String>,
+
+    /// reuse application windows
+    ///
+    /// When opening results with --open-with-profile, pass each application the arguments given
+    /// as `reuse` in open.toml (e.g. `txt = { app = "code", reuse = ["--reuse-window"] }`) so
+    /// that results collect in a window the application already has open instead of each
+    /// spawning a new one.
+    #[clap(long, requires = "open_with_profile", conflicts_with = "open_wait")]
+    open_reuse_window: bool,
+
+    /// print open commands without running them
+    ///
+    /// When opening results, print the command that would open each one, including any
+    /// application from --open-with-profile, instead of running it. Results are chosen exactly as
+    /// they would be when opening them for real.
+    #[clap(long, requires = "open")]
+    dry_open: bool,
+
+    /// open results in the background
+    ///
+    /// When opening results, leave each application in the background rather than letting it
+    /// take focus. This works on macOS and, by starting applications minimized, on Windows.
+    /// Elsewhere results open as usual, with a warning.
+    #[clap(long, requires = "open", conflicts_with = "open_wait")]
+    open_background: bool,
+
+    /// find a document by id
+    ///
+    /// Look up the document carrying the given stable id instead of running a query. Documents
+    /// get an id from an `id:` line in their frontmatter or from an `<file>.id` sidecar file.
+    #[clap(long, conflicts_with_all = ["query", "one_per_term"])]
+    by_id: Option<String>,
+
+    /// structured query
+    ///
+    /// Search with a query combining matches on several fields, written as clauses separated by
+    /// spaces, e.g. `+text:"exact phrase" filename~notes -tags:draft`. `field:value` matches a
+    /// word or quoted phrase exactly; `field~word` matches the word allowing one typo. Any text
+    /// field may be named, as may `filename`, which matches a file's name exactly with `:` or
+    /// as a case-insensitive substring with `~`. Clauses marked `+` must match and those marked
+    /// `-` must not; documents must match at least one of the rest.
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["query", "by_id", "list", "one_per_term"]
+    )]
+    query_spec: Option<String>,
+
+    /// search threads
+    ///
+    /// Search the segments of the index in parallel on a pool of this many threads. (Defaults to
+    /// searching on the current thread.) Each segment is searched by a single thread, so the
+    /// speedup is bounded by the number of segments in the index: threads beyond the segment
+    /// count only cost CPU. Large indexes with many segments benefit most.
+    #[clap(long, value_parser = parse_nonzero)]
+    search_threads: Option<usize>,
+
+    /// ranking profile
+    ///
+    /// Apply a named ranking profile from profiles.toml in the config directory. A profile may
+    /// set the fields searched by default (`fields = ["text"]`) and per-field boosts
+    /// (`boosts = { text = 2.0 }`).
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// field boost
+    ///
+    /// Multiply scores for matches in a field, written as `field=boost` (e.g. `text=2.0`). May be
+    /// passed more than once. Overrides any boost for the same field set by --profile.
+    #[clap(long, value_parser = parse_boost)]
+    boost: Vec<(String, f32)>,
+
+    /// rank exact forms first
+    ///
+    /// In a library created with --stem, boost documents containing the words of the query
+    /// exactly as typed over those matching only their stems, so that a search for "running"
+    /// ranks "running" above "run". Has no effect on libraries without stemming, where every
+    /// match is exact.
+    #[clap(long, conflicts_with_all = ["by_id", "query_spec", "list"])]
+    boost_exact: bool,
+
+    /// expand wildcards
+    ///
+    /// Treat `*` in a word of the query as standing for any run of characters, so that
+    /// `config*` finds "config" and "configuration" alike. Words with wildcards are matched
+    /// against the words of each document's text; a `+` or `-` in front requires or excludes
+    /// them as usual. Short prefixes match a great many words and can make searches slow.
+    #[clap(
+        long,
+        conflicts_with_all = ["by_id", "query_spec", "list", "one_per_term", "boost_exact"]
+    )]
+    wildcard: bool,
+
+    /// tolerate typos
+    ///
+    /// Match each word of the query against words within a small number of edits of it, as well
+    /// as the word itself, so that "hemmingway" finds "hemingway". Words matched exactly still
+    /// rank above those matched only approximately. A `+` or `-` in front requires or excludes a
+    /// word as usual, though exclusions are exact.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "by_id", "query_spec", "list", "one_per_term", "boost_exact", "wildcard"
+        ]
+    )]
+    fuzzy: bool,
+
+    /// edits allowed by --fuzzy
+    ///
+    /// The number of single-character insertions, deletions, substitutions or transpositions a
+    /// word may differ by and still match.
+    #[clap(
+        long,
+        requires = "fuzzy",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u8).range(1..=2)
+    )]
+    fuzzy_distance: u8,
+
+    /// show scores
+    ///
+    /// Print each result's relevance score, to two decimal places, before its path, separated by
+    /// a tab so that results can be piped into `sort`. Ignored when opening results.
+    #[clap(long)]
+    scores: bool,
+
+    /// show normalized scores
+    ///
+    /// Print each result's score, divided by the score of the best result shown, before its
+    /// path. Normalized scores fall between 0 and 1 and are easier to read than raw scores, but
+    /// they are relative to a single query's results and can't be compared across queries.
+    #[clap(long)]
+    normalize_scores: bool,
+
+    /// list the whole index
+    ///
+    /// List every document in the index instead of searching, most recently modified first unless
+    /// --sort says otherwise. The query may also be left out when sorting by modification time or
+    /// size, which lists documents the same way.
+    #[clap(long, conflicts_with_all = ["query", "by_id", "one_per_term"])]
+    list: bool,
+
+    /// print only the number of matching documents
+    ///
+    /// Count the documents matching the query without collecting or ranking them, and print the
+    /// count alone. --skip, --take and --open are ignored.
+    #[clap(long, conflicts_with_all = ["one_per_term", "interactive"])]
+    count: bool,
+
+    /// result order
+    ///
+    /// Order results by relevance (the default), by modification time ("date" for short) or size,
+    /// largest first, or alphabetically by path. Results with the same modification time or size
+    /// are ordered by relevance. Listings are ordered by modification time unless --recency is
+    /// given. Ordering by path means loading every match, so it's slower for broad queries.
+    #[clap(long, value_enum, default_value_t = Sort::Relevance)]
+    sort: Sort,
+
+    /// favor recently opened documents
+    ///
+    /// With `access`, boost the score of each document according to how often and how recently
+    /// it has been opened with --open or find, so that documents in regular use rise to the top.
+    /// Documents that have never been opened keep their usual score. Combined with --list, lists
+    /// documents in order of use. The record of what's been opened is kept per library and can be
+    /// trimmed with prune-access.
+    #[clap(long, value_enum, conflicts_with = "one_per_term")]
+    recency: Option<Recency>,
+
+    /// match any term
+    ///
+    /// Find documents containing any of the terms of the query, overriding a library created with
+    /// --default-and. This is the usual behavior.
+    #[clap(long, conflicts_with = "all")]
+    any: bool,
+
+    /// match all terms
+    ///
+    /// Find only documents containing every term of the query, as though each were marked with
+    /// `+`. Libraries created with --default-and do this by default.
+    #[clap(long)]
+    all: bool,
+
+    /// minimum size
+    ///
+    /// Show only documents larger than the given number of bytes.
+    #[clap(long)]
+    larger_than: Option<u64>,
+
+    /// maximum size
+    ///
+    /// Show only documents smaller than the given number of bytes.
+    #[clap(long)]
+    smaller_than: Option<u64>,
+
+    /// output format [default: plain, or as set in config.toml]
+    #[clap(long, value_enum)]
+    format: Option<Format>,
+
+    /// when to color plain output
+    ///
+    /// Paths and the matches in snippets are colored when printing to a terminal, unless the
+    /// NO_COLOR environment variable is set. Other formats are never colored.
+    #[clap(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: Color,
+
+    /// csv columns
+    ///
+    /// The columns written by --format csv, in order, separated by commas (e.g.
+    /// `path,modified`). Modification times are written in UTC.
+    #[clap(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "path,score,modified,size"
+    )]
+    fields: Vec<Column>,
+
+    /// print the json output schema
+    ///
+    /// Print a JSON Schema document describing the output of `--format json` and exit. The json
+    /// output carries a version number, which changes whenever the meaning of the output does.
+    #[clap(long, conflicts_with_all = ["query", "by_id"])]
+    json_schema: bool,
+
+    /// show the best match for each term
+    ///
+    /// Rather than ranking documents against the query as a whole, search for each term on its
+    /// own and show only the top-scoring document for that term. Documents that are the best
+    /// match for more than one term are shown once.
+    #[clap(long, conflicts_with_all = ["sort", "larger_than", "smaller_than"])]
+    one_per_term: bool,
+
+    /// owner uid
+    ///
+    /// Show only documents owned by the given user id.
+    #[cfg(unix)]
+    #[clap(long)]
+    owner: Option<u32>,
+
+    /// permission bits
+    ///
+    /// Show only documents with exactly the given permissions, written in octal (e.g. 644).
+    #[cfg(unix)]
+    #[clap(long, value_parser = parse_mode)]
+    mode: Option<u32>,
+
+    /// content type
+    ///
+    /// Show only documents whose contents were detected as the given type (e.g. `text/csv`). May
+    /// be passed more than once. Types are detected from file contents at index time; the types
+    /// recognized are text/plain, text/html, text/csv, text/x-log, application/json and
+    /// application/xml, plus a few binary formats.
+    #[clap(long)]
+    mime: Vec<String>,
+
+    /// include documents of unknown type
+    ///
+    /// With --mime, also show documents whose type couldn't be detected.
+    #[clap(long, requires = "mime")]
+    mime_include_unknown: bool,
+
+    /// tag
+    ///
+    /// Show only documents with the given tag. May be passed more than once, in which case
+    /// documents must have every tag given. Documents get tags from a `tags:` list in their
+    /// frontmatter or from a `<file>.tags` sidecar file listing one tag per line.
+    #[clap(long)]
+    tag: Vec<String>,
+
+    /// exclude paths
+    ///
+    /// Drop results whose path matches the given regular expression (e.g. `archive/|draft_`). May
+    /// be passed more than once, in which case results matching any of the expressions are
+    /// dropped. Excluded documents are left out of pages and counts alike.
+    #[clap(long, value_parser = parse_regex)]
+    exclude_path: Vec<Regex>,
+
+    /// only search beneath a directory
+    ///
+    /// Show only documents at or beneath the given path, such as one subproject of a library.
+    /// Relative paths are taken from the current directory. Pages and counts take in only the
+    /// documents beneath the path.
+    #[clap(long, value_name = "PATH")]
+    under: Option<PathBuf>,
+
+    /// cache results
+    ///
+    /// Answer the search from results saved by an earlier identical search, if there are any,
+    /// and save the results of this one. Saved results are discarded whenever the index is
+    /// written (by update, sync or merge) and once they're older than --cache-ttl, but until then
+    /// they won't reflect changes to the files themselves. Ignored with --open-highlight and
+    /// --recency.
+    #[clap(long, overrides_with = "no_cache")]
+    cache: bool,
+
+    /// don't cache results (the default)
+    #[clap(long, overrides_with = "cache")]
+    no_cache: bool,
+
+    /// seconds cached results stay valid
+    #[clap(long, default_value_t = 300)]
+    cache_ttl: u64,
+
+    /// index name
+    ///
+    /// Search a named library instead of guessing the library name based on the current working
+    /// directory.
+    #[clap(short, long)]
+    index: Option<String>,
+
+    /// library group
+    ///
+    /// Search every library in a group (see the group command) at once, merging their results.
+    /// Scores are worked out separately for each library, so they compare best between libraries
+    /// of similar size and content. Only plain, json and csv output are supported.
+    #[clap(
+        long,
+        conflicts_with_all = ["index", "one_per_term", "recency", "open_highlight", "snippet"]
+    )]
+    group: Option<String>,
+
+    /// search every library
+    ///
+    /// Search every registered library at once, merging their results as with --group and
+    /// printing each result's library before it. Evicted libraries are skipped rather than
+    /// rebuilt. (--all is taken: it requires every term of the query.)
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "index", "group", "one_per_term", "recency", "open_highlight", "snippet"
+        ]
+    )]
+    all_libraries: bool,
+
+    /// search interactively
+    ///
+    /// Open the library once and read queries from standard input, one per line, printing the
+    /// paths found for each, as the repl command does. Exit with Ctrl-D. (-i is taken: it names
+    /// the library.)
+    #[clap(
+        long,
+        conflicts_with_all = ["query", "group", "all_libraries", "open", "list"]
+    )]
+    interactive: bool,
+
+    /// describe the search
+    ///
+    /// Before searching, print the library searched (and how it was chosen), where its index is
+    /// stored and the query as parsed to standard error.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// record this query in the query log
+    ///
+    /// Override whether this search is recorded in the query log, which is otherwise kept only
+    /// once enabled with `query-log --enable`.
+    #[clap(long, value_enum)]
+    query_log: Option<Switch>,
+
+    #[clap(flatten)]
+    skip_take: SkipTake,
+
+    #[clap(flatten)]
+    snippet: SnippetOptions,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    fn query_string(&self) -> String {
+        if let Some(spec) = &self.query_spec {
+            return spec.clone();
+        }
+
+        if self.query.is_empty() {
+            return String::new();
+        }
+
+        let mut buf = String::from(&self.query[0]);
+        for part in &self.query[1..] {
+            buf += " ";
+            buf += part;
+        }
+
+        buf
+    }
+
+    /// Returns true if the whole index should be listed rather than searched.
+    fn is_listing(&self) -> bool {
+        self.query.is_empty() && self.by_id.is_none() && self.query_spec.is_none()
+    }
+
+    /// Returns the order of results, which for a listing is never by relevance since every
+    /// document matches equally.
+    fn sort(&self) -> Sort {
+        match self.sort {
+            Sort::Relevance if self.is_listing() && self.recency.is_none() => Sort::Modified,
+            sort => sort,
+        }
+    }
+
+    /// Whether scores are printed with results, as they are when they're normalized.
+    fn show_scores(&self) -> bool {
+        self.scores || self.normalize_scores
+    }
+
+    /// Fills in the options left off the command line from config.toml.
+    fn with_config(&self, config: &Config) -> Args {
+        let mut args = self.clone();
+        args.skip_take.take = args.skip_take.take.or(config.take.map(NonZeroUsize::get));
+        args.skip_take.skip = args.skip_take.skip.or(config.skip);
+        args.format = args.format.or(config.format);
+        args
+    }
+
+    /// Whether plain output is colored. An explicit --color wins over NO_COLOR.
+    fn color(&self) -> bool {
+        match self.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => {
+                io::stdout().is_terminal()
+                    && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+
+    fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Plain)
+    }
+
+    fn skip_take(&self) -> (Skip, Take) {
+        let take = self.skip_take.take.unwrap_or(DEFAULT_TAKE);
+        let skip = match self.skip_take.page {
+            Some(page) => take * page,
+            None => self.skip_take.skip.unwrap_or_default(),
+        };
+
+        (skip.into(), take.into())
+    }
+
+    fn open_options(&self) -> io::Result<OpenOptions> {
+        let profile = if self.open_with_profile {
+            OpenProfile::from_path(&get_config_path()?)?
+        } else {
+            Default::default()
+        };
+
+        Ok(OpenOptions {
+            new_only: self.open_new_only,
+            wait: self.open_wait,
+            profile,
+            command: self.open_with.clone(),
+            delay: Duration::from_millis(self.open_delay),
+            reuse_window: self.open_reuse_window,
+            dry_run: self.dry_open,
+            background: self.open_background,
+        })
+    }
+
+    /// Identifies the results of a search for the result cache.
+    ///
+    /// The key is built from every option except those that only affect how results are shown,
+    /// so that an option added later is assumed to affect the results until it's listed here.
+    fn cache_key(&self) -> String {
+        let mut key = self.clone();
+        key.open = false;
+        key.open_new_only = false;
+        key.open_wait = false;
+        key.open_highlight = false;
+        key.open_delay = 0;
+        key.open_with_profile = false;
+        key.open_with = None;
+        key.open_reuse_window = false;
+        key.dry_open = false;
+        key.open_background = false;
+        key.scores = false;
+        key.normalize_scores = false;
+        key.format = None;
+        key.color = Color::Auto;
+        key.fields = Vec::new();
+        key.search_threads = None;
+        key.cache = false;
+        key.no_cache = false;
+        key.cache_ttl = 0;
+        key.verbose = false;
+        key.query_log = None;
+
+        // A relative --under means something different from each directory.
+        if let (Some(under), Ok(current)) = (&mut key.under, env::current_dir()) {
+            *under = current.join(&*under);
+        }
+        format!("{key:?}")
+    }
+
+    /// Returns the forms the path given to --under might take in the index. Documents are stored
+    /// under their path as found beneath the library root as it was given when the library was
+    /// indexed, which may have been relative, or not canonical.
+    fn under_prefixes(&self) -> Vec<String> {
+        let Some(under) = &self.under else {
+            return Vec::new();
+        };
+
+        let mut prefixes = vec![under.clone()];
+        if let Ok(current) = env::current_dir() {
+            prefixes.push(current.join(under).components().collect());
+        }
+        if let Ok(canonical) = fs::canonicalize(under) {
+            prefixes.push(canonical);
+        }
+
+        let mut prefixes: Vec<_> = prefixes
+            .iter()
+            .map(|prefix| {
+                let prefix = prefix.display().to_string();
+                prefix.trim_end_matches(path::is_separator).to_owned()
+            })
+            .collect();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+        prefixes
+    }
+
+    /// Returns the range of sizes allowed by --larger-than and --smaller-than, if either was
+    /// given.
+    fn size_range(&self) -> Option<(u64, u64)> {
+        if self.larger_than.is_none() && self.smaller_than.is_none() {
+            return None;
+        }
+
+        Some((
+            self.larger_than.unwrap_or(0),
+            self.smaller_than.unwrap_or(u64::MAX),
+        ))
+    }
+
+    /// Whether --owner or --mode was given. Owners and modes are stored but not indexed, so they
+    /// can't narrow the query itself, and have to be checked against each document found.
+    #[cfg(unix)]
+    fn filters_metadata(&self) -> bool {
+        self.owner.is_some() || self.mode.is_some()
+    }
+
+    #[cfg(not(unix))]
+    fn filters_metadata(&self) -> bool {
+        false
+    }
+
+    /// Returns true if a document has the owner and mode given on the command line.
+    #[cfg(unix)]
+    fn matches_metadata(&self, doc: &TantivyDocument, fields: &SearchFields) -> bool {
+        let matches = |field, expected: Option<u32>| match expected {
+            Some(expected) => {
+                doc.get_first(field).and_then(|x| x.as_u64()) == Some(expected.into())
+            }
+            None => true,
+        };
+
+        matches(fields.owner, self.owner) && matches(fields.mode, self.mode)
+    }
+
+    #[cfg(not(unix))]
+    fn matches_metadata(&self, _doc: &TantivyDocument, _fields: &SearchFields) -> bool {
+        true
+    }
+}
+
+struct Skip(usize);
+
+impl From<usize> for Skip {
+    fn from(n: usize) -> Self {
+        Self(n)
+    }
+}
+
+impl Deref for Skip {
+    type Target = usize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+struct Take(usize);
+
+impl From<usize> for Take {
+    fn from(n: usize) -> Self {
+        Self(n)
+    }
+}
+
+impl Deref for Take {
+    type Target = usize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Sort {
+    /// best match first
+    Relevance,
+
+    /// most recently modified first
+    #[value(alias = "date")]
+    Modified,
+
+    /// largest first
+    Size,
+
+    /// alphabetically by path
+    Path,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Switch {
+    On,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Recency {
+    /// documents opened often and lately first
+    Access,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    /// one path per line
+    Plain,
+
+    /// an html fragment listing each result with a highlighted snippet
+    Html,
+
+    /// a versioned json document (see --json-schema)
+    Json,
+
+    /// comma-separated values with a header row (see --fields)
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Color {
+    /// color when printing to a terminal and NO_COLOR isn't set
+    Auto,
+
+    /// always color
+    Always,
+
+    /// never color
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Column {
+    Path,
+    Score,
+    Modified,
+    Size,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    /// create a new index
+    #[clap(alias = "ci")]
+    CreateIndex(IndexCmd),
+
+    /// list indexes
+    #[clap(alias = "ls")]
+    ListIndexes,
+
+    /// print the name of the library for the current directory
+    #[clap(alias = "cur")]
+    Current,
+
+    /// delete a library's index and unregister it
+    #[clap(alias = "rm")]
+    RemoveIndex(RemoveCmd),
+
+    /// update index
+    #[clap(alias = "u")]
+    Update(UpdateArgs),
+
+    /// merge one library into another
+    Merge(MergeCmd),
+
+    /// print the schema of an index
+    DumpSchema(DumpSchemaCmd),
+
+    /// report the size of a library's index
+    Stats(StatsCmd),
+
+    /// point a library at a new root without reindexing
+    Relocate(RelocateCmd),
+
+    /// give a library a new name
+    Rename(RenameCmd),
+
+    /// check the library registry for problems
+    Doctor(DoctorCmd),
+
+    /// check a library's index for damage, rebuilding it if asked
+    Verify(VerifyCmd),
+
+    /// bring an index in line with the files on disk by comparing content hashes
+    Sync(SyncCmd),
+
+    /// search interactively, opening the result picked
+    Find(FindCmd),
+
+    /// forget documents in a library's access log (see --recency)
+    PruneAccess(PruneAccessCmd),
+
+    /// limit the space taken by indexes, evicting those searched least recently
+    Evict(EvictCmd),
+
+    /// print or manage the log of queries searched
+    QueryLog(QueryLogCmd),
+
+    /// keep indexes loaded and answer searches sent by `ask`
+    Daemon(DaemonCmd),
+
+    /// search by way of a running daemon
+    Ask(AskCmd),
+
+    /// search interactively, one query per line, narrowing results with `:refine`
+    Repl(ReplCmd),
+
+    /// show or change the groups a library belongs to (see --group)
+    Group(GroupCmd),
+
+    /// bundle a library's index into a single file, to be installed elsewhere with unpack
+    Pack(PackCmd),
+
+    /// install and register a library from a file written by pack
+    Unpack(UnpackCmd),
+
+    /// print a script that sets up tab completion for a shell
+    #[clap(hide = true)]
+    Completions(CompletionsCmd),
+}
+
+trait IndexArgs {
+    fn name(&self) -> &str;
+    fn roots(&self) -> io::Result<Vec<PathBuf>>;
+    fn force(&self) -> bool;
+    fn require_docs(&self) -> bool;
+    fn strict(&self) -> bool;
+    fn quiet(&self) -> bool;
+    fn writer_options(&self) -> &WriterOptions;
+    fn config(&self) -> IndexConfig;
+}
+
+// FIXME: change IndexCmd to eliminate the error case so that we can simplify the trait
+
+#[derive(Clone, Debug, Parser)]
+struct IndexCmd {
+    /// library name
+    ///
+    /// Each search library needs a name so that we have a place to store the index.
+    name: String,
+
+    /// library roots
+    ///
+    /// The location of the files to be indexed. More than one may be given, in which case the
+    /// files beneath each are indexed together as one library, which is found from within any of
+    /// them. (Defaults to current directory.)
+    roots: Vec<String>,
+
+    /// overwrite existing index
+    ///
+    /// If search finds an existing index in the intended library location, the indexing process
+    /// will be aborted. Pass this flag to force reindexing.
+    #[clap(short, long)]
+    force: bool,
+
+    /// maximum depth
+    ///
+    /// Index only files at most this many levels below the library root, where files directly
+    /// inside the root are at depth 1. (Defaults to unlimited.) The depth is remembered, so later
+    /// updates apply the same limit.
+    #[clap(long)]
+    max_depth: Option<usize>,
+
+    /// index directory names
+    ///
+    /// Make the name of each directory between the library root and a document a search term
+    /// for that document, so that a query for "networking" finds documents under a networking
+    /// directory even if they never use the word. Matches on directory names are boosted. The
+    /// setting is remembered, so later updates do the same.
+    #[clap(long)]
+    index_dir_terms: bool,
+
+    /// fail if there is nothing to index
+    ///
+    /// Refuse to create the library if no documents would be indexed, as happens when the root
+    /// is wrong or contains no files of a supported type. Nothing is changed in that case: an
+    /// existing index is left in place and the library isn't registered.
+    #[clap(long)]
+    require_docs: bool,
+
+    /// stop at the first file that can't be read
+    ///
+    /// Fail if any file can't be read, rather than skipping it with a warning. Files in a format
+    /// we understand whose contents can't be made sense of, such as a corrupt pdf, and files
+    /// over the --max-size limit are still skipped.
+    #[clap(long)]
+    strict: bool,
+
+    /// don't report progress
+    ///
+    /// Leave out the progress bar, or on anything but a terminal the line printed every 20,000
+    /// files, along with the count of files indexed and skipped at the end.
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// require all terms by default
+    ///
+    /// Make searches of this library find only documents containing every term of the query,
+    /// rather than any of them, unless --any is given. The setting is remembered.
+    #[clap(long)]
+    default_and: bool,
+
+    /// index only the comments of source files
+    ///
+    /// Index source code (C, C++, Rust, Go, Java, JavaScript, Python and the like, including
+    /// languages whose files aren't indexed by default), keeping only the text of its comments
+    /// and docstrings so that searches find explanations rather than code. Other files are
+    /// indexed in full as usual. The setting is remembered.
+    #[clap(long)]
+    comments_only: bool,
+
+    /// stem english words
+    ///
+    /// Index words by their stems, so that a search for "running" also finds "run" and "runs".
+    /// The unstemmed text is indexed too, so that searches can prefer exact forms with
+    /// --boost-exact. The setting is remembered.
+    #[clap(long)]
+    stem: bool,
+
+    /// file extension to index
+    ///
+    /// Index files with this extension, matched regardless of case. May be passed more than once,
+    /// and replaces the default set (html, htm, txt, pdf, epub, docx, and the source files rs, py,
+    /// js, ts, go, c and h, unless config.toml sets its own). Markdown (md and markdown) is indexed
+    /// as the text it renders to, and files other than html, pdf, epub and docx as plain text.
+    /// The extensions are remembered, so later updates index the same.
+    #[clap(long = "ext", value_name = "EXT", value_parser = parse_extension)]
+    extensions: Vec<String>,
+
+    /// split long documents into chunks of this many words
+    ///
+    /// Index each document longer than this as a series of chunks, each a separate document with
+    /// the same path, so that a match is scored against the part of the document it's in rather
+    /// than the whole. Searches show each document once, at its best chunk, whose text is used
+    /// for snippets. The index grows with the overlap between chunks and with the stored fields
+    /// repeated for every chunk, and counting a search's matches means reading the path of each.
+    /// The setting is remembered.
+    #[clap(long, value_name = "WORDS", value_parser = parse_nonzero)]
+    chunk: Option<usize>,
+
+    /// index hidden files and directories
+    ///
+    /// Also index files and directories whose names start with a dot, which are otherwise
+    /// skipped along with anything ignored by a .gitignore within the library root. Files
+    /// ignored by a .gitignore are skipped either way. The setting is remembered.
+    #[clap(long)]
+    include_hidden: bool,
+
+    /// words shared by consecutive chunks
+    ///
+    /// Repeat this many words from the end of each chunk at the start of the next, so that a
+    /// phrase spanning the boundary between chunks can still be matched. Must be less than the
+    /// chunk size. (Defaults to 0.)
+    #[clap(long, value_name = "WORDS", requires = "chunk", default_value_t = 0)]
+    chunk_overlap: usize,
+
+    /// largest file indexed
+    ///
+    /// Skip files larger than this, with a warning, rather than reading them into memory: a
+    /// size in bytes, or with a unit such as 10MB. (Defaults to 50MB.) The setting is
+    /// remembered.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// index code blocks in markdown
+    ///
+    /// Index the contents of fenced and indented code blocks in markdown files along with the
+    /// prose, which is all that's indexed otherwise. Code spans within the text are always
+    /// indexed. The setting is remembered.
+    #[clap(long)]
+    markdown_code: bool,
+
+    /// index link targets in markdown
+    ///
+    /// Index the addresses that links and images in markdown files point to, not just their
+    /// text. The setting is remembered.
+    #[clap(long)]
+    markdown_links: bool,
+
+    #[clap(flatten)]
+    writer: WriterOptions,
+}
+
+impl IndexArgs for IndexCmd {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn roots(&self) -> io::Result<Vec<PathBuf>> {
+        if self.roots.is_empty() {
+            return Ok(vec![env::current_dir()?]);
+        }
+        Ok(self.roots.iter().map(PathBuf::from).collect())
+    }
+
+    fn force(&self) -> bool {
+        self.force
+    }
+
+    fn strict(&self) -> bool {
+        self.strict
+    }
+
+    fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    fn require_docs(&self) -> bool {
+        self.require_docs
+    }
+
+    fn writer_options(&self) -> &WriterOptions {
+        &self.writer
+    }
+
+    fn config(&self) -> IndexConfig {
+        IndexConfig {
+            max_depth: self.max_depth,
+            dir_terms: self.index_dir_terms,
+            default_and: self.default_and,
+            comments_only: self.comments_only,
+            stem: self.stem,
+            extensions: (!self.extensions.is_empty()).then(|| self.extensions.clone()),
+            include_hidden: self.include_hidden,
+            max_size: self.max_size,
+            markdown_code: self.markdown_code,
+            markdown_links: self.markdown_links,
+            chunk: self.chunk.map(|size| Chunking {
+                size,
+                overlap: self.chunk_overlap,
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+struct UpdateArgs {
+    #[clap(flatten)]
+    writer: WriterOptions,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct MergeCmd {
+    /// source library
+    ///
+    /// The library whose documents will be merged. The source library itself is left unchanged.
+    source: String,
+
+    /// target library
+    ///
+    /// The library receiving the documents. Where both libraries contain a document with the same
+    /// path, the target's copy is kept. If no library by this name exists, a new one is created.
+    #[clap(long)]
+    into: String,
+
+    /// target library root
+    ///
+    /// The location of the files for a newly created target library. (Required when the target
+    /// library does not already exist.)
+    #[clap(long)]
+    root: Option<String>,
+
+    /// take the root from the library registered to it
+    #[clap(short, long, requires = "root")]
+    force: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct RelocateCmd {
+    /// library name
+    name: String,
+
+    /// new library root
+    ///
+    /// The new location of the library's files. The existing index is kept as-is, so this should
+    /// contain the same files the index was built from.
+    root: String,
+
+    /// take the root from the library registered to it
+    #[clap(short, long)]
+    force: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct RenameCmd {
+    /// current library name
+    old: String,
+
+    /// new library name
+    ///
+    /// The library keeps its root, index, groups and access log. Queries already in the query
+    /// log stay recorded under the old name.
+    new: String,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct SyncCmd {
+    /// library name
+    ///
+    /// The library to synchronize. (Defaults to the library for the current directory.)
+    name: Option<String>,
+
+    /// stop at the first file that can't be read
+    ///
+    /// Fail if any file can't be read, rather than skipping it with a warning. Files in a format
+    /// we understand whose contents can't be made sense of, such as a corrupt pdf, and files
+    /// over the --max-size limit are still skipped.
+    #[clap(long)]
+    strict: bool,
+
+    #[clap(flatten)]
+    writer: WriterOptions,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct FindCmd {
+    /// library name
+    ///
+    /// The library to search. (Defaults to the library for the current directory.)
+    name: Option<String>,
+
+    /// ranking profile
+    ///
+    /// Apply a named ranking profile from profiles.toml in the config directory.
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// results shown
+    #[clap(long, default_value_t = 50, value_parser = parse_nonzero)]
+    limit: usize,
+
+    /// preview with a command
+    ///
+    /// Preview the selected result with the output of a shell command instead of excerpts of
+    /// its text, e.g. `bat --color=never {path}` or `pdftotext {path} - | head -50`. `{path}` is
+    /// replaced by the path of the result; a command without it gets the path as its last
+    /// argument.
+    #[clap(long)]
+    preview_command: Option<String>,
+
+    /// seconds a preview command may run
+    ///
+    /// A preview command still running after this long is killed.
+    #[clap(long, default_value_t = 2, requires = "preview_command")]
+    preview_timeout: u64,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct PruneAccessCmd {
+    /// library name
+    ///
+    /// The library whose access log will be pruned. (Defaults to the library for the current
+    /// directory.)
+    name: Option<String>,
+
+    /// forget documents not opened in this many days
+    ///
+    /// Documents that no longer exist are always forgotten. Pass 0 to forget everything.
+    #[clap(long)]
+    older_than: Option<u64>,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct EvictCmd {
+    /// size limit
+    ///
+    /// The most space the indexes of every library together may take up, e.g. `500M` or `2G`.
+    /// The limit is remembered and enforced whenever an index is built or updated from then on.
+    /// When indexes grow past it, those of the libraries searched least recently are removed
+    /// until they fit. Evicted libraries stay registered and are rebuilt when next searched.
+    #[clap(long, value_parser = parse_size, conflicts_with = "no_limit")]
+    max_total_size: Option<u64>,
+
+    /// remove the size limit
+    #[clap(long)]
+    no_limit: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct QueryLogCmd {
+    /// start recording queries
+    ///
+    /// Record the time, library, result count and query of every search from now on. Nothing
+    /// leaves this machine; the log is kept in the data directory for your own use.
+    #[clap(long, conflicts_with = "disable")]
+    enable: bool,
+
+    /// stop recording queries
+    ///
+    /// The queries already recorded are kept until cleared.
+    #[clap(long)]
+    disable: bool,
+
+    /// delete the queries recorded so far
+    #[clap(long)]
+    clear: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct RemoveCmd {
+    /// library name
+    name: String,
+
+    /// don't ask for confirmation
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct PackCmd {
+    /// library name
+    name: String,
+
+    /// archive to write
+    ///
+    /// (Defaults to the library name with the extension .searchpack, in the current directory.)
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct UnpackCmd {
+    /// archive written by pack
+    archive: PathBuf,
+
+    /// library name
+    ///
+    /// The name to install the library under. (Defaults to the name it was packed under.)
+    #[clap(long)]
+    name: Option<String>,
+
+    /// library root
+    ///
+    /// Where the library's files are on this machine. May be passed more than once for a
+    /// library with several roots. (Defaults to the roots it was packed with.) The index still
+    /// holds paths under the original roots, so run update from the new root to bring it in
+    /// line.
+    #[clap(long)]
+    root: Vec<PathBuf>,
+
+    /// replace a library with the same name, or the library registered to the same root
+    #[clap(short, long)]
+    force: bool,
+}
+
+/// Describes the library in an archive written by pack.
+#[derive(Debug, Deserialize, Serialize)]
+struct PackManifest {
+    name: String,
+    #[serde(alias = "root", deserialize_with = "one_or_many")]
+    roots: Vec<PathBuf>,
+    config: IndexConfig,
+    schema: Schema,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct GroupCmd {
+    /// library name
+    library: String,
+
+    /// add the library to a group
+    ///
+    /// May be passed more than once. Groups exist for as long as any library belongs to them.
+    #[clap(long)]
+    add: Vec<String>,
+
+    /// remove the library from a group
+    ///
+    /// May be passed more than once.
+    #[clap(long)]
+    remove: Vec<String>,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct DaemonCmd {
+    /// libraries to load
+    ///
+    /// The libraries whose indexes are kept open. (Defaults to every registered library.) Each
+    /// index is reloaded shortly after it's updated, so the daemon can keep running while
+    /// libraries change.
+    libraries: Vec<String>,
+
+    /// listen address
+    ///
+    /// A TCP address (e.g. `127.0.0.1:7451`) or a unix socket written as `unix:<path>`. Anyone
+    /// able to connect can search the loaded libraries, so keep TCP addresses local.
+    #[clap(long, default_value = daemon::DEFAULT_ADDRESS)]
+    address: Address,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct AskCmd {
+    #[clap(required = true)]
+    query: Vec<String>,
+
+    /// index name
+    ///
+    /// Search a named library instead of guessing the library name based on the current working
+    /// directory.
+    #[clap(short, long)]
+    index: Option<String>,
+
+    /// results to show
+    #[clap(
+        short,
+        long,
+        visible_alias = "limit",
+        visible_short_alias = 'n',
+        default_value_t = DEFAULT_TAKE,
+        value_parser = parse_nonzero
+    )]
+    take: usize,
+
+    /// print the daemon's json response
+    #[clap(long)]
+    json: bool,
+
+    /// daemon address
+    #[clap(long, default_value = daemon::DEFAULT_ADDRESS)]
+    address: Address,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct ReplCmd {
+    /// library name
+    ///
+    /// The library to search. (Defaults to the library for the current directory.)
+    name: Option<String>,
+
+    /// results to show
+    #[clap(
+        short,
+        long,
+        visible_alias = "limit",
+        visible_short_alias = 'n',
+        default_value_t = DEFAULT_TAKE,
+        value_parser = parse_nonzero
+    )]
+    take: usize,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct DoctorCmd {
+    /// repair problems
+    ///
+    /// Rewrite the registry with every library root in canonical form. Where several roots turn
+    /// out to be the same directory, the most recently built library keeps the directory.
+    /// With --prune-empty-libraries, also remove empty libraries.
+    #[clap(long)]
+    fix: bool,
+
+    /// look for empty libraries
+    ///
+    /// Open each library's index and report those holding no documents at all, as left behind
+    /// by failed or empty builds. With --fix, the index and registry entry of each are removed.
+    /// Evicted libraries are left alone.
+    #[clap(long)]
+    prune_empty_libraries: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct VerifyCmd {
+    /// library name
+    ///
+    /// The library to check. (Defaults to the library for the current directory.)
+    name: Option<String>,
+
+    /// rebuild a damaged index
+    ///
+    /// Reindex the library from its roots, with the settings it was created with, if its index
+    /// can't be opened or read. Tantivy keeps no earlier commits to fall back on, so rebuilding is
+    /// the only repair.
+    #[clap(long)]
+    repair: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct StatsCmd {
+    /// library name
+    ///
+    /// The library to report on. (Defaults to the library for the current directory.)
+    name: Option<String>,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct DumpSchemaCmd {
+    /// library name
+    ///
+    /// The library whose schema will be printed. (Defaults to the library for the current
+    /// directory.)
+    name: Option<String>,
+
+    /// index directory
+    ///
+    /// Read the index stored in this directory instead of a registered library.
+    #[clap(long, conflicts_with = "name")]
+    index_path: Option<PathBuf>,
+
+    /// print the schema as json
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Clone, Debug, Parser)]
+struct CompletionsCmd {
+    /// shell
+    ///
+    /// The shell to write the script for. For bash, for example, add `source <(search
+    /// completions bash)` to ~/.bashrc.
+    #[clap(value_enum, required_unless_present = "complete")]
+    shell: Option<completions::Shell>,
+
+    /// print the completions of a partly typed command line, given after `--`
+    #[clap(long, hide = true)]
+    complete: bool,
+
+    #[clap(last = true, hide = true)]
+    words: Vec<String>,
+}
+
+struct UpdateCmd<'a> {
+    name: &'a str,
+    roots: Vec<PathBuf>,
+    writer: &'a WriterOptions,
+    config: IndexConfig,
+}
+
+impl IndexArgs for UpdateCmd<'_> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn roots(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(self.roots.clone())
+    }
+
+    fn force(&self) -> bool {
+        true
+    }
+
+    fn strict(&self) -> bool {
+        false
+    }
+
+    fn quiet(&self) -> bool {
+        false
+    }
+
+    fn require_docs(&self) -> bool {
+        false
+    }
+
+    fn writer_options(&self) -> &WriterOptions {
+        self.writer
+    }
+
+    fn config(&self) -> IndexConfig {
+        self.config.clone()
+    }
+}
+
+#[derive(Clone, Debug, Default, Parser)]
+struct WriterOptions {
+    /// merge threads
+    ///
+    /// The number of background threads tantivy uses to merge index segments. (Defaults to 4.)
+    /// More threads let large builds finish sooner at the cost of CPU available to everything
+    /// else on the machine; fewer threads keep other work responsive.
+    #[clap(long, value_parser = parse_nonzero)]
+    merge_threads: Option<usize>,
+
+    /// merge policy
+    ///
+    /// How tantivy merges index segments in the background. The default policy merges segments
+    /// of similar size as they accumulate, which keeps searches fast at the cost of rewriting
+    /// documents several times while indexing. No-merge never merges, which makes bulk loads
+    /// cheaper but leaves one segment per commit, slowing searches until the segments are merged
+    /// some other way.
+    #[clap(long, value_enum, default_value_t = MergePolicyKind::Default)]
+    merge_policy: MergePolicyKind,
+
+    /// smallest merged segment
+    ///
+    /// Segments with fewer documents than this are all treated as the same size, so they merge
+    /// together readily. (Default policy only; defaults to 10,000.) Raising it produces fewer,
+    /// larger segments.
+    #[clap(long, value_parser = parse_nonzero)]
+    merge_min_docs: Option<usize>,
+
+    /// largest merged segment
+    ///
+    /// Segments with more documents than this are left alone. (Default policy only; defaults to
+    /// 10,000,000.) Lowering it bounds the cost of any one merge, at the cost of more segments
+    /// in large indexes.
+    #[clap(long, value_parser = parse_nonzero)]
+    merge_max_docs: Option<usize>,
+
+    /// writer memory budget
+    ///
+    /// The memory, in megabytes, the index writer may use to buffer documents before writing
+    /// them out, shared among its threads. (Defaults to 200; must be between 15 and 4000.) A
+    /// bigger budget builds large libraries faster, in fewer segments; a smaller one suits a
+    /// machine short of memory.
+    #[clap(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(15..=4000))]
+    memory: Option<u64>,
+
+    /// files per commit
+    ///
+    /// Commit the index every this many files. (Defaults to 20,000.) Each commit makes the
+    /// documents added so far durable, at some cost in time.
+    #[clap(long, value_parser = parse_nonzero)]
+    batch_size: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum MergePolicyKind {
+    /// merge segments of similar size (tantivy's log merge policy)
+    #[default]
+    Default,
+
+    /// never merge segments
+    NoMerge,
+}
+
+impl WriterOptions {
+    /// The writer's memory budget, in bytes.
+    fn memory(&self) -> usize {
+        self.memory
+            .map_or(MEMORY, |megabytes| megabytes as usize * (1 << 20))
+    }
+
+    fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(BATCH_SIZE)
+    }
+
+    fn merge_policy(&self) -> Box<dyn MergePolicy> {
+        match self.merge_policy {
+            MergePolicyKind::Default => {
+                let mut policy = LogMergePolicy::default();
+                if let Some(docs) = self.merge_min_docs {
+                    policy.set_min_layer_size(docs.try_into().unwrap_or(u32::MAX));
+                }
+                if let Some(docs) = self.merge_max_docs {
+                    policy.set_max_docs_before_merge(docs);
+                }
+                Box::new(policy)
+            }
+            MergePolicyKind::NoMerge => Box::new(NoMergePolicy),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+struct SnippetOptions {
+    /// show snippets
+    ///
+    /// Print an excerpt of each matching document beneath its path, with the words that matched
+    /// highlighted when writing to a terminal. This is the default for plain output; it's
+    /// accepted for compatibility. (Html output always includes snippets.)
+    #[clap(long)]
+    snippet: bool,
+
+    /// show only paths
+    ///
+    /// Leave out the excerpts that plain output shows beneath each path. Finding an excerpt means
+    /// reading the document again, so this also makes searches of large documents quicker.
+    #[clap(long, conflicts_with = "snippet")]
+    no_snippet: bool,
+
+    /// characters of context before each match
+    #[clap(long, default_value_t = 75)]
+    snippet_before: usize,
+
+    /// characters of context after each match
+    #[clap(long, default_value_t = 75)]
+    snippet_after: usize,
+
+    /// snippets per document
+    ///
+    /// The maximum number of excerpts to show for each document. Where a document matches in
+    /// several places, the excerpts with the best matches are shown.
+    #[clap(long, default_value_t = 1, value_parser = parse_nonzero)]
+    snippet_count: usize,
+
+    /// snippet separator
+    ///
+    /// The text placed between excerpts when a document has more than one.
+    #[clap(long, default_value = " … ")]
+    snippet_separator: String,
+}
+
+impl SnippetOptions {
+    /// Whether plain output includes snippets.
+    fn show(&self) -> bool {
+        !self.no_snippet
+    }
+
+    fn create(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        field: Field,
+    ) -> tantivy::Result<Snippets> {
+        Snippets::create(
+            searcher,
+            query,
+            field,
+            self.snippet_before,
+            self.snippet_after,
+            self.snippet_count,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+struct SkipTake {
+    /// results to skip
+    ///
+    /// Pass over this many of the best results before showing any. (Defaults to 0, or the skip
+    /// set in config.toml.)
+    #[clap(short, long)]
+    skip: Option<usize>,
+
+    /// results to show
+    ///
+    /// Show at most this many results. (Defaults to 10, or the take set in config.toml.)
+    #[clap(
+        short,
+        long,
+        visible_alias = "limit",
+        visible_short_alias = 'n',
+        value_parser = parse_nonzero
+    )]
+    take: Option<usize>,
+
+    /// page of results
+    ///
+    /// Show the page of results this many pages in, counting from 0, where a page holds as many
+    /// results as --take shows. Takes the place of --skip.
+    #[clap(short, long)]
+    page: Option<usize>,
+}
+
+struct SearchFields {
+    /// file system path
+    path: Field,
+
+    /// author name/title as a facet
+    // byline: Field,
+
+    /// text
+    text: Field,
+
+    /// owner uid (unix only)
+    owner: Field,
+
+    /// permission bits (unix only)
+    mode: Field,
+
+    /// stable document id
+    id: Field,
+
+    /// source offsets of each block of text (html only)
+    blocks: Field,
+
+    /// content hash
+    hash: Field,
+
+    /// modification time, in seconds since the unix epoch
+    modified: Field,
+
+    /// file size in bytes
+    size: Field,
+
+    /// names of the directories between the library root and the file
+    path_terms: Field,
+
+    /// content type, as detected from the file's contents
+    mime: Field,
+
+    /// tags, each matched exactly
+    tags: Field,
+
+    /// unstemmed text (stemmed libraries only)
+    exact: Field,
+
+    /// title: an html document's title or first heading, or otherwise the file's name
+    title: Field,
+
+    /// multiplier applied to the document's score (1.0 unless given)
+    priority: Field,
+
+    /// where a chunk of a long document starts, as a byte offset into its text (chunked
+    /// libraries only)
+    chunk: Field,
+
+    /// where a chunk of a long document ends (chunked libraries only)
+    chunk_end: Field,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Libraries {
+    mapping: HashMap<PathBuf, String>,
+
+    /// libraries whose indexes were removed to save space, to be rebuilt when next searched
+    #[serde(default)]
+    evicted: HashSet<String>,
+
+    /// the most space all indexes together may take up, in bytes
+    #[serde(default)]
+    max_total_size: Option<u64>,
+
+    /// whether queries are recorded in the query log
+    #[serde(default)]
+    query_log: bool,
+
+    /// the groups each library belongs to
+    #[serde(default)]
+    groups: HashMap<String, BTreeSet<String>>,
+}
+
+impl Libraries {
+    fn from_path(path: &Path) -> io::Result<Libraries> {
+        let path = if path.ends_with("libraries.json") {
+            Cow::from(path)
+        } else {
+            Cow::from(path.join("libraries.json"))
+        };
+
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        // The registry is written to a temporary file and renamed into place so that an
+        // interrupted write can't leave us with a truncated registry. The file is named for this
+        // process, so that two processes saving at once can't write to the same one.
+        let registry = path.join("libraries.json");
+        let temp = path.join(format!("libraries.json.{}.tmp", process::id()));
+        serde_json::to_writer_pretty(&mut File::create(&temp)?, self)?;
+        fs::rename(temp, registry)
+    }
+
+    /// Returns the library other than `name` registered to a canonical root, if there is one.
+    fn root_owner(&self, root: &Path, name: &str) -> Option<&str> {
+        self.mapping
+            .get(root)
+            .map(String::as_str)
+            .filter(|&owner| owner != name)
+    }
+
+    /// The names of every registered library, in order.
+    fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.mapping.values().map(String::as_str).collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Returns the libraries in a group, in order by name.
+    fn group_members(&self, group: &str) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|(_, groups)| groups.contains(group))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn get_root(&self, name: &str) -> Option<&Path> {
+        self.get_roots(name).into_iter().next()
+    }
+
+    /// Returns every root of a library, in order.
+    fn get_roots(&self, name: &str) -> Vec<&Path> {
+        let mut roots: Vec<_> = self
+            .mapping
+            .iter()
+            .filter(|(_, value)| *value == name)
+            .map(|(key, _)| key.as_path())
+            .collect();
+        roots.sort_unstable();
+        roots
+    }
+
+    // fn get_index_name<'a>(&'a self, args: &'a Args) -> io::Result<&'a str> {
+    fn get_index_name<'a>(&'a self, path: &Path) -> io::Result<&'a str> {
+        // Roots are registered in canonical form, so a directory reached through a symlink still
+        // finds its library. Roots registered before that was so are canonicalized in turn. A
+        // directory beneath a root belongs to the library of the nearest root above it.
+        let canonical = canonicalize_root(path);
+        let name = self
+            .mapping
+            .get(path)
+            .or_else(|| self.mapping.get(&canonical))
+            .or_else(|| {
+                self.mapping
+                    .iter()
+                    .map(|(root, name)| (canonicalize_root(root), name))
+                    .filter(|(root, _)| canonical.starts_with(root))
+                    .max_by_key(|(root, _)| root.components().count())
+                    .map(|(_, name)| name)
+            });
+
+        name.map(String::as_str).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no library for {} (if a library's files have moved, point it at them with \
+                     relocate)",
+                    path.display()
+                ),
+            )
+        })
+    }
+}
+
+/// The size, modification time and content hash of every file in an index as of the last time it
+/// was written, stored alongside the index so that updates can tell which files have changed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct FileState {
+    files: HashMap<PathBuf, FileStamp>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct FileStamp {
+    modified: SystemTime,
+    size: u64,
+
+    /// the hash of the file's contents, if known, as stored with its documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+
+    /// when each of the file's sidecars (see `SIDECARS`) was modified, and its size
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    sidecars: BTreeMap<String, (SystemTime, u64)>,
+}
+
+impl FileState {
+    fn from_path(path: &Path) -> io::Result<Option<FileState>> {
+        let path = path.join("files.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let text = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&text)?))
+    }
+
+    /// Recovers the state of the files in an index from the modification times and sizes stored
+    /// with its documents, which are only accurate to the second. Returns `None` for indexes
+    /// that don't store them.
+    fn from_index(index: &Index, fields: &SearchFields) -> tantivy::Result<Option<FileState>> {
+        let schema = index.schema();
+        if schema.get_field("modified").is_err() || schema.get_field("size").is_err() {
+            return Ok(None);
+        }
+
+        let searcher = index.reader()?.searcher();
+        let mut files = HashMap::new();
+        for address in searcher.search(&AllQuery, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let stored = |field| doc.get_first(field).and_then(|value| value.as_u64());
+            if let (Some(path), Some(modified), Some(size)) = (
+                get_path(&doc, fields.path),
+                stored(fields.modified),
+                stored(fields.size),
+            ) {
+                let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(modified);
+                let hash = doc.get_first(fields.hash).and_then(|value| value.as_str());
+                let hash = hash.map(ToOwned::to_owned);
+                files.insert(
+                    PathBuf::from(path),
+                    FileStamp {
+                        modified,
+                        size,
+                        hash,
+                        sidecars: BTreeMap::new(),
+                    },
+                );
+            }
+        }
+
+        Ok(Some(FileState { files }))
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let state = path.join("files.json");
+        let temp = path.join("files.json.tmp");
+        serde_json::to_writer(&mut File::create(&temp)?, self)?;
+        fs::rename(temp, state)
+    }
+}
+
+/// Options given when an index was created, stored alongside the index so that updates can build
+/// the index the same way.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct IndexConfig {
+    max_depth: Option<usize>,
+
+    /// whether directory names are indexed as terms of the documents beneath them
+    dir_terms: bool,
+
+    /// the canonical library roots as of the last time the index was written
+    #[serde(alias = "root", deserialize_with = "one_or_many")]
+    roots: Vec<PathBuf>,
+
+    /// whether queries require all of their terms unless told otherwise
+    default_and: bool,
+
+    /// whether source files are indexed, by their comments alone
+    comments_only: bool,
+
+    /// whether the text is stemmed, with the unstemmed text indexed separately
+    stem: bool,
+
+    /// the extensions of the files indexed, where not the default set
+    extensions: Option<Vec<String>>,
+
+    /// how long documents are split into chunks, if they are
+    chunk: Option<Chunking>,
+
+    /// whether hidden files and directories are indexed
+    include_hidden: bool,
+
+    /// the largest file indexed, in bytes, where not the default
+    max_size: Option<u64>,
+
+    /// whether the contents of code blocks in markdown files are indexed
+    markdown_code: bool,
+
+    /// whether the targets of links in markdown files are indexed
+    markdown_links: bool,
+}
+
+/// Reads a list of paths that older versions wrote as a single path, or null.
+fn one_or_many<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<PathBuf>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Paths {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match Option::<Paths>::deserialize(deserializer)? {
+        Some(Paths::One(path)) => vec![path],
+        Some(Paths::Many(paths)) => paths,
+        None => Vec::new(),
+    })
+}
+
+/// The size of the chunks long documents are split into, and the overlap between them, in words.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+struct Chunking {
+    size: usize,
+    overlap: usize,
+}
+
+impl IndexConfig {
+    fn from_path(path: &Path) -> io::Result<IndexConfig> {
+        let path = path.join("config.json");
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn max_size(&self) -> u64 {
+        self.max_size.unwrap_or(DEFAULT_MAX_SIZE)
+    }
+
+    fn markdown(&self) -> markdown::Options {
+        markdown::Options {
+            code: self.markdown_code,
+            links: self.markdown_links,
+        }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let config = path.join("config.json");
+        let temp = path.join("config.json.tmp");
+        serde_json::to_writer(&mut File::create(&temp)?, self)?;
+        fs::rename(temp, config)
+    }
+}
+
+impl FileStamp {
+    fn from_path(path: &Path) -> io::Result<FileStamp> {
+        let meta = fs::metadata(path)?;
+        let sidecars = SIDECARS
+            .iter()
+            .filter_map(|&extension| {
+                let meta = fs::metadata(sidecar_path(path, extension)).ok()?;
+                let modified = meta.modified();
+                Some(modified.map(|modified| (extension.to_owned(), (modified, meta.len()))))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(FileStamp {
+            modified: meta.modified()?,
+            size: meta.len(),
+            hash: None,
+            sidecars,
+        })
+    }
+
+    /// Takes the stamp of a file along with the hash of its contents, as just read.
+    fn with_hash(path: &Path, data: &[u8]) -> io::Result<FileStamp> {
+        Ok(FileStamp {
+            hash: Some(document_hash(path, data)),
+            ..FileStamp::from_path(path)?
+        })
+    }
+
+    /// Returns true if a file is unchanged since this stamp was taken. A stamp recovered from
+    /// the index (see `FileState::from_index`) only knows the second the file was modified, so
+    /// the file's time is also compared to the second. It knows nothing of sidecars, so a file
+    /// with any is read again to compare its hash.
+    fn matches(&self, current: &FileStamp) -> bool {
+        let truncated = current
+            .modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| SystemTime::UNIX_EPOCH + Duration::from_secs(elapsed.as_secs()))
+            .ok();
+
+        self.size == current.size
+            && (self.modified == current.modified || Some(self.modified) == truncated)
+            && self.sidecars == current.sidecars
+    }
+}
+
+/// Does whatever the command line asks: a search, or one of the subcommands.
+///
+/// A command line that can't be made sense of fails with a [`clap::Error`], which should be
+/// reported the way clap reports its own.
+pub fn run(args: &Args) -> anyhow::Result<()> {
+    if let Some(command) = &args.command {
+        return dispatch(command);
+    }
+
+    let args = &args.with_config(Config::get());
+
+    if args.json_schema {
+        println!("{:#}", json::schema());
+        return Ok(());
+    }
+
+    // A blank query, as from an empty shell variable, or one made up of punctuation alone would
+    // match nothing and look like a search that found nothing. Words are only ever letters and
+    // digits, though a lone * is a query of its own.
+
+    let query = args.query.join(" ");
+    if !args.query.is_empty() && query.trim().is_empty() {
+        return Err(usage_error("no query provided"));
+    }
+    if !query.is_empty() && !query.contains(char::is_alphanumeric) && !query.contains('*') {
+        return Err(usage_error(format!(
+            "nothing to search for: {query:?} has no words in it"
+        )));
+    }
+
+    if let Some(group) = &args.group {
+        return search_group(args, group);
+    }
+
+    if args.all_libraries {
+        return search_all(args);
+    }
+
+    if args.interactive {
+        let (_, take) = args.skip_take();
+        return repl(&ReplCmd {
+            name: args.index.clone(),
+            take: *take,
+        });
+    }
+
+    // It is not valid to perform a search if no index is available, so the first thing we'll do
+    // is check to see that there's a valid index to search. We can do this on the basis of an
+    // index name or on the basis of the current working directory.
+
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.index.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+    prepare_library(&storage_path, &libraries, name)?;
+
+    // A library relocated without being updated still has an index full of paths under its old
+    // root, which may no longer exist.
+
+    let data_path = storage_path.join(name);
+    let config = IndexConfig::from_path(&data_path)?;
+    let registered: BTreeSet<_> = libraries
+        .get_roots(name)
+        .into_iter()
+        .map(canonicalize_root)
+        .collect();
+    if !config.roots.is_empty()
+        && config.roots.iter().collect::<BTreeSet<_>>() != registered.iter().collect()
+    {
+        eprintln!(
+            "warning: library {name:?} was indexed at {}; run update to refresh it",
+            display_roots(&config.roots)
+        );
+    }
+
+    let (_schema, fields) = build_schema();
+    let mut index = open_index(&data_path)?;
+    if let Some(threads) = args.search_threads {
+        index.set_multithread_executor(threads)?;
+    }
+
+    if args.is_listing() && !args.list && args.sort == Sort::Relevance {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a query is required unless listing the index with --list",
+        )
+        .into());
+    }
+
+    let mut required = Vec::new();
+    if matches!(args.sort(), Sort::Modified | Sort::Size) || args.size_range().is_some() {
+        required.push("size");
+    }
+    if !args.mime.is_empty() {
+        required.push("mime");
+    }
+    if !args.tag.is_empty() {
+        required.push("tags");
+    }
+
+    if required
+        .iter()
+        .any(|name| index.schema().get_field(name).is_err())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("library {name:?} was built by an older version; run create-index --force"),
+        )
+        .into());
+    }
+
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+    let parser = build_search_parser(&index, &fields, &config, args)?;
+    let query = build_query(&index, &parser, &fields, &config, args)?;
+
+    if args.verbose {
+        let chosen = if args.index.is_some() {
+            "given by --index".to_owned()
+        } else {
+            format!("registered to {}", env::current_dir()?.display())
+        };
+        eprintln!("library: {name} ({chosen})");
+        eprintln!("index: {}", data_path.display());
+        eprintln!("query: {query:?}");
+    }
+
+    if args.count {
+        println!("{}", count_matches(&searcher, &*query, &fields, args)?);
+        return Ok(());
+    }
+
+    // Opening html results at their best match needs more than the paths of the results, so
+    // those searches never use the cache.
+
+    let access = match args.recency {
+        Some(Recency::Access) => Some(AccessLog::open(&data_path)?),
+        None => None,
+    };
+
+    if args.open && args.open_highlight {
+        let (docs, _) = search(&searcher, &parser, &*query, &fields, args, access.as_ref())?;
+        let snippets = args.snippet.create(&searcher, &*query, fields.text)?;
+        let targets: Vec<_> = docs
+            .iter()
+            .filter_map(|(_, doc)| {
+                let path = get_path(doc, fields.path)?;
+                Some((path, highlight_target(doc, &fields, &snippets)?))
+            })
+            .collect();
+
+        let opened = Opener::new(args.open_options()?)
+            .open_all(targets.iter().map(|(_, target)| target.as_str()))?;
+        let opened = targets
+            .iter()
+            .filter(|(_, target)| opened.contains(&target.as_str()))
+            .map(|&(path, _)| path);
+        record_access(&data_path, opened)?;
+        return Ok(());
+    }
+
+    let ttl = Duration::from_secs(args.cache_ttl);
+    let opstamp = index.load_metas()?.opstamp;
+    let mut cache = if args.cache && args.recency.is_none() {
+        Some(ResultCache::open(&data_path)?)
+    } else {
+        None
+    };
+
+    let key = args.cache_key();
+    let cached = cache
+        .as_ref()
+        .and_then(|cache| cache.get(&key, opstamp, ttl));
+
+    let (found, total) = match cached {
+        Some(cached) => cached,
+        None => {
+            let (docs, total) =
+                search(&searcher, &parser, &*query, &fields, args, access.as_ref())?;
+            let found: Vec<_> = docs
+                .iter()
+                .filter_map(|(score, doc)| Some((*score, get_path(doc, fields.path)?.to_owned())))
+                .collect();
+
+            if let Some(cache) = &mut cache {
+                cache.insert(key, opstamp, total, found.clone());
+                cache.save(opstamp, ttl)?;
+            }
+
+            (found, total)
+        }
+    };
+
+    let query_string = args.query_string();
+    let log_query = match args.query_log {
+        Some(switch) => switch == Switch::On,
+        None => libraries.query_log,
+    };
+    if log_query && !query_string.is_empty() {
+        QueryLog::new(&storage_path).append(name, &query_string, total)?;
+    }
+
+    let mut hits: Vec<_> = found
+        .iter()
+        .map(|(score, path)| (*score, path.as_str()))
+        .collect();
+
+    if args.normalize_scores {
+        normalize_scores(&mut hits);
+    }
+
+    let texts = hits.iter().map(|&(_, path)| path);
+
+    if args.open {
+        let opened = Opener::new(args.open_options()?).open_all(texts)?;
+        record_access(&data_path, opened)?;
+    } else {
+        match args.format() {
+            Format::Plain if args.snippet.show() => {
+                let snippets = args.snippet.create(&searcher, &*query, fields.text)?;
+                write_snippets(
+                    &hits,
+                    &find_titles(&searcher, &fields, &hits)?,
+                    &find_chunks(&searcher, &*query, &fields, &hits)?,
+                    &snippets,
+                    &args.snippet.snippet_separator,
+                    args.show_scores(),
+                    args.color(),
+                );
+            }
+            Format::Plain => write_plain(
+                &hits,
+                &find_titles(&searcher, &fields, &hits)?,
+                args.show_scores(),
+                args.color(),
+            ),
+            Format::Html => {
+                let snippets = args.snippet.create(&searcher, &*query, fields.text)?;
+                write_html(texts, &snippets);
+            }
+            Format::Json => {
+                let titles = find_titles(&searcher, &fields, &hits)?;
+                let envelope = json::Envelope::new(&query_string, total, &hits, &titles);
+                println!("{}", serde_json::to_string(&envelope)?);
+            }
+            Format::Csv => write_csv(&hits, &args.fields, &fields, |path| {
+                find_document(&searcher, &fields, path)
+            })?,
+        }
+
+        if !matches!(args.format(), Format::Json) {
+            write_total(hits.len(), *args.skip_take().0, total);
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the parser for a search of one library, as configured on the command line and when
+/// the library was created.
+fn build_search_parser(
+    index: &Index,
+    fields: &SearchFields,
+    config: &IndexConfig,
+    args: &Args,
+) -> anyhow::Result<SearchParser> {
+    let mut parser = build_parser(index, fields, args.profile.as_deref(), &args.boost)?;
+    if args.all || (config.default_and && !args.any) {
+        parser.set_conjunction_by_default();
+    }
+    Ok(parser)
+}
+
+/// Builds the query for a search of one library from whichever of the query, --by-id and
+/// --query-spec were given, restricted as asked.
+fn build_query(
+    index: &Index,
+    parser: &SearchParser,
+    fields: &SearchFields,
+    config: &IndexConfig,
+    args: &Args,
+) -> anyhow::Result<Box<dyn Query>> {
+    let query: Box<dyn Query> = match (args.by_id.as_deref(), args.query_spec.as_deref()) {
+        (Some(id), _) => Box::new(TermQuery::new(
+            Term::from_field_text(fields.id, id),
+            IndexRecordOption::Basic,
+        )),
+        (None, Some(spec)) => spec::parse(spec, index)?,
+        _ if args.is_listing() => Box::new(AllQuery),
+        _ if args.wildcard => {
+            let conjunction = args.all || (config.default_and && !args.any);
+            wildcard_query(parser, fields, &args.query_string(), conjunction)?
+        }
+        _ if args.fuzzy => {
+            let conjunction = args.all || (config.default_and && !args.any);
+            fuzzy_query(
+                index,
+                parser,
+                fields.text,
+                &args.query_string(),
+                conjunction,
+                args.fuzzy_distance,
+            )?
+        }
+        _ if args.boost_exact && config.stem => {
+            let mut exact = SearchParser::new(index, vec![fields.exact]);
+            if args.all || (config.default_and && !args.any) {
+                exact.set_conjunction_by_default();
+            }
+            let query = args.query_string();
+            prefer_exact(parser.parse(&query)?, exact.parse(&query)?)
+        }
+        _ => parser.parse(&args.query_string())?,
+    };
+    filter_query(index, query, fields, args)
+}
+
+/// Searches every library in a group (see --group).
+fn search_group(args: &Args, group: &str) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let names = libraries.group_members(group);
+    if names.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no libraries belong to group {group:?}"),
+        )
+        .into());
+    }
+
+    search_libraries(args, &storage_path, &libraries, &names)
+}
+
+/// Searches every registered library (see --all-libraries), other than those evicted to save
+/// space, which would otherwise all be rebuilt at once, and those whose index has gone missing.
+fn search_all(args: &Args) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let mut names = Vec::new();
+    for name in libraries.names() {
+        if libraries.evicted.contains(name) {
+            eprintln!(
+                "skipping library {name:?}, which was evicted; search it alone to rebuild it"
+            );
+        } else if !storage_path.join(name).join("meta.json").exists() {
+            eprintln!("skipping library {name:?}, whose index is missing");
+        } else {
+            names.push(name);
+        }
+    }
+
+    if names.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no libraries to search").into());
+    }
+
+    search_libraries(args, &storage_path, &libraries, &names)
+}
+
+/// Searches several libraries, merging their results.
+///
+/// Each library is asked for every result up to the end of the page wanted, so that the page
+/// can be cut from the merged results.
+fn search_libraries(
+    args: &Args,
+    storage_path: &Path,
+    libraries: &Libraries,
+    names: &[&str],
+) -> anyhow::Result<()> {
+    if matches!(args.format(), Format::Html) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "html output isn't supported when searching more than one library",
+        )
+        .into());
+    }
+
+    if args.is_listing() && !args.list && args.sort == Sort::Relevance {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a query is required unless listing the index with --list",
+        )
+        .into());
+    }
+
+    let (skip, take) = args.skip_take();
+    let mut wide = args.clone();
+    wide.skip_take = SkipTake {
+        skip: None,
+        take: Some(*skip + *take),
+        page: None,
+    };
+
+    let (_schema, fields) = build_schema();
+    let mut found = Vec::new();
+    let mut total = 0;
+
+    for &name in names {
+        prepare_library(storage_path, libraries, name)?;
+        let data_path = storage_path.join(name);
+        let config = IndexConfig::from_path(&data_path)?;
+        let index = open_index(&data_path)?;
+
+        let searcher = index.reader()?.searcher();
+        let parser = build_search_parser(&index, &fields, &config, args)?;
+        let query = build_query(&index, &parser, &fields, &config, args)?;
+        if args.verbose {
+            eprintln!("library: {name} ({})", data_path.display());
+            eprintln!("query: {query:?}");
+        }
+
+        if args.count {
+            total += count_matches(&searcher, &*query, &fields, args)?;
+            continue;
+        }
+
+        let (docs, count) = search(&searcher, &parser, &*query, &fields, &wide, None)?;
+        total += count;
+        found.extend(docs.into_iter().map(|(score, doc)| (score, name, doc)));
+    }
+
+    if args.count {
+        println!("{total}");
+        return Ok(());
+    }
+
+    let stored = |doc: &TantivyDocument, field| {
+        doc.get_first(field)
+            .and_then(|value| value.as_u64())
+            .unwrap_or_default()
+    };
+    match args.sort() {
+        Sort::Relevance => found.sort_by(|a, b| b.0.total_cmp(&a.0)),
+        Sort::Modified => found.sort_by_key(|(_, _, doc)| Reverse(stored(doc, fields.modified))),
+        Sort::Size => found.sort_by_key(|(_, _, doc)| Reverse(stored(doc, fields.size))),
+        Sort::Path => {
+            found.sort_by(|a, b| get_path(&a.2, fields.path).cmp(&get_path(&b.2, fields.path)))
+        }
+    }
+    found.truncate(*skip + *take);
+    let found = found.split_off((*skip).min(found.len()));
+
+    let query_string = args.query_string();
+    let log_query = match args.query_log {
+        Some(switch) => switch == Switch::On,
+        None => libraries.query_log,
+    };
+    if log_query && !query_string.is_empty() {
+        QueryLog::new(storage_path).append(&names.join(","), &query_string, total)?;
+    }
+
+    let mut hits: Vec<_> = found
+        .iter()
+        .filter_map(|(score, _, doc)| Some((*score, get_path(doc, fields.path)?)))
+        .collect();
+    if args.normalize_scores {
+        normalize_scores(&mut hits);
+    }
+
+    if args.open {
+        let opened =
+            Opener::new(args.open_options()?).open_all(hits.iter().map(|&(_, path)| path))?;
+        for &name in names {
+            let paths = found
+                .iter()
+                .filter(|(_, library, _)| *library == name)
+                .filter_map(|(_, _, doc)| get_path(doc, fields.path))
+                .filter(|path| opened.contains(path));
+            record_access(&storage_path.join(name), paths)?;
+        }
+        return Ok(());
+    }
+
+    let titles: Vec<_> = found
+        .iter()
+        .map(|(_, _, doc)| Some(doc.get_first(fields.title)?.as_str()?.to_owned()))
+        .collect();
+    let names: Vec<_> = found.iter().map(|&(_, name, _)| name).collect();
+
+    match args.format() {
+        Format::Plain => {
+            for ((&(score, path), title), &name) in hits.iter().zip(&titles).zip(&names) {
+                write_hit(
+                    score,
+                    Some(name),
+                    title.as_deref(),
+                    path,
+                    args.show_scores(),
+                    args.color(),
+                );
+            }
+        }
+        Format::Json => {
+            let envelope =
+                json::Envelope::new(&query_string, total, &hits, &titles).with_libraries(&names);
+            println!("{}", serde_json::to_string(&envelope)?);
+        }
+        Format::Csv => {
+            let docs: HashMap<_, _> = found
+                .iter()
+                .filter_map(|(_, _, doc)| Some((get_path(doc, fields.path)?, doc)))
+                .collect();
+            write_csv(&hits, &args.fields, &fields, |path| {
+                Ok(docs.get(path).map(|&doc| doc.clone()))
+            })?;
+        }
+        Format::Html => unreachable!("html output is rejected above"),
+    }
+
+    if !matches!(args.format(), Format::Json) {
+        write_total(hits.len(), *args.skip_take().0, total);
+    }
+
+    Ok(())
+}
+
+/// Reports how many of the matching documents were shown, on standard error so that it doesn't
+/// get mixed up with the results.
+fn write_total(shown: usize, skip: usize, total: usize) {
+    let matches = if total == 1 { "match" } else { "matches" };
+    match (shown, skip) {
+        _ if total == 0 => eprintln!("no matches"),
+        (0, _) => eprintln!("no more matches ({total} in all)"),
+        (shown, 0) => eprintln!("showing {shown} of {total} {matches}"),
+        (shown, skip) => eprintln!("showing {}-{} of {total} {matches}", skip + 1, skip + shown),
+    }
+}
+
+/// Records documents opened through search in the library's access log (see --recency).
+fn record_access<'a>(data_path: &Path, paths: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+    let mut paths = paths.into_iter().peekable();
+    if paths.peek().is_none() {
+        return Ok(());
+    }
+
+    let mut log = AccessLog::open(data_path)?;
+    log.record(paths);
+    log.save()
+}
+
+/// Builds a link to the best match in an html document, or returns the document's path where
+/// that isn't possible.
+fn highlight_target(
+    doc: &TantivyDocument,
+    fields: &SearchFields,
+    snippets: &Snippets,
+) -> Option<String> {
+    let path = get_path(doc, fields.path)?;
+    let target = || {
+        let blocks: Vec<Block> =
+            serde_json::from_str(doc.get_first(fields.blocks)?.as_str()?).ok()?;
+        let source = read_source(Path::new(path)).ok()?;
+        let (text, _) = extract_html(&source);
+        let matched = snippets.first_match(&text)?;
+        let offset = locate(&blocks, matched.start)?;
+        let matched = source.get(offset..offset + matched.len())?;
+
+        Some(format!(
+            "{}#:~:text={}",
+            file_url(path),
+            percent_encode(matched, b"")
+        ))
+    };
+
+    Some(target().unwrap_or_else(|| path.to_owned()))
+}
+
+/// Scales scores relative to the best result shown, so that the top result scores 1.
+///
+/// Raw scores depend on the query and on the contents of the index, which makes them hard to
+/// interpret. Normalized scores are easier to read, but are only comparable within one page of
+/// results for one query.
+fn normalize_scores(hits: &mut [(Score, &str)]) {
+    let top = hits.iter().map(|&(score, _)| score).fold(0.0, Score::max);
+    if top > 0.0 {
+        for (score, _) in hits {
+            *score /= top;
+        }
+    }
+}
+
+fn write_plain(hits: &[(Score, &str)], titles: &[Option<String>], show_scores: bool, color: bool) {
+    for (&(score, path), title) in hits.iter().zip(titles) {
+        write_hit(score, None, title.as_deref(), path, show_scores, color);
+    }
+}
+
+fn write_snippets(
+    hits: &[(Score, &str)],
+    titles: &[Option<String>],
+    chunks: &[Option<Range<usize>>],
+    snippets: &Snippets,
+    separator: &str,
+    show_scores: bool,
+    color: bool,
+) {
+    // Matches are picked out in bold yellow when coloring, and left alone where the output is
+    // going to another program.
+    let (prefix, suffix) = if color {
+        ("\x1b[1;33m", "\x1b[0m")
+    } else {
+        ("", "")
+    };
+
+    for ((&(score, path), title), chunk) in hits.iter().zip(titles).zip(chunks) {
+        write_hit(score, None, title.as_deref(), path, show_scores, color);
+
+        // The text field isn't stored in the index, so the only way to build a snippet is to
+        // read the document again. A document that has gone missing just gets no snippet, and
+        // one that has changed since it was indexed may have lost its best chunk.
+        let text = read_text(Path::new(path)).unwrap_or_default();
+        let text = chunk
+            .clone()
+            .and_then(|chunk| text.get(chunk))
+            .unwrap_or(&text);
+        let fragments = snippets.fragments(text);
+        if !fragments.is_empty() {
+            let line = snippet::join(&fragments, separator, prefix, suffix, ToOwned::to_owned);
+            println!("    {line}");
+        }
+    }
+}
+
+/// Prints a result as a line of tab-separated fields: the library, when searching more than one,
+/// the score, if asked for, the title, if the library has titles, and the path, in magenta when
+/// coloring.
+fn write_hit(
+    score: Score,
+    library: Option<&str>,
+    title: Option<&str>,
+    path: &str,
+    show_scores: bool,
+    color: bool,
+) {
+    let mut line = String::new();
+    if let Some(library) = library {
+        line += library;
+        line.push('\t');
+    }
+    if show_scores {
+        line += &format!("{score:.2}\t");
+    }
+    if let Some(title) = title {
+        line += &title.replace(['\t', '\n', '\r'], " ");
+        line.push('\t');
+    }
+    if color {
+        line += &format!("\x1b[35m{path}\x1b[0m");
+    } else {
+        line += path;
+    }
+    println!("{line}");
+}
+
+/// Looks up the title of each hit, leaving it out for libraries built without titles.
+fn find_titles(
+    searcher: &Searcher,
+    fields: &SearchFields,
+    hits: &[(Score, &str)],
+) -> tantivy::Result<Vec<Option<String>>> {
+    if searcher.schema().get_field("title").is_err() {
+        return Ok(vec![None; hits.len()]);
+    }
+
+    hits.iter()
+        .map(|&(_, path)| {
+            let doc = find_document(searcher, fields, path)?;
+            Ok(doc.and_then(|doc| Some(doc.get_first(fields.title)?.as_str()?.to_owned())))
+        })
+        .collect()
+}
+
+/// Writes results as csv, using `find` to look up the stored document with a given path.
+fn write_csv<F>(
+    hits: &[(Score, &str)],
+    columns: &[Column],
+    fields: &SearchFields,
+    find: F,
+) -> tantivy::Result<()>
+where
+    F: Fn(&str) -> tantivy::Result<Option<TantivyDocument>>,
+{
+    let header: Vec<_> = columns
+        .iter()
+        .filter_map(|column| column.to_possible_value())
+        .map(|value| value.get_name().to_owned())
+        .collect();
+    println!("{}", header.join(","));
+
+    // Hits carry only a score and a path, so the stored fields are looked up by path, and only
+    // if they're wanted.
+
+    let stored = columns
+        .iter()
+        .any(|column| matches!(column, Column::Modified | Column::Size));
+
+    for &(score, path) in hits {
+        let doc = if stored { find(path)? } else { None };
+        let value = |field| doc.as_ref()?.get_first(field)?.as_u64();
+
+        let row: Vec<_> = columns
+            .iter()
+            .map(|column| match column {
+                Column::Path => path.to_owned(),
+                Column::Score => score.to_string(),
+                Column::Modified => value(fields.modified)
+                    .map(|secs| {
+                        querylog::timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                    })
+                    .unwrap_or_default(),
+                Column::Size => value(fields.size)
+                    .map(|size| size.to_string())
+                    .unwrap_or_default(),
+            })
+            .map(|value| csv_field(&value))
+            .collect();
+        println!("{}", row.join(","));
+    }
+
+    Ok(())
+}
+
+/// Quotes a csv field if it contains anything that would otherwise break up the row.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn find_document(
+    searcher: &Searcher,
+    fields: &SearchFields,
+    path: &str,
+) -> tantivy::Result<Option<TantivyDocument>> {
+    let query = TermQuery::new(
+        Term::from_field_text(fields.path, path),
+        IndexRecordOption::Basic,
+    );
+    let top = searcher.search(&query, &TopDocs::with_limit(1).order_by_score())?;
+    top.first()
+        .map(|&(_, address)| searcher.doc(address))
+        .transpose()
+}
+
+fn write_html<'a>(paths: impl IntoIterator<Item = &'a str>, snippets: &Snippets) {
+    println!("<ol class=\"search-results\">");
+    for path in paths {
+        let text = read_text(Path::new(path)).unwrap_or_default();
+        let href = escape_html(&file_url(path));
+        println!("  <li>");
+        println!("    <a href=\"{href}\">{}</a>", escape_html(path));
+        for fragment in snippets.fragments(&text) {
+            println!(
+                "    <p>{}</p>",
+                fragment.render("<mark>", "</mark>", escape_html)
+            );
+        }
+        println!("  </li>");
+    }
+    println!("</ol>");
+}
+
+fn build_parser(
+    index: &Index,
+    fields: &SearchFields,
+    profile: Option<&str>,
+    boost: &[(String, f32)],
+) -> anyhow::Result<SearchParser> {
+    let schema = index.schema();
+    let get_field = |name: &str| {
+        schema.get_field(name).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no field named {name:?}"),
+            )
+        })
+    };
+
+    let profiles;
+    let profile = match profile {
+        Some(name) => {
+            profiles = Profiles::from_path(&get_config_path()?)?;
+            Some(profiles.get(name)?)
+        }
+        None => None,
+    };
+
+    let default_fields = match profile.and_then(|profile| profile.fields.as_ref()) {
+        Some(names) => names
+            .iter()
+            .map(|name| get_field(name))
+            .collect::<io::Result<_>>()?,
+        None => {
+            let mut default_fields = vec![fields.text];
+            default_fields.extend(
+                ["path_terms", "title"]
+                    .iter()
+                    .filter_map(|name| schema.get_field(name).ok()),
+            );
+            default_fields
+        }
+    };
+
+    let mut parser = SearchParser::new(index, default_fields);
+    if schema.get_field("path_terms").is_ok() {
+        parser
+            .parser
+            .set_field_boost(fields.path_terms, PATH_TERMS_BOOST);
+    }
+
+    // Boosts given on the command line are applied last so that they win over the profile.
+
+    let profile_boosts = profile.into_iter().flat_map(|profile| &profile.boosts);
+    let boosts = profile_boosts.chain(boost.iter().map(|(name, boost)| (name, boost)));
+    for (name, &boost) in boosts {
+        parser.parser.set_field_boost(get_field(name)?, boost);
+    }
+
+    Ok(parser)
+}
+
+/// Parses queries as typed on the command line, searching each word in a set of default fields.
+///
+/// Every query should be parsed through this rather than tantivy's parser alone, so that a
+/// prefix such as `config*` (see `expand_prefixes`) matches the same documents wherever the
+/// query came from. Tantivy only matches patterns against fields named outright, so the parser
+/// needs to remember its default fields to expand prefixes into.
+pub(crate) struct SearchParser {
+    parser: QueryParser,
+
+    /// the names of the default fields that hold text, which are all patterns can match
+    prefix_fields: Vec<String>,
+}
+
+impl SearchParser {
+    fn new(index: &Index, default_fields: Vec<Field>) -> Self {
+        let schema = index.schema();
+        let prefix_fields = default_fields
+            .iter()
+            .filter(|&&field| {
+                matches!(
+                    schema.get_field_entry(field).field_type(),
+                    FieldType::Str(_)
+                )
+            })
+            .map(|&field| schema.get_field_name(field).to_owned())
+            .collect();
+
+        let mut parser = QueryParser::for_index(index, default_fields);
+        parser.allow_regexes();
+        Self {
+            parser,
+            prefix_fields,
+        }
+    }
+
+    fn set_conjunction_by_default(&mut self) {
+        self.parser.set_conjunction_by_default();
+    }
+
+    fn parse(&self, query: &str) -> anyhow::Result<Box<dyn Query>> {
+        let query = expand_prefixes(query, &self.prefix_fields)?;
+        Ok(self.parser.parse_query(&query)?)
+    }
+}
+
+/// Builds a query in which words containing `*` match any word of the text fitting the pattern
+/// (see --wildcard). The rest of the query is parsed as usual.
+fn wildcard_query(
+    parser: &SearchParser,
+    fields: &SearchFields,
+    query: &str,
+    conjunction: bool,
+) -> anyhow::Result<Box<dyn Query>> {
+    // Prefixes shorter than this match enough words to be worth a warning.
+    static SHORT_PREFIX: usize = 3;
+
+    let default = if conjunction {
+        Occur::Must
+    } else {
+        Occur::Should
+    };
+
+    let mut rest = Vec::new();
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+    for word in query.split_whitespace() {
+        let (occur, pattern) = match word.strip_prefix(['+', '-']) {
+            Some(pattern) if word.starts_with('+') => (Occur::Must, pattern),
+            Some(pattern) => (Occur::MustNot, pattern),
+            None => (default, word),
+        };
+
+        // The parser rejects a query that only excludes terms, so exclusions are parsed on their
+        // own.
+        if !pattern.contains('*') {
+            match occur {
+                Occur::MustNot => clauses.push((occur, parser.parse(pattern)?)),
+                _ => rest.push(word),
+            }
+            continue;
+        }
+
+        let prefix = pattern.split('*').next().unwrap_or_default();
+        if prefix.chars().count() < SHORT_PREFIX {
+            eprintln!("warning: {word:?} matches many words, which may make this search slow");
+        }
+
+        // Terms are stored in lowercase, so the pattern is lowercased to match.
+        let regex = pattern
+            .to_lowercase()
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        clauses.push((
+            occur,
+            Box::new(RegexQuery::from_pattern(&regex, fields.text)?),
+        ));
+    }
+
+    if !rest.is_empty() {
+        clauses.push((default, parser.parse(&rest.join(" "))?));
+    }
+
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// A word of a query ending in `*`, such as `config*` or `title:config*`.
+struct Prefix<'a> {
+    /// where the whole word, with its field and `*`, is found in the query
+    range: Range<usize>,
+
+    /// the field the word names, if it names one
+    field: Option<&'a str>,
+
+    /// the word without its field or `*`
+    prefix: &'a str,
+}
+
+/// Finds the words of a query ending in `*` that stand alone, outside any phrase.
+///
+/// Prefixes must be at least a few characters long: shorter ones match so much of the index that
+/// the search would crawl. Anything more elaborate is left to --wildcard.
+fn find_prefixes(query: &str) -> io::Result<Vec<Prefix<'_>>> {
+    static MIN_PREFIX: usize = 3;
+    let pattern = Regex::new(r"(?:\b(\w+):)?\b(\w+)\*").expect("valid regex");
+
+    let mut prefixes = Vec::new();
+    for captures in pattern.captures_iter(query) {
+        let whole = captures.get(0).expect("whole match");
+
+        // Words inside a phrase, or in the middle of something else, aren't prefixes.
+        let quoted = query[..whole.start()].matches('"').count() % 2 == 1;
+        let starts_word = query[..whole.start()]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || "(+-".contains(c));
+        let ends_word = query[whole.end()..]
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || c == ')');
+        if quoted || !starts_word || !ends_word {
+            continue;
+        }
+
+        let prefix = captures.get(2).expect("prefix").as_str();
+        if prefix.chars().count() < MIN_PREFIX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{:?} is too short a prefix; give at least {MIN_PREFIX} characters before the *",
+                    whole.as_str()
+                ),
+            ));
+        }
+
+        prefixes.push(Prefix {
+            range: whole.range(),
+            field: captures.get(1).map(|field| field.as_str()),
+            prefix,
+        });
+    }
+
+    Ok(prefixes)
+}
+
+/// Rewrites each word of a query ending in `*`, such as `config*`, as a regex matching the words
+/// that start with it in each of the given fields, so that the parser searches for every word
+/// with that prefix wherever it would search for a word. Words given with a field of their own
+/// (`title:config*`) are matched against that field alone.
+fn expand_prefixes<'a>(query: &'a str, fields: &[String]) -> io::Result<Cow<'a, str>> {
+    let prefixes = find_prefixes(query)?;
+    if prefixes.is_empty() {
+        return Ok(Cow::from(query));
+    }
+
+    let mut expanded = String::new();
+    let mut last = 0;
+    for Prefix {
+        range,
+        field,
+        prefix,
+    } in prefixes
+    {
+        // Terms are stored in lowercase, so the prefix is lowercased to match.
+        let prefix = regex::escape(&prefix.to_lowercase());
+        let patterns: Vec<_> = match field {
+            Some(field) => vec![format!("{field}:/{prefix}.*/")],
+            None => fields
+                .iter()
+                .map(|field| format!("{field}:/{prefix}.*/"))
+                .collect(),
+        };
+
+        expanded += &query[last..range.start];
+        match &patterns[..] {
+            [pattern] => expanded += pattern,
+            patterns => expanded += &format!("({})", patterns.join(" OR ")),
+        }
+        last = range.end;
+    }
+
+    expanded += &query[last..];
+    Ok(Cow::from(expanded))
+}
+
+/// Builds a query matching each word of a query string either exactly or approximately (see
+/// --fuzzy).
+///
+/// Approximate matches all score the same, and low, so the exact match of each word is what
+/// ranks documents. Each word is tokenized as the text was, so that it's compared with the words
+/// actually in the index.
+fn fuzzy_query(
+    index: &Index,
+    parser: &SearchParser,
+    field: Field,
+    query: &str,
+    conjunction: bool,
+    distance: u8,
+) -> anyhow::Result<Box<dyn Query>> {
+    let default = if conjunction {
+        Occur::Must
+    } else {
+        Occur::Should
+    };
+
+    let mut tokenizer = index.tokenizer_for_field(field)?;
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+    for word in query.split_whitespace() {
+        let (occur, word) = match word.strip_prefix(['+', '-']) {
+            Some(rest) if word.starts_with('+') => (Occur::Must, rest),
+            Some(rest) => (Occur::MustNot, rest),
+            None => (default, word),
+        };
+
+        let exact = parser.parse(word)?;
+        if occur == Occur::MustNot {
+            clauses.push((occur, exact));
+            continue;
+        }
+
+        let mut terms: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        tokenizer.token_stream(word).process(&mut |token| {
+            let term = Term::from_field_text(field, &token.text);
+            terms.push((
+                Occur::Must,
+                Box::new(FuzzyTermQuery::new(term, distance, true)),
+            ));
+        });
+
+        let query: Box<dyn Query> = if terms.is_empty() {
+            exact
+        } else {
+            Box::new(BooleanQuery::new(vec![
+                (Occur::Should, exact),
+                (Occur::Should, Box::new(BooleanQuery::new(terms))),
+            ]))
+        };
+        clauses.push((occur, query));
+    }
+
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Combines a stemmed query with the same query over the unstemmed text (see --boost-exact).
+///
+/// Documents must match the stemmed query, which finds every form of a word; matching the exact
+/// query as well only adds to their score.
+fn prefer_exact(stemmed: Box<dyn Query>, exact: Box<dyn Query>) -> Box<dyn Query> {
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Must, stemmed),
+        (Occur::Should, Box::new(BoostQuery::new(exact, EXACT_BOOST))),
+    ]))
+}
+
+/// Restricts a query to documents of the content types given with --mime and carrying every tag
+/// given with --tag.
+///
+/// The restrictions are applied as part of the query, rather than to its results, so that they
+/// don't eat into the page of results shown. Matching a content type or tag adds nothing to a
+/// document's score.
+fn filter_query(
+    index: &Index,
+    query: Box<dyn Query>,
+    fields: &SearchFields,
+    args: &Args,
+) -> anyhow::Result<Box<dyn Query>> {
+    if args.mime.is_empty()
+        && args.tag.is_empty()
+        && args.under.is_none()
+        && args.exclude_path.is_empty()
+    {
+        return Ok(query);
+    }
+
+    let term_query = |field, value: &str| -> Box<dyn Query> {
+        let term = Term::from_field_text(field, value);
+        Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+    };
+    let unscored = |query| -> Box<dyn Query> { Box::new(ConstScoreQuery::new(query, 0.0)) };
+
+    let mut clauses = vec![(Occur::Must, query)];
+
+    if !args.mime.is_empty() {
+        let unknown = args.mime_include_unknown.then_some(mime::UNKNOWN);
+        let types = args.mime.iter().map(String::as_str).chain(unknown);
+        let types = types
+            .map(|mime| (Occur::Should, term_query(fields.mime, mime)))
+            .collect();
+        clauses.push((Occur::Must, unscored(Box::new(BooleanQuery::new(types)))));
+    }
+
+    for tag in &args.tag {
+        clauses.push((Occur::Must, unscored(term_query(fields.tags, tag))));
+    }
+
+    // Paths are indexed whole, so a path beneath a directory is one that matches the directory
+    // followed by a separator and anything at all.
+
+    let prefixes = args.under_prefixes();
+    if !prefixes.is_empty() {
+        let separator = regex::escape(path::MAIN_SEPARATOR_STR);
+        let prefixes: Vec<_> = prefixes
+            .iter()
+            .map(|prefix| regex::escape(prefix))
+            .collect();
+        let pattern = format!("(?:{})(?:{separator}.*)?", prefixes.join("|"));
+        let under = RegexQuery::from_pattern(&pattern, fields.path)?;
+        clauses.push((Occur::Must, unscored(Box::new(under))));
+    }
+
+    // Excluded paths are found by running each expression over every path in the index, rather
+    // than with a regex query, which would have to match the whole path and knows no anchors.
+
+    if !args.exclude_path.is_empty() {
+        let searcher = index.reader()?.searcher();
+        let mut excluded = Vec::new();
+        for reader in searcher.segment_readers() {
+            let inverted_index = reader.inverted_index(fields.path)?;
+            let mut paths = inverted_index.terms().stream()?;
+            while paths.advance() {
+                let Ok(path) = str::from_utf8(paths.key()) else {
+                    continue;
+                };
+                if args.exclude_path.iter().any(|regex| regex.is_match(path)) {
+                    excluded.push(Term::from_field_text(fields.path, path));
+                }
+            }
+        }
+        clauses.push((Occur::MustNot, Box::new(TermSetQuery::new(excluded))));
+    }
+
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Runs the search described by the command line, returning the documents found and the total
+/// number of matching documents.
+fn search(
+    searcher: &Searcher,
+    parser: &SearchParser,
+    query: &dyn Query,
+    fields: &SearchFields,
+    args: &Args,
+    access: Option<&AccessLog>,
+) -> anyhow::Result<(Vec<(Score, TantivyDocument)>, usize)> {
+    if args.one_per_term {
+        let docs = best_per_term(searcher, parser, query, fields, args)?;
+        let total = docs.len();
+        return Ok((docs, total));
+    }
+
+    let (skip, take) = args.skip_take();
+    let filtered = args.filters_metadata();
+    if !is_chunked(searcher) && !filtered {
+        let (texts, total) = top_docs(searcher, query, fields, args, access, *skip, *take)?;
+        let docs = texts
+            .into_iter()
+            .filter_map(|(score, doc_id)| Some((score, searcher.doc(doc_id).ok()?)))
+            .collect();
+        return Ok((docs, total));
+    }
+
+    // A long document may match in several of its chunks, of which only the best is shown. The
+    // chunks are ranked together from the top, with more collected until there are enough
+    // documents to fill the page or there are no more chunks to collect.
+    //
+    // Documents with the wrong owner or mode are dropped as they're found. Since every match
+    // must be looked at to count those that are left, they're all collected at once.
+
+    let wanted = *skip + *take;
+    let everything = (searcher.num_docs() as usize).max(1);
+    let mut limit = if filtered { everything } else { wanted * 2 };
+    loop {
+        let (texts, _) = top_docs(searcher, query, fields, args, access, 0, limit)?;
+        let exhausted = texts.len() < limit || limit >= everything;
+
+        let mut seen = HashSet::new();
+        let docs: Vec<(Score, TantivyDocument)> = texts
+            .into_iter()
+            .filter_map(|(score, doc_id)| Some((score, searcher.doc(doc_id).ok()?)))
+            .filter(|(_, doc)| args.matches_metadata(doc, fields))
+            .filter(|(_, doc)| seen.insert(get_path(doc, fields.path).map(ToOwned::to_owned)))
+            .collect();
+
+        if docs.len() >= wanted || exhausted {
+            let total = if filtered {
+                docs.len()
+            } else {
+                count_documents(searcher, query, fields, args)?
+            };
+            let docs = docs.into_iter().skip(*skip).take(*take).collect();
+            return Ok((docs, total));
+        }
+        limit *= 2;
+    }
+}
+
+/// Collects a page of matches, ranked as asked for on the command line.
+fn top_docs(
+    searcher: &Searcher,
+    query: &dyn Query,
+    fields: &SearchFields,
+    args: &Args,
+    access: Option<&AccessLog>,
+    skip: usize,
+    take: usize,
+) -> anyhow::Result<(Vec<(Score, DocAddress)>, usize)> {
+    let top = TopDocs::with_limit(take).and_offset(skip);
+    let (texts, total) = match args.sort() {
+        // Adjusting scores costs us the collector's ability to skip documents that can't make
+        // the cut, so scores are only adjusted when there's something to adjust them by.
+        Sort::Relevance if access.is_none() && !has_priorities(searcher) => {
+            collect(searcher, query, (top.order_by_score(), Count), args)?
+        }
+        Sort::Relevance => {
+            let boosts: Vec<_> = access
+                .into_iter()
+                .flat_map(AccessLog::boosts)
+                .map(|(path, boost)| (Term::from_field_text(fields.path, path), boost))
+                .collect();
+            let path = fields.path;
+
+            let boosted = top.tweak_score(move |reader: &SegmentReader| {
+                let boosts = segment_boosts(reader, path, &boosts);
+                let priorities = reader.fast_fields().f64("priority").ok();
+                move |doc_id, score: Score| {
+                    let priority = priorities
+                        .as_ref()
+                        .and_then(|column| column.first(doc_id))
+                        .unwrap_or(1.0);
+                    score * priority as Score * boosts.get(&doc_id).unwrap_or(&1.0)
+                }
+            });
+            collect(searcher, query, (boosted, Count), args)?
+        }
+        Sort::Modified | Sort::Size => {
+            let field = if args.sort() == Sort::Modified {
+                "modified"
+            } else {
+                "size"
+            };
+
+            // Sorting by a fast field reads one value per matching document from a column,
+            // rather than loading every stored document to compare them.
+
+            let sorted = top.tweak_score(move |reader: &SegmentReader| {
+                let column = reader.fast_fields().u64(field).ok();
+                move |doc_id, score| {
+                    let value = column.as_ref().and_then(|column| column.first(doc_id));
+                    (value.unwrap_or_default(), score)
+                }
+            });
+
+            let (texts, total) = collect(searcher, query, (sorted, Count), args)?;
+            let texts = texts
+                .into_iter()
+                .map(|((_, score), address)| (score, address))
+                .collect();
+            (texts, total)
+        }
+        Sort::Path => {
+            // Paths aren't a fast field, so ordering by path means loading every match.
+
+            let all = TopDocs::with_limit(searcher.num_docs().max(1) as usize).order_by_score();
+            let (texts, total) = collect(searcher, query, (all, Count), args)?;
+            let mut texts: Vec<_> = texts
+                .into_iter()
+                .filter_map(|(score, address)| {
+                    let doc: TantivyDocument = searcher.doc(address).ok()?;
+                    Some((score, address, get_path(&doc, fields.path)?.to_owned()))
+                })
+                .collect();
+            texts.sort_by(|a, b| a.2.cmp(&b.2));
+
+            let texts = texts
+                .into_iter()
+                .skip(skip)
+                .take(take)
+                .map(|(score, address, _)| (score, address))
+                .collect();
+            (texts, total)
+        }
+    };
+
+    Ok((texts, total))
+}
+
+/// Returns true if any document in the index is a chunk of a longer one (see --chunk).
+fn is_chunked(searcher: &Searcher) -> bool {
+    searcher.segment_readers().iter().any(|reader| {
+        reader
+            .fast_fields()
+            .u64("chunk")
+            .is_ok_and(|column| column.max_value() > 0)
+    })
+}
+
+/// Counts the documents matching a query (see --count). Only chunked libraries need to look at
+/// the matches, since a document may match in more than one of its chunks, along with searches
+/// filtered by owner or mode, which are stored rather than indexed.
+fn count_matches(
+    searcher: &Searcher,
+    query: &dyn Query,
+    fields: &SearchFields,
+    args: &Args,
+) -> tantivy::Result<usize> {
+    if is_chunked(searcher) || args.filters_metadata() {
+        count_documents(searcher, query, fields, args)
+    } else {
+        collect(searcher, query, Count, args)
+    }
+}
+
+/// Counts the documents matching a query in a chunked library, where each may match in more
+/// than one chunk. Paths aren't a fast field, so this means loading every match.
+fn count_documents(
+    searcher: &Searcher,
+    query: &dyn Query,
+    fields: &SearchFields,
+    args: &Args,
+) -> tantivy::Result<usize> {
+    let mut paths = HashSet::new();
+    for address in collect(searcher, query, DocSetCollector, args)? {
+        let doc: TantivyDocument = searcher.doc(address)?;
+        if !args.matches_metadata(&doc, fields) {
+            continue;
+        }
+        if let Some(path) = get_path(&doc, fields.path) {
+            paths.insert(path.to_owned());
+        }
+    }
+    Ok(paths.len())
+}
+
+/// Finds the best matching chunk of each hit, for libraries built with --chunk, so that its
+/// snippet can be taken from the part of the document that matched.
+fn find_chunks(
+    searcher: &Searcher,
+    query: &dyn Query,
+    fields: &SearchFields,
+    hits: &[(Score, &str)],
+) -> tantivy::Result<Vec<Option<Range<usize>>>> {
+    if !is_chunked(searcher) {
+        return Ok(vec![None; hits.len()]);
+    }
+
+    hits.iter()
+        .map(|&(_, path)| {
+            let query = BooleanQuery::new(vec![
+                (Occur::Must, query.box_clone()),
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(fields.path, path),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ]);
+            let top = searcher.search(&query, &TopDocs::with_limit(1).order_by_score())?;
+            let Some(&(_, address)) = top.first() else {
+                return Ok(None);
+            };
+
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let offset = |field| Some(doc.get_first(field)?.as_u64()? as usize);
+            Ok(offset(fields.chunk)
+                .zip(offset(fields.chunk_end))
+                .map(|(start, end)| start..end))
+        })
+        .collect()
+}
+
+/// Returns true if any document in the index has a priority other than 1.
+fn has_priorities(searcher: &Searcher) -> bool {
+    searcher.segment_readers().iter().any(|reader| {
+        reader
+            .fast_fields()
+            .f64("priority")
+            .is_ok_and(|column| column.min_value() != 1.0 || column.max_value() != 1.0)
+    })
+}
+
+/// Finds the documents in a segment with the given paths, mapping each to its boost.
+///
+/// Paths are looked up in the segment's index of paths, which for an access log of limited size
+/// is much cheaper than loading each matching document to read its path.
+fn segment_boosts(
+    reader: &SegmentReader,
+    path: Field,
+    boosts: &[(Term, Score)],
+) -> HashMap<DocId, Score> {
+    let mut docs = HashMap::new();
+    let Ok(index) = reader.inverted_index(path) else {
+        return docs;
+    };
+
+    for (term, boost) in boosts {
+        if let Ok(Some(mut postings)) = index.read_postings(term, IndexRecordOption::Basic) {
+            while postings.doc() != TERMINATED {
+                docs.insert(postings.doc(), *boost);
+                postings.advance();
+            }
+        }
+    }
+
+    docs
+}
+
+/// Runs a search, dropping documents outside the size range given on the command line.
+///
+/// Sizes are read from a fast field, so filtering doesn't require loading stored documents.
+fn collect<C>(
+    searcher: &Searcher,
+    query: &dyn Query,
+    collector: C,
+    args: &Args,
+) -> tantivy::Result<C::Fruit>
+where
+    C: Collector + Send + Sync,
+{
+    match args.size_range() {
+        Some((min, max)) => {
+            let filter = move |size: u64| size > min && size < max;
+            searcher.search(
+                query,
+                &FilterCollector::new("size".into(), filter, collector),
+            )
+        }
+        None => searcher.search(query, &collector),
+    }
+}
+
+/// Finds the best match for each word of the query (see --one-per-term).
+///
+/// The words are those of the query as parsed, so a phrase contributes each of its words, and
+/// each word is looked for in every default field. A prefix (`config*`) counts as a word, and
+/// finds the best match for any word it starts. The best match for a word must still match the
+/// query as a whole, with its phrases, required and excluded words and filters, so a word that's
+/// only excluded finds nothing.
+fn best_per_term(
+    searcher: &Searcher,
+    parser: &SearchParser,
+    query: &dyn Query,
+    fields: &SearchFields,
+    args: &Args,
+) -> anyhow::Result<Vec<(Score, TantivyDocument)>> {
+    let query_string = args.query_string();
+    let parsed = parser.parse(&query_string)?;
+    let mut terms_by_word: Vec<(String, Vec<Term>)> = Vec::new();
+    parsed.query_terms(&mut |term, _| {
+        let value = term.value();
+        let Some(word) = value.as_str() else {
+            return;
+        };
+        match terms_by_word.iter_mut().find(|(known, _)| known == word) {
+            Some((_, terms)) if terms.contains(term) => {}
+            Some((_, terms)) => terms.push(term.clone()),
+            None => terms_by_word.push((word.to_owned(), vec![term.clone()])),
+        }
+    });
+
+    let mut words: Vec<Box<dyn Query>> = terms_by_word
+        .into_iter()
+        .map(|(_, terms)| -> Box<dyn Query> {
+            let terms = terms
+                .into_iter()
+                .map(|term| -> (Occur, Box<dyn Query>) {
+                    let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+                    (Occur::Should, Box::new(query))
+                })
+                .collect();
+            Box::new(BooleanQuery::new(terms))
+        })
+        .collect();
+
+    // Prefixes are matched with patterns, which have no terms to find, so each is parsed again
+    // on its own.
+    for prefix in find_prefixes(&query_string)? {
+        if !query_string[..prefix.range.start].ends_with('-') {
+            words.push(parser.parse(&query_string[prefix.range])?);
+        }
+    }
+
+    // Documents with the wrong owner or mode are dropped as they're found, as in `search`, so
+    // every match of a word may need to be looked at to find its best.
+
+    let limit = if args.filters_metadata() {
+        (searcher.num_docs() as usize).max(1)
+    } else {
+        1
+    };
+    let path = fields.path;
+    let mut docs: Vec<(Score, TantivyDocument)> = Vec::new();
+
+    for word in words {
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, word),
+            (
+                Occur::Must,
+                Box::new(ConstScoreQuery::new(query.box_clone(), 0.0)),
+            ),
+        ]);
+        let best = searcher.search(&query, &TopDocs::with_limit(limit).order_by_score())?;
+        let best = best.into_iter().find_map(|(score, doc_id)| {
+            let doc: TantivyDocument = searcher.doc(doc_id).ok()?;
+            args.matches_metadata(&doc, fields).then_some((score, doc))
+        });
+
+        if let Some(best) = best {
+            let is_duplicate = docs
+                .iter()
+                .any(|(_, doc)| get_path(doc, path) == get_path(&best.1, path));
+            if !is_duplicate {
+                docs.push(best);
+            }
+        }
+    }
+
+    Ok(docs)
+}
+
+fn get_path(doc: &TantivyDocument, path: Field) -> Option<&str> {
+    doc.get_first(path)?.as_str()
+}
+
+fn dispatch(command: &Command) -> anyhow::Result<()> {
+    match command {
+        Command::CreateIndex(args) => build_index(args),
+        Command::ListIndexes => list_indexes(),
+        Command::Update(args) => update_index(args),
+        Command::Merge(args) => merge_libraries(args),
+        Command::DumpSchema(args) => dump_schema(args),
+        Command::Stats(args) => stats(args),
+        Command::Relocate(args) => relocate_library(args),
+        Command::Rename(args) => rename_library(args),
+        Command::Doctor(args) => doctor(args),
+        Command::Verify(args) => verify(args),
+        Command::Sync(args) => sync_index(args),
+        Command::Find(args) => find(args),
+        Command::PruneAccess(args) => prune_access(args),
+        Command::Evict(args) => evict(args),
+        Command::QueryLog(args) => query_log(args),
+        Command::Daemon(args) => run_daemon(args),
+        Command::Ask(args) => ask(args),
+        Command::Repl(args) => repl(args),
+        Command::Group(args) => group(args),
+        Command::RemoveIndex(args) => remove_library(args),
+        Command::Current => current_library(),
+        Command::Pack(args) => pack_library(args),
+        Command::Unpack(args) => unpack_library(args),
+        Command::Completions(args) => print_completions(args),
+    }
+}
+
+/// Searches a library interactively, reusing one searcher for every keystroke.
+fn find(args: &FindCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.name.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+
+    prepare_library(&storage_path, &libraries, name)?;
+
+    let data_path = storage_path.join(name);
+    let (_schema, fields) = build_schema();
+    let index = open_index(&data_path)?;
+    let reader = index.reader()?;
+
+    let mut parser = build_parser(&index, &fields, args.profile.as_deref(), &[])?;
+    if IndexConfig::from_path(&data_path)?.default_and {
+        parser.set_conjunction_by_default();
+    }
+
+    let mut source = FindSource {
+        searcher: reader.searcher(),
+        parser,
+        fields: &fields,
+        limit: args.limit,
+        snippets: None,
+        preview_command: args
+            .preview_command
+            .clone()
+            .map(|command| PreviewCommand::new(command, Duration::from_secs(args.preview_timeout))),
+    };
+
+    if let Some(path) = finder::run(&mut source)? {
+        let opened = Opener::new(OpenOptions::default()).open_all([path.as_str()])?;
+        record_access(&data_path, opened)?;
+    }
+
+    Ok(())
+}
+
+struct FindSource<'a> {
+    searcher: Searcher,
+    parser: SearchParser,
+    fields: &'a SearchFields,
+    limit: usize,
+    snippets: Option<Snippets>,
+    preview_command: Option<PreviewCommand>,
+}
+
+impl finder::Source for FindSource<'_> {
+    fn search(&mut self, query: &str) -> Vec<String> {
+        // Queries are searched as they're typed, so most of them are unfinished and plenty won't
+        // parse (an unclosed quote, say). Those just match nothing until they're fixed.
+
+        let Ok(query) = self.parser.parse(query) else {
+            self.snippets = None;
+            return Vec::new();
+        };
+
+        self.snippets = Snippets::create(&self.searcher, &*query, self.fields.text, 60, 60, 3).ok();
+
+        let top = TopDocs::with_limit(self.limit).order_by_score();
+        let docs = self.searcher.search(&query, &top).unwrap_or_default();
+        docs.into_iter()
+            .filter_map(|(_, address)| {
+                let doc: TantivyDocument = self.searcher.doc(address).ok()?;
+                get_path(&doc, self.fields.path).map(ToOwned::to_owned)
+            })
+            .collect()
+    }
+
+    fn preview(&mut self, path: &str) -> Vec<String> {
+        if let Some(command) = &self.preview_command {
+            return command
+                .run(path)
+                .unwrap_or_else(|e| vec![format!("(unable to run preview command: {e})")]);
+        }
+
+        let Some(snippets) = &self.snippets else {
+            return Vec::new();
+        };
+
+        let text = read_text(Path::new(path)).unwrap_or_default();
+        snippets
+            .fragments(&text)
+            .iter()
+            .map(|fragment| fragment.render("", "", ToOwned::to_owned))
+            .collect()
+    }
+}
+
+fn prune_access(args: &PruneAccessCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.name.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+
+    let cutoff = args
+        .older_than
+        .map(|days| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60));
+
+    let mut log = AccessLog::open(&storage_path.join(name))?;
+    let count = log.prune(cutoff);
+    log.save()?;
+
+    println!("{count} forgotten");
+    Ok(())
+}
+
+fn run_daemon(args: &DaemonCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let names: Vec<_> = if args.libraries.is_empty() {
+        libraries
+            .names()
+            .into_iter()
+            .filter(|&name| !libraries.evicted.contains(name))
+            .map(ToOwned::to_owned)
+            .collect()
+    } else {
+        args.libraries.clone()
+    };
+
+    let mut loaded = HashMap::new();
+    for name in names {
+        let engine = SearchEngine::open(&name)?;
+        eprintln!("loaded {name}");
+        loaded.insert(name, engine);
+    }
+
+    let listener = daemon::Listener::bind(&args.address)?;
+    eprintln!("listening on {}", args.address);
+
+    daemon::serve(listener, |request| {
+        let library = loaded
+            .get(&request.library)
+            .ok_or_else(|| format!("library {:?} isn't loaded", request.library))?;
+
+        let (found, total) = library
+            .search_with_total(&request.query, 0, request.limit)
+            .map_err(|e| e.to_string())?;
+
+        let paths: Vec<_> = found
+            .iter()
+            .map(|result| result.path.to_string_lossy())
+            .collect();
+        let hits: Vec<_> = found
+            .iter()
+            .zip(&paths)
+            .map(|(result, path)| (result.score, path.as_ref()))
+            .collect();
+        let titles: Vec<_> = found.iter().map(|result| result.title.clone()).collect();
+
+        let envelope = json::Envelope::new(&request.query, total, &hits, &titles);
+        serde_json::to_string(&envelope).map_err(|e| e.to_string())
+    })?;
+
+    Ok(())
+}
+
+fn ask(args: &AskCmd) -> anyhow::Result<()> {
+    let library = match &args.index {
+        Some(name) => name.clone(),
+        None => {
+            let libraries = Libraries::from_path(&get_storage_path()?)?;
+            libraries.get_index_name(&env::current_dir()?)?.to_owned()
+        }
+    };
+
+    let request = daemon::Request {
+        library,
+        query: args.query.join(" "),
+        limit: args.take,
+    };
+    let response = daemon::ask(&args.address, &request)?;
+
+    if let Some(error) = response.get("error").and_then(|error| error.as_str()) {
+        return Err(io::Error::other(error.to_owned()).into());
+    }
+
+    if args.json {
+        println!("{response}");
+        return Ok(());
+    }
+
+    let results = response["results"].as_array().into_iter().flatten();
+    for path in results.filter_map(|result| result["path"].as_str()) {
+        println!("{path}");
+    }
+
+    Ok(())
+}
+
+fn repl(args: &ReplCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.name.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+    prepare_library(&storage_path, &libraries, name)?;
+
+    let data_path = storage_path.join(name);
+    let (_schema, fields) = build_schema();
+    let index = open_index(&data_path)?;
+    let searcher = index.reader()?.searcher();
+
+    let mut parser = build_parser(&index, &fields, None, &[])?;
+    if IndexConfig::from_path(&data_path)?.default_and {
+        parser.set_conjunction_by_default();
+    }
+
+    // Each search remembers everything it matched, not just the page shown, so that `:refine`
+    // can narrow the whole result set.
+
+    let mut previous: Option<ResultSet> = None;
+    let mut stdout = io::stdout();
+    let mut lines = io::stdin().lock().lines();
+
+    loop {
+        print!("> ");
+        stdout.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            println!();
+            return Ok(());
+        };
+
+        let line = line.trim();
+        let (text, refine) = match line.split_once(char::is_whitespace) {
+            Some((":refine", terms)) => (terms.trim(), true),
+            _ if line == ":quit" || line == ":q" => return Ok(()),
+            _ if line.starts_with(':') => {
+                eprintln!("unknown directive {line:?}; try :refine <terms> or :quit");
+                continue;
+            }
+            _ => (line, false),
+        };
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let query = match parser.parse(text) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            }
+        };
+
+        let query: Box<dyn Query> = match (&previous, refine) {
+            (Some(previous), true) => {
+                let within = ConstScoreQuery::new(Box::new(previous.clone()), 0.0);
+                Box::new(BooleanQuery::new(vec![
+                    (Occur::Must, query),
+                    (Occur::Must, Box::new(within)),
+                ]))
+            }
+            (None, true) => {
+                eprintln!("nothing to refine; search first");
+                continue;
+            }
+            (_, false) => query,
+        };
+
+        let top = TopDocs::with_limit(args.take).order_by_score();
+        let docs = searcher.search(&query, &top)?;
+        let found = ResultSet::collect(&searcher, &*query)?;
+
+        for (_, address) in docs {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            if let Some(path) = get_path(&doc, fields.path) {
+                println!("{path}");
+            }
+        }
+        eprintln!("{} found", found.len());
+
+        previous = Some(found);
+    }
+}
+
+fn query_log(args: &QueryLogCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let log = QueryLog::new(&storage_path);
+
+    if args.enable || args.disable {
+        let mut libraries = Libraries::from_path(&storage_path)?;
+        libraries.query_log = args.enable;
+        libraries.save(&storage_path)?;
+    }
+
+    if args.clear {
+        log.clear()?;
+    }
+
+    if !(args.enable || args.disable || args.clear) {
+        print!("{}", log.read()?);
+    }
+
+    Ok(())
+}
+
+fn evict(args: &EvictCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let mut libraries = Libraries::from_path(&storage_path)?;
+
+    if args.no_limit {
+        libraries.max_total_size = None;
+        libraries.save(&storage_path)?;
+        return Ok(());
+    }
+
+    if args.max_total_size.is_some() {
+        libraries.max_total_size = args.max_total_size;
+        libraries.save(&storage_path)?;
+    } else if libraries.max_total_size.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no size limit is set; pass --max-total-size to set one",
+        )
+        .into());
+    }
+
+    enforce_size_limit(&storage_path, None)
+}
+
+/// Evicts the indexes of the libraries searched least recently until all indexes together fit
+/// within the size limit, if one is set. The library named by `current`, which has presumably
+/// just been built or searched, is never evicted.
+fn enforce_size_limit(storage_path: &Path, current: Option<&str>) -> anyhow::Result<()> {
+    let mut libraries = Libraries::from_path(storage_path)?;
+    let Some(limit) = libraries.max_total_size else {
+        return Ok(());
+    };
+
+    let mut sizes: Vec<_> = libraries
+        .names()
+        .into_iter()
+        .filter(|&name| !libraries.evicted.contains(name))
+        .map(|name| (name.to_owned(), dir_size(&storage_path.join(name))))
+        .filter(|&(_, size)| size > 0)
+        .collect();
+
+    let mut total: u64 = sizes.iter().map(|(_, size)| size).sum();
+    if total <= limit {
+        return Ok(());
+    }
+
+    // Libraries that have never been searched are judged by when they were built.
+
+    sizes.sort_by_key(|(name, _)| {
+        let data_path = storage_path.join(name);
+        last_searched(&data_path).or_else(|| last_built(storage_path, name))
+    });
+
+    for (name, size) in sizes {
+        if total <= limit {
+            break;
+        }
+
+        if Some(name.as_str()) != current {
+            remove_index(&storage_path.join(&name))?;
+            println!("evicted {name} ({size} bytes)");
+            total -= size;
+            libraries.evicted.insert(name);
+        }
+    }
+
+    libraries.save(storage_path)?;
+
+    if total > limit {
+        eprintln!("warning: indexes take up {total} bytes, more than the limit of {limit}");
+    }
+
+    Ok(())
+}
+
+/// Removes an evicted library's index, keeping the files needed to rebuild it the same way.
+fn remove_index(data_path: &Path) -> io::Result<()> {
+    static KEEP: &[&str] = &["config.json", "access.json"];
+
+    for entry in fs::read_dir(data_path)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| KEEP.iter().any(|keep| name == *keep))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// Gets a library ready to search, rebuilding its index if it was evicted, and records that it
+/// was searched.
+fn prepare_library(storage_path: &Path, libraries: &Libraries, name: &str) -> anyhow::Result<()> {
+    if libraries.evicted.contains(name) {
+        let roots = libraries.get_roots(name);
+        if roots.is_empty() {
+            return Err(not_registered(name).into());
+        }
+        eprintln!("rebuilding library {name:?}, which was evicted to save space");
+
+        build_index(&UpdateCmd {
+            name,
+            roots: roots.into_iter().map(ToOwned::to_owned).collect(),
+            writer: &WriterOptions::default(),
+            config: IndexConfig::from_path(&storage_path.join(name))?,
+        })?;
+    }
+
+    // A library that can't be written to can still be searched; it just can't say when.
+    let _ = touch_searched(&storage_path.join(name));
+    Ok(())
+}
+
+/// Records that a library has just been searched, for the purposes of eviction, as the modified
+/// time of a stamp file alongside its index. Searching leaves the registry alone, so that
+/// searches can run side by side without overwriting each other's changes to it.
+fn touch_searched(data_path: &Path) -> io::Result<()> {
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(data_path.join(SEARCHED_STAMP))?
+        .set_modified(SystemTime::now())
+}
+
+fn last_searched(data_path: &Path) -> Option<SystemTime> {
+    fs::metadata(data_path.join(SEARCHED_STAMP))
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+fn update_index(args: &UpdateArgs) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = libraries.get_index_name(&env::current_dir()?)?;
+    let data_path = storage_path.join(name);
+    let cmd = UpdateCmd {
+        roots: libraries
+            .get_roots(name)
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect(),
+        name,
+        writer: &args.writer,
+        config: IndexConfig::from_path(&data_path)?,
+    };
+
+    // An incremental update is only possible if we know what the index looked like when it was
+    // last written and if we're able to delete documents from it by path. Where the record of
+    // files is missing, the index itself holds the time and size of each file as of when it was
+    // indexed. Indexes built by older versions may have neither, in which case they're simply
+    // rebuilt.
+
+    let (_schema, fields) = build_schema();
+    let index = open_index(&data_path).ok().filter(|index| {
+        let schema = index.schema();
+        schema.get_field_entry(fields.path).is_indexed()
+    });
+
+    let state = match (&index, FileState::from_path(&data_path)?) {
+        (_, Some(state)) => Some(state),
+        (Some(index), None) => FileState::from_index(index, &fields)?,
+        (None, None) => None,
+    };
+
+    match (index, state) {
+        (Some(index), Some(state)) => update_incremental(&cmd, &index, &data_path, state)?,
+        _ => build_index(&cmd)?,
+    }
+
+    enforce_size_limit(&storage_path, Some(name))
+}
+
+fn sync_index(args: &SyncCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.name.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+    let roots: Vec<_> = libraries
+        .get_roots(name)
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect();
+    if roots.is_empty() {
+        return Err(not_registered(name).into());
+    }
+
+    let data_path = storage_path.join(name);
+    let (_schema, fields) = build_schema();
+    let index = open_index(&data_path)?;
+    if !index.schema().get_field_entry(fields.path).is_indexed() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("library {name:?} was built by an older version; run create-index --force"),
+        )
+        .into());
+    }
+
+    // First, gather the hash of every document currently in the index. Documents indexed before
+    // hashes were recorded have no hash and are treated as changed.
+
+    let searcher = index.reader()?.searcher();
+    let mut indexed: HashMap<String, Option<String>> = HashMap::new();
+    for (segment_ord, reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in reader.doc_ids_alive() {
+            let doc: TantivyDocument = searcher.doc(DocAddress::new(segment_ord as u32, doc_id))?;
+            if let Some(path) = get_path(&doc, fields.path) {
+                let hash = doc.get_first(fields.hash).and_then(|x| x.as_str());
+                indexed.insert(path.to_owned(), hash.map(ToOwned::to_owned));
+            }
+        }
+    }
+
+    let mut config = IndexConfig::from_path(&data_path)?;
+    let mut writer = open_writer(&index, &args.writer)?;
+    let mut state = FileState::default();
+    let (mut added, mut updated, mut deleted, mut unchanged, mut skipped) = (0, 0, 0, 0, 0);
+
+    // A file that can't be read or made sense of keeps whatever was indexed for it, under the
+    // hash it was indexed with, so that the next sync tries it again.
+    let keep = |state: &mut FileState, path: &Path, previous: Option<String>| {
+        if let Ok(stamp) = FileStamp::from_path(path) {
+            let stamp = FileStamp {
+                hash: previous,
+                ..stamp
+            };
+            state.files.insert(path.to_owned(), stamp);
+        }
+    };
+
+    for (root, path) in read_roots(&roots, &config) {
+        let key = format!("{}", path.display());
+
+        // A file too large to index is left out of the state, so whatever was indexed for it
+        // before is deleted along with the files that are gone.
+        let data = read_document(&path, &config);
+        let too_large = matches!(&data, Err(e) if e.get_ref().is_some_and(|e| e.is::<TooLarge>()));
+        let Some(data) = skip_unreadable(data, &path, args.strict)? else {
+            skipped += 1;
+            if !too_large {
+                if let Some(previous) = indexed.remove(&key) {
+                    keep(&mut state, &path, previous);
+                }
+            }
+            continue;
+        };
+        let hash = document_hash(&path, &data);
+
+        match indexed.remove(&key) {
+            Some(Some(previous)) if previous == hash => unchanged += 1,
+            previous => {
+                let documents = documents_from_data(&path, &data, root, &config, &fields);
+                let Some(documents) = skip_unreadable(documents, &path, args.strict)? else {
+                    skipped += 1;
+                    if let Some(previous) = previous {
+                        keep(&mut state, &path, previous);
+                    }
+                    continue;
+                };
+
+                if previous.is_some() {
+                    writer.delete_term(path_term(&fields, &path));
+                    updated += 1;
+                } else {
+                    added += 1;
+                }
+                for document in documents {
+                    writer.add_document(document)?;
+                }
+            }
+        }
+
+        state.files.insert(
+            path.clone(),
+            FileStamp {
+                hash: Some(hash),
+                ..FileStamp::from_path(&path)?
+            },
+        );
+    }
+
+    for path in indexed.keys() {
+        writer.delete_term(path_term(&fields, Path::new(path)));
+        deleted += 1;
+    }
+
+    writer.commit()?;
+    state.save(&data_path)?;
+    config.roots = roots.iter().map(|root| canonicalize_root(root)).collect();
+    config.save(&data_path)?;
+
+    println!(
+        "{added} added, {updated} updated, {deleted} deleted, {unchanged} unchanged, {skipped} \
+         skipped"
+    );
+    enforce_size_limit(&storage_path, Some(name))
+}
+
+fn update_incremental(
+    args: &impl IndexArgs,
+    index: &Index,
+    data_path: &Path,
+    state: FileState,
+) -> anyhow::Result<()> {
+    let roots = args.roots()?;
+    let mut config = args.config();
+    let (_schema, fields) = build_schema();
+    let mut writer = open_writer(index, args.writer_options())?;
+    let batch_size = args.writer_options().batch_size();
+    let mut current = FileState::default();
+    let (mut added, mut changed, mut removed, mut skipped) = (0, 0, 0, 0);
+    let mut count = 0;
+    let strict = args.strict();
+
+    for (root, path) in read_roots(&roots, &config) {
+        let previous = state.files.get(&path);
+
+        // A file that can't be read keeps whatever was indexed for it before, and the stamp it
+        // was indexed with, so that the next update tries it again.
+
+        let stamp = FileStamp::from_path(&path);
+        let Some(stamp) = skip_unreadable(stamp, &path, strict)? else {
+            skipped += 1;
+            if let Some(previous) = previous {
+                current.files.insert(path, previous.clone());
+            }
+            continue;
+        };
+
+        if let Some(previous) = previous.filter(|previous| previous.matches(&stamp)) {
+            let stamp = FileStamp {
+                hash: previous.hash.clone(),
+                ..stamp
+            };
+            current.files.insert(path, stamp);
+            continue;
+        }
+
+        let data = read_document(&path, &config);
+        let Some(data) = skip_unreadable(data, &path, strict)? else {
+            skipped += 1;
+            if let Some(previous) = previous {
+                current.files.insert(path, previous.clone());
+            }
+            continue;
+        };
+
+        // Some tools touch files without changing them. A file whose contents hash the same as
+        // when it was indexed only needs its new stamp recorded.
+
+        let stamp = FileStamp {
+            hash: Some(document_hash(&path, &data)),
+            ..stamp
+        };
+        if previous.is_some_and(|previous| previous.hash == stamp.hash) {
+            current.files.insert(path, stamp);
+            continue;
+        }
+
+        count += 1;
+        if count % batch_size == 0 {
+            writer.commit()?;
+        }
+
+        let documents = documents_from_data(&path, &data, root, &config, &fields);
+        let Some(documents) = skip_unreadable(documents, &path, strict)? else {
+            skipped += 1;
+            if let Some(previous) = previous {
+                current.files.insert(path, previous.clone());
+            }
+            continue;
+        };
+
+        if previous.is_some() {
+            writer.delete_term(path_term(&fields, &path));
+            changed += 1;
+        } else {
+            added += 1;
+        }
+        for document in documents {
+            writer.add_document(document)?;
+        }
+
+        current.files.insert(path, stamp);
+    }
+
+    for path in state.files.keys() {
+        if !current.files.contains_key(path) {
+            writer.delete_term(path_term(&fields, path));
+            removed += 1;
+        }
+    }
+
+    writer.commit()?;
+    current.save(data_path)?;
+
+    config.roots = roots.iter().map(|root| canonicalize_root(root)).collect();
+    config.save(data_path)?;
+
+    println!("{added} added, {changed} changed, {removed} removed, {skipped} skipped");
+    Ok(())
+}
+
+fn merge_libraries(args: &MergeCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+
+    if libraries.get_root(&args.source).is_none() {
+        return Err(not_registered(&args.source).into());
+    }
+
+    // The target's segments come first so that, when deduplicating by path below, documents
+    // already present in the target win over those coming from the source. Every chunk of a
+    // chunked document is kept, but only from the library it's found in first.
+
+    let mut indexes = Vec::new();
+    let created = libraries.get_roots(&args.into).is_empty();
+    let roots = match libraries.get_roots(&args.into) {
+        roots if !created => {
+            indexes.push(open_index(storage_path.join(&args.into))?);
+            roots.into_iter().map(ToOwned::to_owned).collect()
+        }
+        _ => match args.root.as_deref() {
+            Some(root) => {
+                let canonical = canonicalize_root(Path::new(root));
+                if let Some(existing) = libraries.root_owner(&canonical, &args.into) {
+                    if !args.force {
+                        return Err(root_conflict(&canonical, existing, &args.into).into());
+                    }
+                }
+                vec![PathBuf::from(root)]
+            }
+            None => {
+                let name = &args.into;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("library {name:?} does not exist; pass --root to create it"),
+                )
+                .into());
+            }
+        },
+    };
+    indexes.push(open_index(storage_path.join(&args.source))?);
+
+    if indexes
+        .iter()
+        .any(|index| index.schema() != indexes[0].schema())
+    {
+        let (source, target) = (&args.source, &args.into);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("libraries {source:?} and {target:?} have different schemas"),
+        )
+        .into());
+    }
+
+    let (_schema, fields) = build_schema();
+    let mut segments = Vec::new();
+    let mut filters = Vec::new();
+    let mut seen = HashSet::new();
+    let mut count = 0;
+
+    for index in &indexes {
+        let mut kept = HashSet::new();
+        for segment in index.searchable_segments()? {
+            let reader = SegmentReader::open(&segment)?;
+            let store = reader.get_store_reader(1)?;
+            let mut alive = BitSet::with_max_value(reader.max_doc());
+
+            for doc_id in reader.doc_ids_alive() {
+                let doc: TantivyDocument = store.get(doc_id)?;
+                let path = get_path(&doc, fields.path).map(ToOwned::to_owned);
+                let chunk = doc.get_first(fields.chunk).and_then(|x| x.as_u64());
+                if !seen.contains(&path) && kept.insert((path, chunk)) {
+                    alive.insert(doc_id);
+                    count += 1;
+                }
+            }
+
+            let mut buf = Vec::new();
+            write_alive_bitset(&alive, &mut buf)?;
+            filters.push(Some(AliveBitSet::open(OwnedBytes::new(buf))));
+            segments.push(segment);
+        }
+        seen.extend(kept.into_iter().map(|(path, _)| path));
+    }
+
+    // The merged index is written alongside the target and swapped into place only once it is
+    // complete, so a failed merge leaves the target as it was.
+
+    let data_path = storage_path.join(&args.into);
+    let merge_path = storage_path.join(format!("{}.merge", args.into));
+    if merge_path.exists() {
+        fs::remove_dir_all(&merge_path)?;
+    }
+    fs::create_dir_all(&merge_path)?;
+
+    // A new target is configured as the source was, so that updating it later indexes the same
+    // files the same way the documents it starts with were indexed.
+
+    let mut config = if created {
+        IndexConfig::from_path(&storage_path.join(&args.source))?
+    } else {
+        IndexConfig::from_path(&data_path)?
+    };
+    config.roots = roots.iter().map(|root| canonicalize_root(root)).collect();
+    let settings = indexes[0].settings().clone();
+    merge_filtered_segments(
+        &segments,
+        settings,
+        filters,
+        MmapDirectory::open(&merge_path)?,
+    )?;
+    drop(indexes);
+
+    if data_path.exists() {
+        fs::remove_dir_all(&data_path)?;
+    }
+    fs::rename(&merge_path, &data_path)?;
+    config.save(&data_path)?;
+
+    update_registry(
+        storage_path.clone(),
+        &UpdateCmd {
+            name: &args.into,
+            roots: roots.clone(),
+            writer: &WriterOptions::default(),
+            config,
+        },
+        &roots,
+    )?;
+
+    println!("{count} documents in {}", args.into);
+    enforce_size_limit(&storage_path, Some(&args.into))
+}
+
+fn relocate_library(args: &RelocateCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+
+    match libraries.get_roots(&args.name).len() {
+        0 => return Err(not_registered(&args.name).into()),
+        1 => {}
+        _ => {
+            let name = &args.name;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "library {name:?} has more than one root; recreate it with create-index \
+                     --force to change them"
+                ),
+            )
+            .into());
+        }
+    }
+
+    let root = fs::canonicalize(&args.root).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("unable to resolve root {:?}: {e}", args.root),
+        )
+    })?;
+
+    if !root.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", root.display()),
+        )
+        .into());
+    }
+
+    if let Some(existing) = libraries.root_owner(&root, &args.name) {
+        if !args.force {
+            return Err(root_conflict(&root, existing, &args.name).into());
+        }
+    }
+
+    update_registry(
+        storage_path,
+        &UpdateCmd {
+            name: &args.name,
+            roots: vec![root.clone()],
+            writer: &WriterOptions::default(),
+            config: IndexConfig::default(),
+        },
+        &[root],
+    )?;
+
+    Ok(())
+}
+
+fn rename_library(args: &RenameCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let mut libraries = Libraries::from_path(&storage_path)?;
+    let (old, new) = (&args.old, &args.new);
+
+    if libraries.get_root(old).is_none() {
+        return Err(not_registered(old).into());
+    }
+
+    check_library_name(new)?;
+
+    let old_path = storage_path.join(old);
+    let new_path = storage_path.join(new);
+    if libraries.get_root(new).is_some() || new_path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("library {new:?} already exists"),
+        )
+        .into());
+    }
+
+    // The index is moved with a single rename, and moved back if the registry can't be saved,
+    // so a failure leaves the library whole under one name or the other. An evicted library has
+    // no index to move.
+
+    let moved = old_path.exists();
+    if moved {
+        fs::rename(&old_path, &new_path)?;
+    }
+
+    for value in libraries.mapping.values_mut() {
+        if value == old {
+            value.clone_from(new);
+        }
+    }
+    if libraries.evicted.remove(old) {
+        libraries.evicted.insert(new.clone());
+    }
+    if let Some(groups) = libraries.groups.remove(old) {
+        libraries.groups.insert(new.clone(), groups);
+    }
+
+    if let Err(e) = libraries.save(&storage_path) {
+        if moved {
+            fs::rename(&new_path, &old_path)?;
+        }
+        return Err(e.into());
+    }
+
+    println!("renamed library {old:?} to {new:?}");
+    Ok(())
+}
+
+fn doctor(args: &DoctorCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let mut libraries = Libraries::from_path(&storage_path)?;
+
+    let mut entries: Vec<_> = libraries.mapping.into_iter().collect();
+    entries.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+
+    let mut mapping: HashMap<PathBuf, String> = HashMap::new();
+    let mut problems = 0;
+    let mut missing = 0;
+
+    // A root that can't be resolved isn't something we can repair, but it's worth mentioning
+    // because no directory will ever map to that library.
+
+    for (root, name) in entries {
+        let canonical = match fs::canonicalize(&root) {
+            Ok(canonical) => canonical,
+            Err(_) => {
+                println!("{name}: root {} cannot be resolved", root.display());
+                missing += 1;
+                root.clone()
+            }
+        };
+
+        if canonical != root {
+            println!(
+                "{name}: root {} should be {}",
+                root.display(),
+                canonical.display()
+            );
+            problems += 1;
+        }
+
+        let name = match mapping.remove(&canonical) {
+            Some(existing) => {
+                let keep =
+                    if last_built(&storage_path, &name) > last_built(&storage_path, &existing) {
+                        name.clone()
+                    } else {
+                        existing.clone()
+                    };
+
+                println!(
+                    "{}: registered to both {existing:?} and {name:?}; keeping {keep:?}",
+                    canonical.display()
+                );
+                problems += 1;
+                keep
+            }
+            None => name,
+        };
+
+        mapping.insert(canonical, name);
+    }
+
+    let mut empty = Vec::new();
+    if args.prune_empty_libraries {
+        let mut names: Vec<_> = mapping.values().cloned().collect();
+        names.sort_unstable();
+        names.dedup();
+
+        for name in names {
+            if !libraries.evicted.contains(&name) && is_empty(&storage_path.join(&name)) {
+                println!("{name}: index is empty");
+                problems += 1;
+                empty.push(name);
+            }
+        }
+    }
+
+    if problems == 0 {
+        if missing == 0 {
+            println!("no problems found");
+        }
+    } else if args.fix {
+        for name in &empty {
+            let data_path = storage_path.join(name);
+            if data_path.exists() {
+                fs::remove_dir_all(data_path)?;
+            }
+            libraries.groups.remove(name);
+            println!("pruned library {name:?}");
+        }
+        mapping.retain(|_, name| !empty.contains(name));
+
+        Libraries {
+            mapping,
+            ..libraries
+        }
+        .save(&storage_path)?;
+        println!("registry repaired");
+    } else {
+        println!("run with --fix to repair");
+    }
+
+    Ok(())
+}
+
+/// Returns true if an index holds no documents. An index that can't be opened isn't known to be
+/// empty.
+fn is_empty(data_path: &Path) -> bool {
+    let num_docs = || -> tantivy::Result<u64> {
+        let index = open_index(data_path)?;
+        Ok(index.reader()?.searcher().num_docs())
+    };
+    num_docs().is_ok_and(|n| n == 0)
+}
+
+fn verify(args: &VerifyCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.name.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+    let roots = libraries.get_roots(name);
+    if roots.is_empty() {
+        return Err(not_registered(name).into());
+    }
+
+    if libraries.evicted.contains(name) {
+        println!("library {name:?} was evicted to save space, and is rebuilt when next searched");
+        return Ok(());
+    }
+
+    let data_path = storage_path.join(name);
+    let problems = check_index(&data_path);
+    if problems.is_empty() {
+        println!("library {name:?} is healthy");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{name}: {problem}");
+    }
+
+    if !args.repair {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("library {name:?} is damaged; pass --repair to rebuild it from its roots"),
+        )
+        .into());
+    }
+
+    // Rebuilding from a root that's gone would only leave an empty library.
+
+    if let Some(root) = roots.iter().find(|root| !root.is_dir()) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "unable to repair library {name:?}: its root {} is missing; recreate it with \
+                 create-index --force",
+                root.display()
+            ),
+        )
+        .into());
+    }
+
+    // The settings are needed to rebuild the library as it was. If they've been lost too, the
+    // defaults will have to do.
+
+    let config = IndexConfig::from_path(&data_path).unwrap_or_else(|e| {
+        eprintln!("warning: rebuilding with the default settings: {e}");
+        IndexConfig::default()
+    });
+    let roots: Vec<_> = roots.into_iter().map(ToOwned::to_owned).collect();
+    eprintln!("rebuilding library {name:?} from {}", display_roots(&roots));
+
+    let rebuilt = build_index(&UpdateCmd {
+        name,
+        roots,
+        writer: &WriterOptions::default(),
+        config,
+    });
+    match rebuilt {
+        Ok(()) if check_index(&data_path).is_empty() => {
+            println!("repaired library {name:?}");
+            Ok(())
+        }
+        result => {
+            let reason = match result {
+                Ok(()) => String::from("the rebuilt index is damaged too"),
+                Err(e) => e.to_string(),
+            };
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unable to repair library {name:?} ({reason}); recreate it with create-index \
+                     --force"
+                ),
+            )
+            .into())
+        }
+    }
+}
+
+/// Looks for damage to an index, returning a description of each problem found. An index with
+/// no problems opens, its files are all present with intact checksums, and every segment's terms
+/// and stored documents can be read.
+fn check_index(data_path: &Path) -> Vec<String> {
+    let index = match open_index(data_path) {
+        Ok(index) => index,
+        Err(e) => return vec![format!("index can't be opened: {e}")],
+    };
+
+    let mut problems = Vec::new();
+    let metas = match index.searchable_segment_metas() {
+        Ok(metas) => metas,
+        Err(e) => return vec![format!("segments can't be listed: {e}")],
+    };
+    for meta in &metas {
+        // Every segment has a name for its deletes, but only those with deletes have the file.
+        let components = SegmentComponent::iterator()
+            .filter(|&&component| component != SegmentComponent::Delete || meta.has_deletes());
+        for &component in components {
+            let file = meta.relative_path(component);
+            if !data_path.join(&file).exists() {
+                problems.push(format!("missing file {}", file.display()));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    match index.validate_checksum() {
+        Ok(damaged) => {
+            let mut damaged: Vec<_> = damaged.into_iter().collect();
+            damaged.sort_unstable();
+            problems.extend(
+                damaged
+                    .into_iter()
+                    .map(|file| format!("damaged file {}", file.display())),
+            );
+        }
+        Err(e) => problems.push(format!("checksums can't be checked: {e}")),
+    }
+
+    // Checksums don't catch everything, so an index whose files look intact is read through as
+    // a search would read it.
+
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    let searcher = match index.reader() {
+        Ok(reader) => reader.searcher(),
+        Err(e) => return vec![format!("index can't be read: {e}")],
+    };
+    let schema = index.schema();
+    for reader in searcher.segment_readers() {
+        let segment = reader.segment_id().short_uuid_string();
+        for (field, _) in schema.fields().filter(|(_, entry)| entry.is_indexed()) {
+            if let Err(e) = reader.inverted_index(field) {
+                let field = schema.get_field_name(field);
+                problems.push(format!(
+                    "segment {segment}: terms of {field} can't be read: {e}"
+                ));
+            }
+        }
+
+        let read = reader
+            .get_store_reader(0)
+            .map_err(tantivy::TantivyError::from);
+        let damaged = read.and_then(|store| {
+            store
+                .iter::<TantivyDocument>(reader.alive_bitset())
+                .try_for_each(|doc| doc.map(drop))
+        });
+        if let Err(e) = damaged {
+            problems.push(format!("segment {segment}: documents can't be read: {e}"));
+        }
+    }
+
+    problems
+}
+
+fn last_built(storage_path: &Path, name: &str) -> Option<SystemTime> {
+    fs::metadata(storage_path.join(name).join("meta.json"))
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+fn stats(args: &StatsCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = match args.name.as_deref() {
+        Some(name) => name,
+        None => libraries.get_index_name(&env::current_dir()?)?,
+    };
+    let roots = libraries.get_roots(name);
+    if roots.is_empty() {
+        return Err(not_registered(name).into());
+    }
+
+    let mut rows = vec![("library", name.to_owned())];
+    rows.extend(
+        roots
+            .iter()
+            .map(|root| ("root", root.display().to_string())),
+    );
+
+    // An evicted library has no index to report on, and looking at it shouldn't rebuild it.
+
+    let data_path = storage_path.join(name);
+    if libraries.evicted.contains(name) {
+        rows.push(("index", String::from("evicted")));
+    } else {
+        let searcher = open_index(&data_path)?.reader()?.searcher();
+        let deleted: u32 = searcher
+            .segment_readers()
+            .iter()
+            .map(SegmentReader::num_deleted_docs)
+            .sum();
+        let size = dir_size(&data_path);
+
+        rows.push(("documents", searcher.num_docs().to_string()));
+        rows.push(("deleted", deleted.to_string()));
+        rows.push(("segments", searcher.segment_readers().len().to_string()));
+        rows.push(("size", format!("{} ({size} bytes)", format_size(size))));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or_default();
+    for (label, value) in rows {
+        println!("{label:width$}  {value}");
+    }
+
+    Ok(())
+}
+
+/// Formats a size in bytes for people, in powers of 1024.
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn dump_schema(args: &DumpSchemaCmd) -> anyhow::Result<()> {
+    let path = match &args.index_path {
+        Some(path) => path.clone(),
+        None => {
+            let storage_path = get_storage_path()?;
+            let libraries = Libraries::from_path(&storage_path)?;
+            let name = match args.name.as_deref() {
+                Some(name) => name,
+                None => libraries.get_index_name(&env::current_dir()?)?,
+            };
+            storage_path.join(name)
+        }
+    };
+
+    let schema = open_index(path)?.schema();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        return Ok(());
+    }
+
+    for (_, entry) in schema.fields() {
+        let mut options = vec![entry.field_type().value_type().name()];
+        if entry.is_indexed() {
+            options.push("indexed");
+        }
+        if entry.is_stored() {
+            options.push("stored");
+        }
+        if entry.is_fast() {
+            options.push("fast");
+        }
+
+        println!("{}\n  {}", entry.name(), options.join(", "));
+
+        if let FieldType::Str(text) = entry.field_type() {
+            if let Some(indexing) = text.get_indexing_options() {
+                println!("  tokenizer: {}", indexing.tokenizer());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_library(args: &RemoveCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let mut libraries = Libraries::from_path(&storage_path)?;
+    let name = &args.name;
+
+    if libraries.get_root(name).is_none() {
+        let names = libraries.names();
+        let available = if names.is_empty() {
+            String::from("no libraries are registered")
+        } else {
+            format!("registered libraries: {}", names.join(", "))
+        };
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("library {name:?} is not registered ({available})"),
+        )
+        .into());
+    }
+
+    if !args.yes {
+        eprint!("remove library {name:?} and delete its index? [y/N] ");
+        io::stderr().flush()?;
+
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("nothing removed");
+            return Ok(());
+        }
+    }
+
+    let data_path = storage_path.join(name);
+    if data_path.exists() {
+        fs::remove_dir_all(data_path)?;
+    }
+
+    libraries.mapping.retain(|_, value| value != name);
+    libraries.evicted.remove(name);
+    libraries.groups.remove(name);
+    libraries.save(&storage_path)?;
+
+    println!("removed library {name:?}");
+    Ok(())
+}
+
+fn pack_library(args: &PackCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    let name = &args.name;
+    let roots: Vec<_> = libraries
+        .get_roots(name)
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect();
+    if roots.is_empty() {
+        return Err(not_registered(name).into());
+    }
+    prepare_library(&storage_path, &libraries, name)?;
+
+    let data_path = storage_path.join(name);
+    let manifest = PackManifest {
+        name: name.clone(),
+        roots,
+        config: IndexConfig::from_path(&data_path)?,
+        schema: open_index(&data_path)?.schema(),
+    };
+
+    let archive = match &args.output {
+        Some(path) => path.clone(),
+        None => PathBuf::from(format!("{name}.searchpack")),
+    };
+    let count = pack::write(&archive, &manifest, &data_path)?;
+
+    println!("packed {count} files into {}", archive.display());
+    Ok(())
+}
+
+fn unpack_library(args: &UnpackCmd) -> anyhow::Result<()> {
+    // The name may come from the archive, which is no more to be trusted than its contents, and
+    // is checked before anything is written under it.
+
+    let manifest: PackManifest = pack::read_manifest(&args.archive)?;
+    let name = args.name.as_deref().unwrap_or(&manifest.name);
+    check_library_name(name)?;
+    let roots = if args.root.is_empty() {
+        manifest.roots.clone()
+    } else {
+        args.root.clone()
+    };
+
+    // Indexes built by older versions lack only the fields added since, which searches allow
+    // for, and may tokenize their text as tantivy does by default. An index with fields this
+    // version doesn't know, or with fields that differ otherwise, can't be searched reliably.
+
+    let stem = manifest.config.stem;
+    let schemas = [
+        build_schema_stemmed(stem).0,
+        build_schema_tokenized(stem, LEGACY_TOKENIZERS).0,
+    ];
+    let compatible = schemas.iter().any(|schema| {
+        manifest.schema.num_fields() <= schema.num_fields()
+            && manifest
+                .schema
+                .fields()
+                .zip(schema.fields())
+                .all(|((_, packed), (_, current))| packed == current)
+    });
+    if !compatible {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the index in {} was built by an incompatible version of search",
+                args.archive.display()
+            ),
+        )
+        .into());
+    }
+
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    if !args.force {
+        if libraries.get_root(name).is_some() || storage_path.join(name).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("library {name:?} already exists; pass --force to replace it"),
+            )
+            .into());
+        }
+
+        for root in &roots {
+            let canonical = canonicalize_root(root);
+            if let Some(existing) = libraries.root_owner(&canonical, name) {
+                return Err(root_conflict(&canonical, existing, name).into());
+            }
+        }
+    }
+
+    // As with merging, the index is unpacked alongside its final location and only swapped into
+    // place once it's complete.
+
+    let data_path = storage_path.join(name);
+    let unpack_path = storage_path.join(format!("{name}.unpack"));
+    if unpack_path.exists() {
+        fs::remove_dir_all(&unpack_path)?;
+    }
+    fs::create_dir_all(&unpack_path)?;
+    if let Err(e) = pack::extract(&args.archive, &unpack_path) {
+        fs::remove_dir_all(&unpack_path)?;
+        return Err(e.into());
+    }
+
+    if data_path.exists() {
+        fs::remove_dir_all(&data_path)?;
+    }
+    fs::rename(&unpack_path, &data_path)?;
+
+    update_registry(
+        storage_path.clone(),
+        &UpdateCmd {
+            name,
+            roots: roots.clone(),
+            writer: &WriterOptions::default(),
+            config: manifest.config,
+        },
+        &roots,
+    )?;
+
+    let noun = if roots.len() == 1 { "root" } else { "roots" };
+    println!(
+        "unpacked library {name:?} with {noun} {}",
+        display_roots(&roots)
+    );
+    enforce_size_limit(&storage_path, Some(name))
+}
+
+fn group(args: &GroupCmd) -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let mut libraries = Libraries::from_path(&storage_path)?;
+    if libraries.get_root(&args.library).is_none() {
+        return Err(not_registered(&args.library).into());
+    }
+
+    let groups = libraries.groups.entry(args.library.clone()).or_default();
+    groups.extend(args.add.iter().cloned());
+    for group in &args.remove {
+        groups.remove(group);
+    }
+
+    let groups: Vec<_> = groups.iter().cloned().collect();
+    if groups.is_empty() {
+        libraries.groups.remove(&args.library);
+    }
+
+    if !args.add.is_empty() || !args.remove.is_empty() {
+        libraries.save(&storage_path)?;
+    }
+
+    if groups.is_empty() {
+        println!("{} belongs to no groups", args.library);
+    } else {
+        println!("{}: {}", args.library, groups.join(", "));
+    }
+
+    Ok(())
+}
+
+fn print_completions(args: &CompletionsCmd) -> anyhow::Result<()> {
+    let command = Args::command();
+
+    if args.complete {
+        // Completion goes on without library names if the registry can't be read; an error
+        // would only end up on the terminal mid-line.
+        let libraries = || {
+            let libraries = get_storage_path().and_then(|path| Libraries::from_path(&path));
+            libraries
+                .map(|libraries| {
+                    libraries
+                        .names()
+                        .into_iter()
+                        .map(ToOwned::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        for candidate in completions::complete(command, &args.words, libraries) {
+            println!("{candidate}");
+        }
+        return Ok(());
+    }
+
+    if let Some(shell) = args.shell {
+        let bin = command.get_name().to_owned();
+        print!("{}", completions::script(shell, &bin));
+    }
+    Ok(())
+}
+
+fn current_library() -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+    println!("{}", libraries.get_index_name(&env::current_dir()?)?);
+    Ok(())
+}
+
+fn list_indexes() -> anyhow::Result<()> {
+    let storage_path = get_storage_path()?;
+    let libraries = Libraries::from_path(&storage_path)?;
+
+    for name in libraries.names() {
+        let mut label = name.to_owned();
+        if libraries.evicted.contains(name) {
+            label += " (evicted)";
+        }
+        if let Some(groups) = libraries
+            .groups
+            .get(name)
+            .filter(|groups| !groups.is_empty())
+        {
+            let groups: Vec<_> = groups.iter().map(String::as_str).collect();
+            label += &format!(" (groups: {})", groups.join(", "));
+        }
+        println!("{label}");
+        for root in libraries.get_roots(name) {
+            println!("  {}", root.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn build_index(args: &impl IndexArgs) -> anyhow::Result<()> {
+    // To build our index is actually a two-step process. First, we actually need to register the
+    // library in our library mappings, because we need some way to know which library we are
+    // searching. Before that (so zerost, I guess) we need to actually create the index, beacuse
+    // there is no point in registering a library for an index that we failed to build to begin
+    // with.
+
+    let roots = args.roots()?;
+    let storage_path = get_storage_path()?;
+
+    if let Some(chunking) = args.config().chunk {
+        if chunking.overlap >= chunking.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the chunk overlap must be less than the chunk size",
+            )
+            .into());
+        }
+    }
+
+    // Checking for documents up front, rather than counting what was indexed, means a mistake
+    // is caught before any existing index is removed.
+
+    let config = args.config();
+    if args.require_docs() && read_roots(&roots, &config).next().is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no documents to index in {}", display_roots(&roots)),
+        )
+        .into());
+    }
+
+    // A root registered to another library is caught here too, for the same reason.
+
+    if !args.force() {
+        let libraries = Libraries::from_path(&storage_path)?;
+        for root in &roots {
+            let canonical = canonicalize_root(root);
+            if let Some(existing) = libraries.root_owner(&canonical, args.name()) {
+                return Err(root_conflict(&canonical, existing, args.name()).into());
+            }
+        }
+    }
+
+    initialize(args, &storage_path, &roots)?;
+
+    // Registration starts here. The first thing we need to concern ourselves about is whether or
+    // not a library with the given name is already registered. If so, we'll either return here
+    // or continue depending on whether or not the force flag has been set.
+
+    update_registry(storage_path.clone(), args, &roots)?;
+
+    enforce_size_limit(&storage_path, Some(args.name()))
+}
+
+fn update_registry(
+    storage_path: PathBuf,
+    args: &impl IndexArgs,
+    roots: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    let libraries = Libraries::from_path(&storage_path)?;
+
+    if libraries.mapping.values().any(|val| val == args.name()) && !args.force() {
+        let name = args.name();
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("library {name:?} is already registered"),
+        )
+        .into());
+    }
+
+    // Only one library can be found from a given directory, so registering a root that already
+    // belongs to another library hides that library from directory-based lookups.
+
+    let roots: Vec<_> = roots.iter().map(|root| canonicalize_root(root)).collect();
+    for root in &roots {
+        if let Some(existing) = libraries.root_owner(root, args.name()) {
+            if !args.force() {
+                return Err(root_conflict(root, existing, args.name()).into());
+            }
+
+            eprintln!(
+                "warning: {} was registered to library {existing:?}, which is now unregistered",
+                root.display()
+            );
+        }
+    }
+
+    let mut mapping: HashMap<_, _> = libraries
+        .mapping
+        .into_iter()
+        .filter(|(_key, value)| value != args.name())
+        .collect();
+    for root in roots {
+        mapping.insert(root, args.name().to_owned());
+    }
+
+    let mut libraries = Libraries {
+        mapping,
+        ..libraries
+    };
+    libraries.evicted.remove(args.name());
+    libraries.save(&storage_path)?;
+    Ok(())
+}
+
+/// Resolves a library root to its canonical form so that the same directory always produces the
+/// same registry key. Roots that can't be resolved (e.g. because they no longer exist) are
+/// returned as-is.
+fn canonicalize_root(root: &Path) -> PathBuf {
+    fs::canonicalize(root).unwrap_or_else(|_| root.to_owned())
+}
+
+fn display_roots(roots: &[impl AsRef<Path>]) -> String {
+    let roots: Vec<_> = roots
+        .iter()
+        .map(|root| root.as_ref().display().to_string())
+        .collect();
+    roots.join(", ")
+}
+
+/// Finds the files to index beneath each of a library's roots, along with the root each was
+/// found under. A file beneath more than one root is indexed once, under the first.
+fn read_roots<'a>(
+    roots: &'a [PathBuf],
+    config: &'a IndexConfig,
+) -> impl Iterator<Item = (&'a Path, PathBuf)> {
+    let mut seen = HashSet::new();
+    roots
+        .iter()
+        .flat_map(move |root| read_paths(root, config).map(move |path| (root.as_path(), path)))
+        .filter(move |(_, path)| seen.insert(path.clone()))
+}
+
+/// The boost applied to matches on directory names (see --index-dir-terms), which say more about
+/// what a document is about than any one word of its text.
+static PATH_TERMS_BOOST: Score = 2.0;
+
+/// The boost applied to matches on the unstemmed text (see --boost-exact), enough to lift exact
+/// matches over documents matching only a stem.
+static EXACT_BOOST: Score = 2.0;
+
+/// The writer's memory budget and the files indexed between commits, unless --memory and
+/// --batch-size say otherwise.
+static MEMORY: usize = 0xC800000; // 200 megs
+static BATCH_SIZE: usize = 20_000;
+
+/// The largest file indexed unless --max-size says otherwise.
+static DEFAULT_MAX_SIZE: u64 = 50 << 20;
+
+/// The number of results shown unless --take or config.toml says otherwise.
+static DEFAULT_TAKE: usize = 10;
+
+/// The file alongside an index whose modified time is when the library was last searched.
+static SEARCHED_STAMP: &str = "searched";
+
+fn initialize(
+    args: &impl IndexArgs,
+    storage_path: &Path,
+    roots: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    let data_path = get_data_path(args, storage_path)?;
+    let config = IndexConfig {
+        roots: roots.iter().map(|root| canonicalize_root(root)).collect(),
+        ..args.config()
+    };
+    let (schema, fields) = build_schema_stemmed(config.stem);
+    let index = Index::create_in_dir(&data_path, schema)?;
+    tokenizer::register(&index);
+
+    let mut writer = open_writer(&index, args.writer_options())?;
+    let batch_size = args.writer_options().batch_size();
+    let mut state = FileState::default();
+    let (mut count, mut skipped) = (0, 0);
+    let strict = args.strict();
+
+    // Reading and parsing documents is spread across rayon's thread pool, while this thread adds
+    // them to the index as they're ready. The channel is bounded so that a slow writer doesn't
+    // leave the whole library parsed in memory, and if adding a document fails, the dropped
+    // receiver stops the parsing too.
+
+    let paths: Vec<_> = read_roots(roots, &config).collect();
+    let mut progress = Progress::new(paths.len(), batch_size, args.quiet());
+    let (tx, rx) = mpsc::sync_channel(rayon::current_num_threads() * 4);
+
+    thread::scope(|scope| -> anyhow::Result<()> {
+        let (config, fields) = (&config, &fields);
+        scope.spawn(move || {
+            paths
+                .into_par_iter()
+                .try_for_each_with(tx, |tx, (root, path)| {
+                    let documents = read_document(&path, config).and_then(|data| {
+                        let documents = documents_from_data(&path, &data, root, config, fields)?;
+                        Ok((documents, FileStamp::with_hash(&path, &data)?))
+                    });
+                    tx.send((path, documents))
+                })
+        });
+
+        for (path, documents) in rx {
+            count += 1;
+            if count % batch_size == 0 {
+                writer.commit()?;
+            }
+
+            let Some((documents, stamp)) = skip_unreadable(documents, &path, strict)? else {
+                skipped += 1;
+                progress.advance();
+                continue;
+            };
+            for document in documents {
+                writer.add_document(document)?;
+            }
+            state.files.insert(path, stamp);
+            progress.advance();
+        }
+
+        Ok(())
+    })?;
+
+    writer.commit()?;
+    state.save(&data_path)?;
+    config.save(&data_path)?;
+
+    progress.finish(count - skipped, skipped);
+
+    Ok(())
+}
+
+/// Passes over documents that can't be read with a warning, so that one bad file doesn't stop the
+/// whole library from being indexed. When strict, only documents whose format we understand but
+/// whose contents we can't read, such as a corrupt pdf, epub or docx, and those too large to
+/// index are passed over, and any other error is returned.
+fn skip_unreadable<T>(
+    documents: io::Result<T>,
+    path: &Path,
+    strict: bool,
+) -> io::Result<Option<T>> {
+    match documents {
+        Ok(documents) => Ok(Some(documents)),
+        Err(e)
+            if !strict
+                || e.get_ref().is_some_and(|e| {
+                    e.is::<pdf::Error>()
+                        || e.is::<epub::Error>()
+                        || e.is::<docx::Error>()
+                        || e.is::<TooLarge>()
+                }) =>
+        {
+            eprintln!("warning: skipping {}: {e}", path.display());
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// A file passed over for being larger than the library's --max-size.
+#[derive(Debug)]
+struct TooLarge {
+    size: u64,
+    max: u64,
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is larger than the limit of {} (see --max-size)",
+            format_size(self.size),
+            format_size(self.max)
+        )
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// Reads a file to be indexed, unless it's too large to be worth reading.
+fn read_document(path: &Path, config: &IndexConfig) -> io::Result<Vec<u8>> {
+    let size = fs::metadata(path)?.len();
+    let max = config.max_size();
+    if size > max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            TooLarge { size, max },
+        ));
+    }
+    fs::read(path)
+}
+
+/// Builds the documents for a file: one, or one per chunk for a long file in a chunked library.
+fn documents_from_data(
+    path: &Path,
+    data: &[u8],
+    root: &Path,
+    config: &IndexConfig,
+    fields: &SearchFields,
+) -> io::Result<Vec<TantivyDocument>> {
+    let source = decode_document(path, data, &config.markdown())?;
+
+    // Rendering markdown leaves out its frontmatter, so that's read from the file as written.
+    let written = if is_markdown(path) {
+        Cow::from(encoding::decode(data, false))
+    } else {
+        Cow::from(&source)
+    };
+
+    let text = match comments::syntax_for(path).filter(|_| config.comments_only) {
+        Some(syntax) => comments::extract(&source, syntax),
+        None => extract_text(path, &source),
+    };
+    let stored_path = format!("{}", path.display());
+
+    let meta = fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    let mut document = doc! {
+        fields.path => stored_path,
+        fields.hash => document_hash(path, data),
+        fields.modified => modified,
+        fields.size => meta.len(),
+        fields.mime => mime::detect(data, &source),
+        fields.title => extract_title(path, &source),
+        fields.priority => read_priority(path, &written),
+    };
+
+    #[cfg(unix)]
+    {
+        document.add_u64(fields.owner, meta.uid().into());
+        document.add_u64(fields.mode, (meta.mode() & 0o7777).into());
+    }
+
+    if let Some(id) = read_id(path, &written) {
+        document.add_text(fields.id, id);
+    }
+
+    for tag in read_tags(path, &written) {
+        document.add_text(fields.tags, tag);
+    }
+
+    if config.dir_terms {
+        let parent = path.strip_prefix(root).ok().and_then(Path::parent);
+        for component in parent.into_iter().flat_map(Path::components) {
+            if let Component::Normal(name) = component {
+                document.add_text(fields.path_terms, name.to_string_lossy());
+            }
+        }
+    }
+
+    if is_html(path) {
+        let (_, blocks) = extract_html(&source);
+        document.add_text(fields.blocks, serde_json::to_string(&blocks)?);
+    }
+
+    let documents = chunk_ranges(&text, config.chunk)
+        .into_iter()
+        .map(|range| {
+            let mut document = document.clone();
+            document.add_text(fields.text, &text[range.clone()]);
+            if config.stem {
+                document.add_text(fields.exact, &text[range.clone()]);
+            }
+            if config.chunk.is_some() {
+                document.add_u64(fields.chunk, range.start as u64);
+                document.add_u64(fields.chunk_end, range.end as u64);
+            }
+            document
+        })
+        .collect();
+
+    Ok(documents)
+}
+
+/// Splits text into windows of whole words, each overlapping the last, returning the byte range
+/// of each. Text that isn't chunked, or is no longer than one window, is returned whole.
+fn chunk_ranges(text: &str, chunking: Option<Chunking>) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let words: Vec<_> = match chunking {
+        Some(_) => text
+            .split_whitespace()
+            .map(|word| {
+                let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                start..start + word.len()
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    match chunking {
+        Some(chunking) if words.len() > chunking.size => {
+            let step = chunking.size - chunking.overlap;
+            for start in (0..words.len()).step_by(step) {
+                let end = (start + chunking.size).min(words.len());
+                ranges.push(words[start].start..words[end - 1].end);
+                if end == words.len() {
+                    break;
+                }
+            }
+        }
+        _ => ranges.push(0..text.len()),
+    }
+
+    ranges
+}
+
+/// Hashes a file's contents along with those of its sidecars, which are as much a part of its
+/// documents. A file with no sidecars hashes as its contents alone.
+fn document_hash(path: &Path, data: &[u8]) -> String {
+    let mut hasher = SipHasher13::new();
+    hasher.write(data);
+    for &extension in SIDECARS {
+        if let Ok(sidecar) = fs::read(sidecar_path(path, extension)) {
+            hasher.write(extension.as_bytes());
+            hasher.write_usize(sidecar.len());
+            hasher.write(&sidecar);
+        }
+    }
+    format!("{:032x}", hasher.finish128().as_u128())
+}
+
+fn path_term(fields: &SearchFields, path: &Path) -> Term {
+    Term::from_field_text(fields.path, &format!("{}", path.display()))
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    Ok(extract_text(path, &read_source(path)?))
+}
+
+/// Reads a file as it was indexed, except that markdown is rendered with the default options,
+/// since the library's aren't known here.
+fn read_source(path: &Path) -> io::Result<String> {
+    decode_document(path, &fs::read(path)?, &markdown::Options::default())
+}
+
+/// Decodes a file's contents as text, extracting the text of pdfs, the chapters of epubs and the
+/// paragraphs of Word documents, and rendering markdown as html.
+fn decode_document(path: &Path, data: &[u8], markdown: &markdown::Options) -> io::Result<String> {
+    if is_markdown(path) {
+        Ok(markdown::render(&encoding::decode(data, false), markdown))
+    } else if is_pdf(path) {
+        pdf::extract(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    } else if is_epub(path) {
+        epub::extract(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    } else if is_docx(path) {
+        docx::extract(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    } else {
+        Ok(encoding::decode(data, is_html(path)))
+    }
+}
+
+/// Extracts a document's title: for html, the text of its `<title>` or else its first `<h1>`,
+/// falling back to the file stem; for anything else, the file name. Markdown, having no
+/// `<title>`, is titled by its first top-level heading.
+fn extract_title(path: &Path, source: &str) -> String {
+    let file_name = |path: &Path, stem| {
+        let name = if stem {
+            path.file_stem()
+        } else {
+            path.file_name()
+        };
+        name.map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    if !is_html(path) && !is_epub(path) && !is_docx(path) && !is_markdown(path) {
+        return file_name(path, false);
+    }
+
+    let document = Html::parse_document(source);
+    ["title", "h1"]
+        .iter()
+        .map(|selector| Selector::parse(selector).expect("valid selector"))
+        .find_map(|selector| {
+            let element = document.select(&selector).next()?;
+            let text = element.text().collect::<Vec<_>>().join(" ");
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!text.is_empty()).then_some(text)
+        })
+        .unwrap_or_else(|| file_name(path, true))
+}
+
+fn extract_text(path: &Path, source: &str) -> String {
+    if is_html(path) || is_epub(path) || is_docx(path) || is_markdown(path) {
+        extract_html(source).0
+    } else {
+        source.to_owned()
+    }
+}
+
+/// Extracts the text from an html document along with the location of each run of text within
+/// the source.
+fn extract_html(source: &str) -> (String, Vec<Block>) {
+    let fragment = Html::parse_fragment(source);
+
+    // The parser doesn't tell us where in the source each text node came from, so we find each
+    // one by searching forward from the end of the last. Text that doesn't appear verbatim in the
+    // source (because it contains entities, for instance) simply goes unmapped.
+
+    // Scripts and stylesheets are text nodes too, but nobody reads them, so they're passed over,
+    // though the search for the next text node still starts after them.
+
+    let mut buf = String::with_capacity(source.len());
+    let mut blocks = Vec::new();
+    let mut cursor = 0;
+
+    for node in fragment.root_element().descendants() {
+        let Some(s) = node.value().as_text() else {
+            continue;
+        };
+        let s = s.trim();
+
+        let hidden = node.ancestors().any(|ancestor| {
+            ancestor
+                .value()
+                .as_element()
+                .is_some_and(|element| matches!(element.name(), "script" | "style"))
+        });
+        if hidden {
+            if let Some(idx) = source[cursor..].find(s).filter(|_| !s.is_empty()) {
+                cursor += idx + s.len();
+            }
+            continue;
+        }
+
+        buf += " ";
+        if let Some(idx) = source[cursor..].find(s).filter(|_| !s.is_empty()) {
+            blocks.push(Block {
+                text: buf.len(),
+                source: cursor + idx,
+                len: s.len(),
+            });
+            cursor += idx + s.len();
+        }
+
+        buf += s;
+    }
+
+    // Captions are ordinary text nodes and are picked up above, but the alt text describing
+    // an image lives in an attribute.
+    let images = Selector::parse("img[alt]").expect("valid selector");
+    for image in fragment.select(&images) {
+        if let Some(alt) = image.value().attr("alt") {
+            buf += " ";
+            buf += alt.trim();
+        }
+    }
+
+    (buf, blocks)
+}
+
+/// A run of text in an html document, mapping its position in the extracted text to its position
+/// in the source file.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+struct Block {
+    text: usize,
+    source: usize,
+    len: usize,
+}
+
+/// Maps an offset in a document's extracted text to the corresponding offset in its source.
+fn locate(blocks: &[Block], offset: usize) -> Option<usize> {
+    blocks
+        .iter()
+        .find(|block| (block.text..block.text + block.len).contains(&offset))
+        .map(|block| block.source + offset - block.text)
+}
+
+/// The extensions of the files beside a document that add to it, when its frontmatter doesn't:
+/// its id, its priority and its tags.
+static SIDECARS: &[&str] = &["id", "priority", "tags"];
+
+fn read_id(path: &Path, source: &str) -> Option<String> {
+    // Frontmatter wins over a sidecar; we only go looking for the sidecar if the document
+    // doesn't identify itself.
+    frontmatter_value(source, "id")
+        .map(ToOwned::to_owned)
+        .or_else(|| read_sidecar(path, "id"))
+}
+
+/// Reads a document's priority, a positive multiplier for its score. Documents without one, or
+/// with one that isn't a positive number, get the neutral priority of 1.
+fn read_priority(path: &Path, source: &str) -> f64 {
+    let priority = frontmatter_value(source, "priority")
+        .map(ToOwned::to_owned)
+        .or_else(|| read_sidecar(path, "priority"))
+        .and_then(|priority| priority.parse().ok());
+
+    match priority {
+        Some(priority) if priority > 0.0 && f64::is_finite(priority) => priority,
+        _ => 1.0,
+    }
+}
+
+fn read_tags(path: &Path, source: &str) -> Vec<String> {
+    let tags = match frontmatter_list(source, "tags") {
+        Some(tags) => tags,
+        None => read_sidecar(path, "tags")
+            .map(|text| text.lines().map(ToOwned::to_owned).collect())
+            .unwrap_or_default(),
+    };
+
+    tags.into_iter()
+        .map(|tag| tag.trim().to_owned())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+fn frontmatter_value<'a>(source: &'a str, key: &str) -> Option<&'a str> {
+    let mut lines = source.lines();
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    lines
+        .take_while(|line| line.trim_end() != "---")
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim().trim_matches('"');
+            (name.trim() == key && !value.is_empty()).then_some(value)
+        })
+}
+
+/// Reads a list from frontmatter, written either inline (`tags: [a, b]` or `tags: a, b`) or as a
+/// block of `- item` lines following the key.
+fn frontmatter_list(source: &str, key: &str) -> Option<Vec<String>> {
+    let mut lines = source.lines();
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    let mut lines = lines.take_while(|line| line.trim_end() != "---");
+    let value = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == key).then(|| value.trim())
+    })?;
+
+    let unquote = |item: &str| item.trim().trim_matches('"').to_owned();
+
+    if value.is_empty() {
+        let items = lines
+            .map_while(|line| line.trim_start().strip_prefix('-'))
+            .map(unquote)
+            .collect();
+        return Some(items);
+    }
+
+    let value = value.trim_start_matches('[').trim_end_matches(']');
+    Some(value.split(',').map(unquote).collect())
+}
+
+fn read_sidecar(path: &Path, extension: &str) -> Option<String> {
+    let text = fs::read_to_string(sidecar_path(path, extension)).ok()?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// The path of a document's sidecar: the document's own path with an extension added, as in
+/// `notes.md.tags`.
+fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(extension);
+    sidecar.into()
+}
+
+fn open_writer(index: &Index, options: &WriterOptions) -> tantivy::Result<IndexWriter> {
+    // This is the same thread heuristic `Index::writer` applies, which we have to repeat here
+    // because that method doesn't let us configure anything else about the writer.
+    static MAX_THREADS: usize = 8;
+    static MIN_THREAD_MEMORY: usize = 15_000_000;
+
+    let memory = options.memory();
+    let threads = thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        .min(MAX_THREADS)
+        .min(memory / MIN_THREAD_MEMORY)
+        .max(1);
+
+    let policy = options.merge_policy();
+    let options = IndexWriterOptions::builder()
+        .num_worker_threads(threads)
+        .memory_budget_per_thread(memory / threads)
+        .maybe_num_merge_threads(options.merge_threads)
+        .build();
+
+    let writer = index.writer_with_options(options)?;
+    writer.set_merge_policy(policy);
+    Ok(writer)
+}
+
+fn read_paths(root: &Path, config: &IndexConfig) -> impl Iterator<Item = PathBuf> {
+    // This is a starter set, which config.toml may replace. We'll need more, I'm sure.
+    static EXTENSIONS: &[&str] = &[
+        "html", "htm", "txt", "pdf", "epub", "docx", "rs", "py", "js", "ts", "go", "c", "h",
+    ];
+
+    let extensions: Vec<String> = match (&config.extensions, &Config::get().extensions) {
+        (Some(extensions), _) | (None, Some(extensions)) => extensions.clone(),
+        (None, None) => EXTENSIONS.iter().map(|&ext| ext.to_owned()).collect(),
+    };
+
+    let mut walk = walkdir::WalkDir::new(root);
+    if let Some(depth) = config.max_depth {
+        walk = walk.max_depth(depth);
+    }
+
+    // Hidden entries and those ignored by a .gitignore are pruned as the walk reaches them, so
+    // nothing beneath an ignored directory is visited at all. The root itself is always walked.
+
+    let include_hidden = config.include_hidden;
+    let mut ignores = Ignores::new(root);
+    let walk = walk.into_iter().filter_entry(move |entry| {
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        entry.depth() == 0
+            || ((include_hidden || !hidden)
+                && !ignores.is_ignored(entry.path(), entry.file_type().is_dir()))
+    });
+
+    let comments_only = config.comments_only;
+    walk.filter_map(move |entry| {
+        let entry = entry.ok()?;
+        let path = entry.path();
+        let extension = path.extension()?;
+
+        let is_source = comments_only && comments::syntax_for(path).is_some();
+        if path.is_file()
+            && (is_source
+                || extensions
+                    .iter()
+                    .any(|ext| extension.eq_ignore_ascii_case(ext)))
+        {
+            Some(path.into())
+        } else {
+            None
+        }
+    })
+}
+
+/// Opens a library's index, with the tokenizers its schema names registered.
+fn open_index(path: impl AsRef<Path>) -> tantivy::Result<Index> {
+    let index = Index::open_in_dir(path)?;
+    tokenizer::register(&index);
+    Ok(index)
+}
+
+/// The tokenizers of the text in libraries built before it was tokenized as code.
+static LEGACY_TOKENIZERS: [&str; 2] = ["default", "en_stem"];
+
+fn build_schema() -> (Schema, SearchFields) {
+    build_schema_stemmed(false)
+}
+
+/// Builds the schema, stemming the text if asked. Stemming changes only how the text is
+/// tokenized, so the fields are the same either way.
+fn build_schema_stemmed(stem: bool) -> (Schema, SearchFields) {
+    build_schema_tokenized(stem, [tokenizer::CODE, tokenizer::CODE_STEM])
+}
+
+/// Builds the schema with the text tokenized by the first of `tokenizers`, or by the second if
+/// stemmed. Libraries built before the text was tokenized as code used tantivy's own
+/// tokenizers (see [`LEGACY_TOKENIZERS`]).
+fn build_schema_tokenized(stem: bool, tokenizers: [&str; 2]) -> (Schema, SearchFields) {
+    let text_options = |tokenizer| {
+        let indexing = TextFieldIndexing::default()
+            .set_tokenizer(tokenizer)
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+        TextOptions::default().set_indexing_options(indexing)
+    };
+    let [plain, stemmed] = tokenizers;
+
+    let mut builder = Schema::builder();
+    let fields = SearchFields {
+        path: builder.add_text_field("path", schema::STRING | schema::STORED),
+        // byline: builder.add_facet_field("byline", schema::INDEXED | schema::STORED),
+        text: builder.add_text_field("text", text_options(if stem { stemmed } else { plain })),
+        owner: builder.add_u64_field("owner", schema::STORED),
+        mode: builder.add_u64_field("mode", schema::STORED),
+        id: builder.add_text_field("id", schema::STRING | schema::STORED),
+        blocks: builder.add_text_field("blocks", schema::STORED),
+        hash: builder.add_text_field("hash", schema::STORED),
+        modified: builder.add_u64_field("modified", schema::FAST | schema::STORED),
+        size: builder.add_u64_field("size", schema::FAST | schema::STORED),
+        path_terms: builder.add_text_field("path_terms", schema::TEXT),
+        mime: builder.add_text_field("mime", schema::STRING | schema::STORED),
+        tags: builder.add_text_field("tags", schema::STRING | schema::STORED),
+        exact: builder.add_text_field("exact", text_options(plain)),
+        title: builder.add_text_field("title", schema::TEXT | schema::STORED),
+        priority: builder.add_f64_field("priority", schema::FAST | schema::STORED),
+        chunk: builder.add_u64_field("chunk", schema::FAST | schema::STORED),
+        chunk_end: builder.add_u64_field("chunk_end", schema::STORED),
+    };
+    (builder.build(), fields)
+}
+
+fn get_data_path(args: &impl IndexArgs, storage: &Path) -> io::Result<PathBuf> {
+    let path = storage.join(args.name());
+    let meta = path.join("meta.json");
+
+    if meta.exists() && !args.force() {
+        let name = &args.name();
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("an index already exists for library {name:?}"),
+        ));
+    }
+
+    if path.exists() {
+        fs::remove_dir_all(&path)?;
+    }
+
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+fn usage_error(message: impl fmt::Display) -> anyhow::Error {
+    Args::command()
+        .error(clap::error::ErrorKind::InvalidValue, message)
+        .into()
+}
+
+fn root_conflict(root: &Path, existing: &str, name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!(
+            "{} is already registered to library {existing:?}; pass --force to register it to \
+             {name:?} instead",
+            root.display()
+        ),
+    )
+}
+
+/// Refuses a name that can't be a library's, since a library's name is the name of its index
+/// directory and mustn't lead anywhere else.
+fn check_library_name(name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', ':']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name:?} can't be used as a library name"),
+        ));
+    }
+    Ok(())
+}
+
+fn not_registered(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("library {name:?} is not registered"),
+    )
+}
+
+fn get_storage_path() -> io::Result<PathBuf> {
+    let dirs = ProjectDirs::from("org", "Hack Commons", "Search-App")
+        .ok_or_else(|| io::Error::other("unable to initialize project directory"))?;
+
+    Ok(dirs.data_dir().into())
+}
+
+fn get_config_path() -> io::Result<PathBuf> {
+    let dirs = ProjectDirs::from("org", "Hack Commons", "Search-App")
+        .ok_or_else(|| io::Error::other("unable to initialize project directory"))?;
+
+    Ok(dirs.config_dir().into())
+}
+
+fn parse_boost(s: &str) -> Result<(String, f32), String> {
+    let (field, boost) = s
+        .split_once('=')
+        .ok_or_else(|| String::from("expected field=boost"))?;
+    let boost = boost.parse().map_err(|e| format!("invalid boost: {e}"))?;
+    Ok((field.to_owned(), boost))
+}
+
+/// Parses a size in bytes, optionally followed by a unit: K, M, G or T (powers of 1024).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let n: u64 = digits.parse().map_err(|e| format!("invalid size: {e}"))?;
+
+    let shift = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        "T" | "TB" | "TIB" => 40,
+        unit => return Err(format!("unknown unit {unit:?}; expected K, M, G or T")),
+    };
+
+    n.checked_mul(1 << shift)
+        .ok_or_else(|| String::from("size is too large"))
+}
+
+fn parse_extension(s: &str) -> Result<String, String> {
+    let extension = s.strip_prefix('.').unwrap_or(s);
+    if extension.is_empty() || extension.contains(['/', '\\', '.']) {
+        return Err(format!("{s:?} isn't a file extension"));
+    }
+    Ok(extension.to_owned())
+}
+
+fn parse_command(s: &str) -> Result<String, String> {
+    if s.trim().is_empty() {
+        return Err(String::from("the command is empty"));
+    }
+    Ok(s.to_owned())
+}
+
+fn parse_nonzero(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err(String::from("value must be at least 1")),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("{e}")),
+    }
+}
+
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+#[cfg(unix)]
+fn parse_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8).map_err(|e| format!("expected octal permissions: {e}"))
+}
+
+/// Builds a `file://` url for a path, percent-encoded so that characters such as `#`, `?`, `%` and
+/// spaces are read as part of the path.
+fn file_url(path: &str) -> String {
+    let root = if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    };
+    format!("{root}{}", percent_encode(path, b"/"))
+}
+
+/// Percent-encodes everything but unreserved characters and those in `keep`.
+///
+/// Hyphens are encoded too, because they have special meaning in text fragments.
+fn percent_encode(s: &str, keep: &[u8]) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"._~".contains(&b) || keep.contains(&b) {
+            buf.push(b as char);
+        } else {
+            buf += &format!("%{b:02X}");
+        }
+    }
+    buf
+}
+
+fn escape_html(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => buf += "&amp;",
+            '<' => buf += "&lt;",
+            '>' => buf += "&gt;",
+            '"' => buf += "&quot;",
+            '\'' => buf += "&#39;",
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+fn is_epub(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+}
+
+fn is_docx(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("docx"))
+}
+
+fn is_markdown(path: &Path) -> bool {
+    static EXTENSIONS: &[&str] = &["md", "markdown"];
+
+    path.extension()
+        .map(|a| EXTENSIONS.iter().any(|b| a.eq_ignore_ascii_case(b)))
+        .unwrap_or_default()
+}
+
+fn is_html(path: &Path) -> bool {
+    static EXTENSIONS: &[&str] = &["htm", "html"];
+
+    path.extension()
+        .map(|a| EXTENSIONS.iter().any(|b| a.eq_ignore_ascii_case(b)))
+        .unwrap_or_default()
+}
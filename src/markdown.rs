@@ -0,0 +1,609 @@
+/// What of a markdown document's markup is kept as text when it's rendered, beyond the prose.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// the contents of fenced and indented code blocks
+    pub code: bool,
+
+    /// the targets of links and images, written after the text they belong to
+    pub links: bool,
+}
+
+/// Renders a markdown document as html, so that it can be indexed like any other html: its
+/// headings, paragraphs and list items become the corresponding elements, and the markup within
+/// them (emphasis, code spans, links and images) is reduced to the text a reader would see.
+///
+/// This isn't a complete CommonMark implementation, only enough of one to separate the prose
+/// from the markup. Html written into the document is passed through as it stands, and front
+/// matter at the top of the document is left out.
+pub fn render(source: &str, options: &Options) -> String {
+    let mut lines = source.lines().peekable();
+
+    if lines.peek().is_some_and(|line| line.trim_end() == "---") {
+        lines.next();
+        for line in lines.by_ref() {
+            if matches!(line.trim_end(), "---" | "...") {
+                break;
+            }
+        }
+    }
+
+    let lines: Vec<_> = lines.collect();
+    let mut html = String::with_capacity(source.len() + source.len() / 4);
+    render_blocks(&lines, options, &mut html);
+    html
+}
+
+/// Measures the indentation of a line in bytes of ascii spaces and tabs, which are the only
+/// whitespace that indents code; other whitespace, such as the ideographic space that begins a
+/// paragraph of Japanese, is part of the text.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// The block being gathered from consecutive lines.
+enum Block {
+    Paragraph,
+    Item,
+    Html,
+}
+
+fn render_blocks(lines: &[&str], options: &Options, html: &mut String) {
+    let mut open: Option<(Block, Vec<&str>)> = None;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+        let indent = indentation(line);
+        i += 1;
+
+        if trimmed.is_empty() {
+            close(open.take(), options, html);
+            continue;
+        }
+
+        // Html blocks run to the next blank line; everything else can interrupt a paragraph or
+        // list item.
+
+        if let Some((Block::Html, lines)) = &mut open {
+            lines.push(line);
+            continue;
+        }
+
+        if let Some((fence, len)) = fence(trimmed) {
+            close(open.take(), options, html);
+            let mut code = Vec::new();
+            while let Some(&line) = lines.get(i) {
+                i += 1;
+                let closing = line.trim();
+                if closing.len() >= len && closing.chars().all(|c| c == fence) {
+                    break;
+                }
+                code.push(line);
+            }
+            render_code(&code, options, html);
+            continue;
+        }
+
+        if indent >= 4 && open.is_none() {
+            let mut code = vec![&line[4..]];
+            while let Some(&line) = lines.get(i) {
+                let trimmed = line.trim_start();
+                if !trimmed.is_empty() && indentation(line) < 4 {
+                    break;
+                }
+                code.push(line.get(4..).unwrap_or(trimmed));
+                i += 1;
+            }
+            render_code(&code, options, html);
+            continue;
+        }
+
+        // An underline turns the paragraph above it into a heading; a line of dashes on its own
+        // is a rule.
+
+        if let Some((Block::Paragraph, text)) = &open {
+            if let Some(level) = setext(trimmed) {
+                render_heading(level, &text.join("\n"), options, html);
+                open = None;
+                continue;
+            }
+        }
+
+        if is_rule(trimmed) {
+            close(open.take(), options, html);
+            html.push_str("<hr>\n");
+            continue;
+        }
+
+        if let Some((level, text)) = atx(trimmed) {
+            close(open.take(), options, html);
+            render_heading(level, text, options, html);
+            continue;
+        }
+
+        if trimmed.starts_with('>') {
+            close(open.take(), options, html);
+            let mut quoted = vec![unquote(trimmed)];
+            while let Some(line) = lines.get(i).map(|line| line.trim_start()) {
+                if !line.starts_with('>') {
+                    break;
+                }
+                quoted.push(unquote(line));
+                i += 1;
+            }
+            html.push_str("<blockquote>\n");
+            render_blocks(&quoted, options, html);
+            html.push_str("</blockquote>\n");
+            continue;
+        }
+
+        if let Some(target) = definition(trimmed) {
+            close(open.take(), options, html);
+            if options.links {
+                html.push_str(&format!("<p>{}</p>\n", escape(target)));
+            }
+            continue;
+        }
+
+        if is_delimiter_row(trimmed) {
+            continue;
+        }
+
+        if let Some(text) = item(trimmed) {
+            close(open.take(), options, html);
+            open = Some((Block::Item, vec![text]));
+            continue;
+        }
+
+        let is_tag = trimmed.starts_with('<')
+            && angle(trimmed).is_some_and(|(len, html)| html == trimmed[..len]);
+        if is_tag && open.is_none() {
+            open = Some((Block::Html, vec![line]));
+            continue;
+        }
+
+        match &mut open {
+            Some((_, lines)) => lines.push(trimmed),
+            None => open = Some((Block::Paragraph, vec![trimmed])),
+        }
+    }
+
+    close(open, options, html);
+}
+
+fn close(block: Option<(Block, Vec<&str>)>, options: &Options, html: &mut String) {
+    let Some((block, lines)) = block else {
+        return;
+    };
+    let text = lines.join("\n");
+    match block {
+        Block::Paragraph => html.push_str(&format!("<p>{}</p>\n", inline(&text, options))),
+        Block::Item => html.push_str(&format!("<li>{}</li>\n", inline(&text, options))),
+        Block::Html => {
+            html.push_str(&text);
+            html.push('\n');
+        }
+    }
+}
+
+fn render_heading(level: usize, text: &str, options: &Options, html: &mut String) {
+    html.push_str(&format!("<h{level}>{}</h{level}>\n", inline(text, options)));
+}
+
+fn render_code(lines: &[&str], options: &Options, html: &mut String) {
+    if options.code {
+        html.push_str(&format!(
+            "<pre><code>{}</code></pre>\n",
+            escape(&lines.join("\n"))
+        ));
+    }
+}
+
+/// Recognizes the opening of a fenced code block, returning the fence character and length.
+fn fence(line: &str) -> Option<(char, usize)> {
+    let fence = line.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = line.chars().take_while(|&c| c == fence).count();
+    (len >= 3 && (fence == '~' || !line[len..].contains('`'))).then_some((fence, len))
+}
+
+fn atx(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+
+    // A closing run of #s is markup too, provided it's set off from the text.
+    let rest = rest.trim();
+    let unclosed = rest.trim_end_matches('#');
+    let text = if unclosed.is_empty() || unclosed.ends_with([' ', '\t']) {
+        unclosed.trim_end()
+    } else {
+        rest
+    };
+    Some((level, text))
+}
+
+fn setext(line: &str) -> Option<usize> {
+    let line = line.trim_end();
+    if !line.is_empty() && line.chars().all(|c| c == '=') {
+        Some(1)
+    } else if !line.is_empty() && line.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&mark| marks.chars().all(|c| c == mark))
+}
+
+fn unquote(line: &str) -> &str {
+    let line = &line[1..];
+    line.strip_prefix(' ').unwrap_or(line)
+}
+
+/// Recognizes a link reference definition, `[label]: target`, returning the target.
+fn definition(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('[')?;
+    let (label, rest) = rest.split_once("]:")?;
+    if label.is_empty() || label.contains(['[', ']']) {
+        return None;
+    }
+    let target = rest.split_whitespace().next()?;
+    Some(target.trim_start_matches('<').trim_end_matches('>'))
+}
+
+/// Recognizes the row separating a table's header from its body, as in `|---|:--:|`.
+fn is_delimiter_row(line: &str) -> bool {
+    let line = line.trim_end();
+    line.contains('|')
+        && line.contains('-')
+        && line
+            .chars()
+            .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// Recognizes a list item, returning its text.
+fn item(line: &str) -> Option<&str> {
+    let marker = if line.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        let delimited = line[digits..].starts_with(['.', ')']);
+        if !(1..=9).contains(&digits) || !delimited {
+            return None;
+        }
+        digits + 1
+    };
+
+    let rest = &line[marker..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    // Task list items start with a checkbox, which isn't part of the text.
+    let rest = rest.trim_start();
+    let rest = ["[ ] ", "[x] ", "[X] "]
+        .iter()
+        .find_map(|checkbox| rest.strip_prefix(checkbox))
+        .unwrap_or(rest);
+    Some(rest)
+}
+
+/// Renders the markup within a block: code spans, links, images, emphasis, escapes and any html.
+fn inline(text: &str, options: &Options) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut prev = None;
+
+    while let Some(c) = rest.chars().next() {
+        let (consumed, rendered) = match c {
+            '\\' => match rest[1..].chars().next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => {
+                    (1 + escaped.len_utf8(), escape(&escaped.to_string()))
+                }
+                _ => (1, String::from("\\")),
+            },
+            '`' => code_span(rest).unwrap_or_else(|| {
+                let len = rest.chars().take_while(|&c| c == '`').count();
+                (len, rest[..len].to_owned())
+            }),
+            '!' => match link(&rest[1..]) {
+                Some((len, alt, target)) => {
+                    let alt = plain(alt);
+                    let mut image =
+                        format!("<img alt=\"{}\">", escape(&alt).replace('"', "&quot;"));
+                    if let Some(target) = target.filter(|_| options.links) {
+                        image += &format!(" {}", escape(target));
+                    }
+                    (1 + len, image)
+                }
+                None => (1, String::from("!")),
+            },
+            '[' => match link(rest) {
+                Some((len, label, Some(target))) => {
+                    let mut link = format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape(target).replace('"', "&quot;"),
+                        inline(label, options)
+                    );
+                    if options.links {
+                        link += &format!(" {}", escape(target));
+                    }
+                    (len, link)
+                }
+                Some((len, label, None)) => (len, inline(label, options)),
+                None => (1, String::from("[")),
+            },
+            '<' => angle(rest).unwrap_or_else(|| (1, String::from("&lt;"))),
+            '>' => (1, String::from("&gt;")),
+            '&' => {
+                let entity = rest[1..]
+                    .find(';')
+                    .filter(|&end| {
+                        let name = &rest[1..1 + end];
+                        !name.is_empty()
+                            && name.len() <= 32
+                            && name
+                                .trim_start_matches('#')
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric())
+                    })
+                    .map(|end| end + 2);
+                match entity {
+                    Some(len) => (len, rest[..len].to_owned()),
+                    None => (1, String::from("&amp;")),
+                }
+            }
+            '*' | '_' | '~' => {
+                let len = rest.chars().take_while(|&run| run == c).count();
+                let next = rest[len..].chars().next();
+                let flanking = next.is_some_and(|next| !next.is_whitespace())
+                    || prev.is_some_and(|prev: char| !prev.is_whitespace());
+                let intraword = prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric);
+                if flanking && !intraword {
+                    (len, String::new())
+                } else {
+                    (len, rest[..len].to_owned())
+                }
+            }
+            c => (c.len_utf8(), c.to_string()),
+        };
+
+        prev = rest[..consumed].chars().next_back();
+        html += &rendered;
+        rest = &rest[consumed..];
+    }
+
+    html
+}
+
+/// Renders a code span, returning the length of the markup and its html.
+fn code_span(text: &str) -> Option<(usize, String)> {
+    let len = text.chars().take_while(|&c| c == '`').count();
+    let ticks = &text[..len];
+
+    // The span ends at the next run of exactly as many backticks.
+    let mut offset = len;
+    loop {
+        let found = offset + text[offset..].find(ticks)?;
+        let run = text[found..].chars().take_while(|&c| c == '`').count();
+        if run == len {
+            let code = text[len..found].replace('\n', " ");
+            let code = match code
+                .strip_prefix(' ')
+                .and_then(|code| code.strip_suffix(' '))
+            {
+                Some(trimmed) if !trimmed.trim().is_empty() => trimmed.to_owned(),
+                _ => code,
+            };
+            return Some((found + len, format!("<code>{}</code>", escape(&code))));
+        }
+        offset = found + run;
+    }
+}
+
+/// Parses a link starting at `[`, returning the length of the markup, the link text and, for an
+/// inline link, its target. Reference links have no target here, since their definitions are
+/// rendered where they are.
+fn link(text: &str) -> Option<(usize, &str, Option<&str>)> {
+    if !text.starts_with('[') {
+        return None;
+    }
+    let text_end = closing(text, '[', ']')?;
+    let label = &text[1..text_end];
+    let rest = &text[text_end + 1..];
+
+    if rest.starts_with('(') {
+        let end = closing(rest, '(', ')')?;
+        let inside = rest[1..end].trim();
+        let target = if let Some(inside) = inside.strip_prefix('<') {
+            inside.split('>').next().unwrap_or_default()
+        } else {
+            inside.split_whitespace().next().unwrap_or_default()
+        };
+        return Some((text_end + 1 + end + 1, label, Some(target)));
+    }
+
+    if rest.starts_with('[') {
+        let end = closing(rest, '[', ']')?;
+        return Some((text_end + 1 + end + 1, label, None));
+    }
+
+    None
+}
+
+/// Finds the bracket closing the one that `text` starts with, allowing for nesting and escapes.
+fn closing(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Renders an autolink, such as `<https://example.com>`, or passes an html tag or comment
+/// through, returning the length of the markup and its html.
+fn angle(text: &str) -> Option<(usize, String)> {
+    if let Some(comment) = text.strip_prefix("<!--") {
+        let end = comment.find("-->")? + "<!---->".len();
+        return Some((end, text[..end].to_owned()));
+    }
+
+    if !text.starts_with('<') {
+        return None;
+    }
+    let end = text.find('>')?;
+    let inside = &text[1..end];
+    if inside.is_empty() || inside.contains(['<', '\n']) {
+        return None;
+    }
+
+    let scheme = inside.split_once(':').map(|(scheme, _)| scheme);
+    let is_uri = scheme.is_some_and(|scheme| {
+        scheme.len() >= 2
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    }) && !inside.contains(char::is_whitespace);
+    let is_email = !inside.contains(char::is_whitespace)
+        && inside
+            .split_once('@')
+            .is_some_and(|(user, host)| !user.is_empty() && host.contains('.'));
+
+    if is_uri || is_email {
+        let href = if is_email && !is_uri {
+            format!("mailto:{inside}")
+        } else {
+            inside.to_owned()
+        };
+        let link = format!(
+            "<a href=\"{}\">{}</a>",
+            escape(&href).replace('"', "&quot;"),
+            escape(inside)
+        );
+        return Some((end + 1, link));
+    }
+
+    let name = inside.strip_prefix('/').unwrap_or(inside);
+    let is_tag = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '/')
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    is_tag.then(|| (end + 1, text[..end + 1].to_owned()))
+}
+
+/// Reduces inline markup to its text, as for the alt text of an image.
+fn plain(text: &str) -> String {
+    let html = inline(text, &Options::default());
+    let mut plain = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_all(source: &str) -> String {
+        let options = Options {
+            code: true,
+            links: true,
+        };
+        render(source, &options)
+    }
+
+    #[test]
+    fn renders_prose_with_multibyte_characters() {
+        let html = render_all("Über den Fluss -> Brücke\n\né>\n");
+        assert!(html.contains("Über den Fluss -&gt; Brücke"), "{html}");
+        assert!(html.contains("é&gt;"), "{html}");
+    }
+
+    #[test]
+    fn ideographic_indentation_is_text() {
+        let html = render_all("\u{3000}\u{3000}日本語の段落です。\n");
+        assert!(html.contains("<p>"), "{html}");
+        assert!(html.contains("日本語の段落です。"), "{html}");
+    }
+
+    #[test]
+    fn indented_code_keeps_multibyte_lines() {
+        let html = render_all("    let café = 1;\n  \u{3000}\n    naïve()\n");
+        assert!(html.contains("let café = 1;"), "{html}");
+        assert!(html.contains("naïve()"), "{html}");
+    }
+
+    #[test]
+    fn autolinks_and_tags_still_render() {
+        let html = render_all("<https://example.com>\n\n<div>raw</div>\n");
+        assert!(html.contains("https://example.com"), "{html}");
+        assert!(html.contains("<div>raw</div>"), "{html}");
+    }
+
+    /// Renders many short documents mixing markup with multibyte characters, none of which may
+    /// panic. The generator is seeded, so a failure can be reproduced.
+    #[test]
+    fn arbitrary_input_does_not_panic() {
+        const PIECES: &[&str] = &[
+            " ", "    ", "\t", "\n", "\u{3000}", "é", "Ü", "日本", "🦀", "<", ">", "<!--", "-->",
+            "`", "```", "~~~", "*", "_", "#", "-", "+", "1.", ")", "[", "]", "(", ")", "!", "\\",
+            "&", ";", "&amp;", ":", "@", "|", "=", "---", "http:", "a", "b ",
+        ];
+
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..300_000 {
+            let len = next() % 12;
+            let source: String = (0..len)
+                .map(|_| PIECES[next() as usize % PIECES.len()])
+                .collect();
+            let result = std::panic::catch_unwind(|| render_all(&source));
+            assert!(result.is_ok(), "panicked rendering {source:?}");
+        }
+    }
+}
@@ -0,0 +1,114 @@
+/// The content type recorded for files that couldn't be classified.
+pub const UNKNOWN: &str = "unknown";
+
+static MAGIC: &[(&[u8], &str)] = &[
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+];
+
+/// The number of lines examined when classifying text.
+const SAMPLE_LINES: usize = 20;
+
+/// Detects the content type of a file from its raw bytes and its text as decoded for indexing.
+///
+/// Files are classified by their contents rather than their extensions: first by the magic
+/// numbers of common binary formats, then, for text, by looking at how the first few lines are
+/// put together. The checks are cheap and deliberately conservative; anything that can't be
+/// classified is marked `UNKNOWN`.
+pub fn detect(data: &[u8], text: &str) -> &'static str {
+    // An epub is a zip archive whose first file, stored uncompressed, names its type.
+    if data.starts_with(b"PK\x03\x04") && data.get(30..58) == Some(b"mimetypeapplication/epub+zip")
+    {
+        return "application/epub+zip";
+    }
+
+    // A Word document is a zip archive holding its text in word/document.xml, which is named in
+    // the central directory at the end whatever order the files are in.
+    if data.starts_with(b"PK\x03\x04")
+        && data
+            .windows(b"word/document.xml".len())
+            .any(|name| name == b"word/document.xml")
+    {
+        return "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+    }
+
+    if let Some(&(_, mime)) = MAGIC.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return mime;
+    }
+
+    // Text doesn't contain nul bytes, and text that had to be decoded lossily isn't text we
+    // understand.
+
+    let sample = &data[..data.len().min(8192)];
+    if sample.contains(&0) || text.contains(char::REPLACEMENT_CHARACTER) {
+        return UNKNOWN;
+    }
+
+    let trimmed = text.trim_start();
+    let head = trimmed
+        .get(..trimmed.len().min(64))
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return "text/html";
+    }
+
+    if head.starts_with("<?xml") {
+        return "application/xml";
+    }
+
+    if (head.starts_with('{') || head.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+    {
+        return "application/json";
+    }
+
+    let lines: Vec<_> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(SAMPLE_LINES)
+        .collect();
+
+    if is_csv(&lines) {
+        return "text/csv";
+    }
+
+    if is_log(&lines) {
+        return "text/x-log";
+    }
+
+    "text/plain"
+}
+
+/// Text is taken to be csv if it has at least two lines and every line has the same, nonzero
+/// number of commas.
+fn is_csv(lines: &[&str]) -> bool {
+    let commas = |line: &&str| line.matches(',').count();
+    let Some(expected) = lines.first().map(commas) else {
+        return false;
+    };
+
+    lines.len() >= 2 && expected > 0 && lines.iter().all(|line| commas(line) == expected)
+}
+
+/// Text is taken to be a log if most of its lines begin with a date (`2023-07-14`), optionally
+/// in brackets.
+fn is_log(lines: &[&str]) -> bool {
+    let is_dated = |line: &&&str| {
+        let line = line.trim_start_matches('[').as_bytes();
+        line.len() >= 10
+            && line[..4].iter().all(u8::is_ascii_digit)
+            && line[4] == b'-'
+            && line[5..7].iter().all(u8::is_ascii_digit)
+            && line[7] == b'-'
+            && line[8..10].iter().all(u8::is_ascii_digit)
+    };
+
+    lines.len() >= 2 && lines.iter().filter(is_dated).count() * 4 >= lines.len() * 3
+}
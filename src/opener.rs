@@ -0,0 +1,416 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+/// Controls how an `Opener` launches files.
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    /// skip files that have already been opened
+    pub new_only: bool,
+
+    /// wait for each application to exit before opening the next file
+    pub wait: bool,
+
+    /// how long to pause between launching applications
+    pub delay: Duration,
+
+    /// applications to open files with, by extension
+    pub profile: OpenProfile,
+
+    /// a command to open every file with, given the path as its last argument, in place of the
+    /// profile or default application
+    pub command: Option<String>,
+
+    /// open files in an existing window where the profile says how
+    pub reuse_window: bool,
+
+    /// print the commands that would open each file instead of running them
+    pub dry_run: bool,
+
+    /// leave applications in the background where the platform allows it
+    pub background: bool,
+}
+
+/// Applications to open files with, by extension, as read from `open.toml` in the config
+/// directory.
+///
+/// Each key is an extension and each value the application to open files with that extension:
+///
+/// ```toml
+/// pdf = "zathura"
+/// html = { app = "firefox", reuse = ["--new-tab"] }
+/// ```
+///
+/// An application may be given as a table carrying the arguments that make it open files in a
+/// window it already has open, rather than a new one, as `reuse`.
+///
+/// Extensions are matched without regard to case. Files with extensions not in the profile open
+/// with their default application.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct OpenProfile {
+    apps: HashMap<String, App>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum App {
+    Name(String),
+    Command {
+        app: String,
+        #[serde(default)]
+        reuse: Vec<String>,
+    },
+}
+
+impl App {
+    fn name(&self) -> &str {
+        match self {
+            App::Name(app) | App::Command { app, .. } => app,
+        }
+    }
+
+    fn reuse_command(&self, path: &str) -> Option<Command> {
+        match self {
+            App::Command { app, reuse } if !reuse.is_empty() => {
+                let mut command = Command::new(app);
+                command.args(reuse).arg(path);
+                Some(command)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl OpenProfile {
+    pub fn from_path(path: &Path) -> io::Result<OpenProfile> {
+        let path = path.join("open.toml");
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        let text = fs::read_to_string(&path)?;
+        let profile: OpenProfile = toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unable to read {}: {e}", path.display()),
+            )
+        })?;
+
+        Ok(OpenProfile {
+            apps: profile
+                .apps
+                .into_iter()
+                .map(|(extension, app)| (extension.to_lowercase(), app))
+                .collect(),
+        })
+    }
+
+    fn app_for(&self, path: &str) -> Option<&App> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        self.apps.get(&extension)
+    }
+}
+
+/// Opens search results with the default application for each file.
+///
+/// An `Opener` remembers what it has opened, so that a long-lived opener can avoid opening the
+/// same file twice.
+pub struct Opener {
+    options: OpenOptions,
+    opened: HashSet<PathBuf>,
+}
+
+impl Opener {
+    pub fn new(options: OpenOptions) -> Self {
+        Self {
+            options,
+            opened: HashSet::new(),
+        }
+    }
+
+    /// Opens each path in turn, returning the paths actually opened (which, for a dry run, is
+    /// none of them).
+    pub fn open_all<'a>(
+        &mut self,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> io::Result<Vec<&'a str>> {
+        let open_elsewhere = if self.options.new_only {
+            open_files()
+        } else {
+            HashSet::new()
+        };
+
+        if self.options.background && !cfg!(any(target_os = "macos", windows)) {
+            eprintln!("warning: opening in the background isn't supported on this platform");
+        }
+
+        let mut state = false;
+        let mut opened = Vec::new();
+        for path in paths {
+            let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+            if self.options.new_only
+                && (self.opened.contains(&canonical) || open_elsewhere.contains(&canonical))
+            {
+                continue;
+            }
+
+            if let Some(command) = &self.options.command {
+                let command = with_command(path, command);
+                if self.options.dry_run {
+                    println!("{}", display_command(&command));
+                } else if self.options.wait {
+                    open_and_wait(path, vec![command])?;
+                } else {
+                    if state {
+                        thread::sleep(self.options.delay);
+                    } else {
+                        state = true;
+                    }
+                    spawn(command)?;
+                }
+
+                self.opened.insert(canonical);
+                if !self.options.dry_run {
+                    opened.push(path);
+                }
+                continue;
+            }
+
+            let app = self.options.profile.app_for(path);
+            let reuse = app
+                .filter(|_| self.options.reuse_window)
+                .and_then(|app| app.reuse_command(path));
+            let background = if self.options.background {
+                background_command(path, app.map(App::name))
+            } else {
+                None
+            };
+
+            if self.options.dry_run {
+                let command = reuse.or(background).or_else(|| match app {
+                    Some(app) => Some(open::with_command(path, app.name())),
+                    None if self.options.wait => wait_commands(path).into_iter().next(),
+                    None => open::commands(path).into_iter().next(),
+                });
+
+                if let Some(command) = command {
+                    println!("{}", display_command(&command));
+                }
+
+                self.opened.insert(canonical);
+                continue;
+            }
+
+            // An application reusing its window has no new window to bring up, so there's no
+            // need to give it time before the next launch.
+
+            if let Some(command) = reuse {
+                open_in_place(command)?;
+            } else if self.options.wait {
+                match app {
+                    Some(app) => open_and_wait(path, vec![open::with_command(path, app.name())])?,
+                    None => open_and_wait(path, wait_commands(path))?,
+                }
+            } else {
+                if state {
+                    thread::sleep(self.options.delay);
+                } else {
+                    state = true;
+                }
+
+                match (background, app) {
+                    (Some(command), _) => open_in_place(command)?,
+                    (None, Some(app)) => open::with(path, app.name())?,
+                    (None, None) => open::that(path)?,
+                }
+            }
+
+            self.opened.insert(canonical);
+            opened.push(path);
+        }
+
+        Ok(opened)
+    }
+}
+
+/// Formats a command as it might be typed into a shell, quoting arguments where necessary.
+fn display_command(command: &Command) -> String {
+    let quote = |arg: &OsStr| {
+        let arg = arg.to_string_lossy();
+        if !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_./:=@%+,".contains(c))
+        {
+            arg.into_owned()
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    };
+
+    let mut buf = quote(command.get_program());
+    for arg in command.get_args() {
+        buf += " ";
+        buf += &quote(arg);
+    }
+    buf
+}
+
+/// Builds the command given to --open-with for a path: the command split at whitespace, with the
+/// path appended.
+fn with_command(path: &str, command: &str) -> Command {
+    let mut words = command.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or_default());
+    command.args(words).arg(path);
+    command
+}
+
+/// Starts a command without waiting for it to exit.
+fn spawn(mut command: Command) -> io::Result<()> {
+    command.stdin(Stdio::null()).spawn().map_err(|e| {
+        let program = command.get_program().to_string_lossy().into_owned();
+        io::Error::new(e.kind(), format!("unable to run {program}: {e}"))
+    })?;
+    Ok(())
+}
+
+fn open_in_place(mut command: Command) -> io::Result<()> {
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        let program = command.get_program().to_string_lossy().into_owned();
+        Err(io::Error::other(format!("{program} failed: {status}")))
+    }
+}
+
+fn open_and_wait(path: &str, commands: Vec<Command>) -> io::Result<()> {
+    let mut error = None;
+
+    for mut command in commands {
+        match command.status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => {
+                let program = command.get_program().to_string_lossy().into_owned();
+                error = Some(io::Error::other(format!("{program} failed: {status}")));
+            }
+            Err(e) => error = Some(e),
+        }
+    }
+
+    Err(error.unwrap_or_else(|| io::Error::other(format!("unable to open {path}"))))
+}
+
+/// Commands which open a file and don't return until the application they launch has exited.
+///
+/// On macOS, `open -W` waits for the application to quit. On Windows, `start /wait` waits for the
+/// launched process, which doesn't help with applications that hand the file off to an instance
+/// that is already running. Elsewhere we run the same openers `open::that` would; whether they
+/// block depends on the desktop environment, and most only block for terminal applications.
+#[cfg(target_os = "macos")]
+fn wait_commands(path: &str) -> Vec<Command> {
+    let mut command = Command::new("/usr/bin/open");
+    command.arg("-W").arg(path);
+    vec![command]
+}
+
+#[cfg(windows)]
+fn wait_commands(path: &str) -> Vec<Command> {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("cmd");
+    command
+        .arg("/c")
+        .arg("start")
+        .arg("/wait")
+        .raw_arg("\"\"")
+        .raw_arg(format!("\"{path}\""));
+    vec![command]
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn wait_commands(path: &str) -> Vec<Command> {
+    open::commands(path)
+}
+
+/// A command which opens a file without bringing the application to the foreground, on
+/// platforms that have a way to do that.
+///
+/// On macOS, `open -g` leaves the application in the background. On Windows, `start /min`
+/// starts the application minimized, though an application that is already running may still
+/// come forward.
+#[cfg(target_os = "macos")]
+fn background_command(path: &str, app: Option<&str>) -> Option<Command> {
+    let mut command = Command::new("/usr/bin/open");
+    command.arg("-g");
+    if let Some(app) = app {
+        command.arg("-a").arg(app);
+    }
+    command.arg(path);
+    Some(command)
+}
+
+#[cfg(windows)]
+fn background_command(path: &str, app: Option<&str>) -> Option<Command> {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("cmd");
+    command.arg("/c").arg("start").arg("/min").raw_arg("\"\"");
+    if let Some(app) = app {
+        command.raw_arg(format!("\"{app}\""));
+    }
+    command.raw_arg(format!("\"{path}\""));
+    Some(command)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn background_command(_path: &str, _app: Option<&str>) -> Option<Command> {
+    None
+}
+
+/// Lists files currently held open by any process we're allowed to inspect.
+///
+/// Many editors read a file and close it again, so this will miss plenty of files a user would
+/// consider "open." It does catch viewers that keep their documents open, such as most PDF
+/// readers and pagers.
+#[cfg(target_os = "linux")]
+fn open_files() -> HashSet<PathBuf> {
+    let mut files = HashSet::new();
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return files;
+    };
+
+    for process in processes.flatten() {
+        let Ok(descriptors) = fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+
+        files.extend(
+            descriptors
+                .flatten()
+                .filter_map(|descriptor| fs::read_link(descriptor.path()).ok())
+                .filter(|target| Path::new(target).is_absolute()),
+        );
+    }
+
+    files
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_files() -> HashSet<PathBuf> {
+    HashSet::new()
+}
@@ -0,0 +1,286 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Marks the start of an archive written by `pack`.
+const MAGIC: &[u8; 8] = b"SRCHPACK";
+
+/// The version of the archive format, bumped whenever the layout changes.
+const VERSION: u32 = 2;
+
+/// Files of an index directory that aren't worth carrying to another machine: tantivy's lock
+/// files, the record of what the packer opened and their cached results.
+const EXCLUDED: &[&str] = &[
+    ".tantivy-meta.lock",
+    ".tantivy-writer.lock",
+    "access.json",
+    "cache.json",
+];
+
+/// Writes the files of an index directory to a single archive, preceded by a manifest.
+///
+/// The layout is simple enough to read without this program: the magic bytes, the format version
+/// (u32), the manifest as json (length-prefixed, u64), the number of files (u32), then each file
+/// as its name (u16 length prefix), its length (u64) and its contents, all integers
+/// little-endian. The count means an archive cut short between files is known to be incomplete.
+pub fn write(archive: &Path, manifest: &impl Serialize, dir: &Path) -> io::Result<usize> {
+    let mut out = BufWriter::new(File::create(archive)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+
+    let manifest = serde_json::to_vec(manifest)?;
+    out.write_all(&(manifest.len() as u64).to_le_bytes())?;
+    out.write_all(&manifest)?;
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if entry.file_type()?.is_file()
+            && !EXCLUDED.contains(&name.as_str())
+            && !name.ends_with(".tmp")
+        {
+            files.push((name, entry.path()));
+        }
+    }
+    files.sort();
+    out.write_all(&(files.len() as u32).to_le_bytes())?;
+
+    for (name, path) in &files {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        out.write_all(&(name.len() as u16).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&len.to_le_bytes())?;
+        io::copy(&mut file.take(len), &mut out)?;
+    }
+
+    out.flush()?;
+    Ok(files.len())
+}
+
+/// Reads the manifest at the start of an archive.
+pub fn read_manifest<T: DeserializeOwned>(archive: &Path) -> io::Result<T> {
+    read_header(&mut BufReader::new(File::open(archive)?))
+}
+
+/// Extracts the files of an archive into a directory, which must already exist.
+pub fn extract(archive: &Path, dir: &Path) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(archive)?);
+    read_header::<serde_json::Value>(&mut input)?;
+
+    let mut count = [0; 4];
+    input.read_exact(&mut count).map_err(truncated)?;
+    for _ in 0..u32::from_le_bytes(count) {
+        let mut len = [0; 2];
+        input.read_exact(&mut len).map_err(truncated)?;
+        let mut name = vec![0; u16::from_le_bytes(len).into()];
+        input.read_exact(&mut name).map_err(truncated)?;
+        let name = String::from_utf8(name).map_err(|_| invalid("a file name isn't utf-8"))?;
+
+        // Names come from the archive, so one that would land outside the directory is refused
+        // rather than trusted.
+        if name.is_empty() || name == ".." || name.contains(['/', '\\']) {
+            return Err(invalid(&format!("{name:?} isn't a plain file name")));
+        }
+
+        let len = read_u64(&mut input).map_err(truncated)?;
+        let mut file = File::create(dir.join(&name))?;
+        let copied = io::copy(&mut (&mut input).take(len), &mut file)?;
+        if copied != len {
+            return Err(invalid(&format!("{name} is truncated")));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_header<T: DeserializeOwned>(input: &mut impl Read) -> io::Result<T> {
+    let mut magic = [0; 8];
+    let read = input.read_exact(&mut magic);
+    if read.is_err() || &magic != MAGIC {
+        return Err(invalid("not an index archive"));
+    }
+
+    let mut version = [0; 4];
+    input.read_exact(&mut version).map_err(truncated)?;
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "archive format {version} isn't supported (expected {VERSION})"
+        )));
+    }
+
+    let len = read_u64(input).map_err(truncated)?;
+    let mut manifest = Vec::new();
+    input.take(len).read_to_end(&mut manifest)?;
+    if manifest.len() as u64 != len {
+        return Err(invalid("the archive is truncated"));
+    }
+    Ok(serde_json::from_slice(&manifest)?)
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Reports running out of archive as the archive's fault rather than as a bare end of file.
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        invalid("the archive is truncated")
+    } else {
+        e
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        path::PathBuf,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// A directory of its own, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> TempDir {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+            let id = NEXT.fetch_add(1, Ordering::Relaxed);
+            let path = env::temp_dir().join(format!("search-pack-test-{}-{id}", process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes an archive by hand: a header for the given format version, then each entry.
+    fn archive(version: u32, entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let manifest = br#"{"name":"library"}"#;
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&(manifest.len() as u64).to_le_bytes());
+        data.extend_from_slice(manifest);
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for (name, contents) in entries {
+            data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+            data.extend_from_slice(contents);
+        }
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let (index, out, dir) = (TempDir::new(), TempDir::new(), TempDir::new());
+        fs::write(index.0.join("meta.json"), "{}").unwrap();
+        fs::write(index.0.join("abc.store"), [0, 1, 2, 255]).unwrap();
+        fs::write(index.0.join("empty.idx"), "").unwrap();
+        fs::write(index.0.join(".tantivy-meta.lock"), "").unwrap();
+        fs::write(index.0.join("cache.json"), "[]").unwrap();
+        fs::write(index.0.join("half.tmp"), "").unwrap();
+        fs::create_dir(index.0.join("subdir")).unwrap();
+
+        let archive = out.0.join("library.pack");
+        let manifest = json!({"name": "library", "roots": ["/somewhere"]});
+        assert_eq!(write(&archive, &manifest, &index.0).unwrap(), 3);
+        assert_eq!(read_manifest::<Value>(&archive).unwrap(), manifest);
+
+        extract(&archive, &dir.0).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["abc.store", "empty.idx", "meta.json"]);
+        assert_eq!(fs::read(dir.0.join("abc.store")).unwrap(), [0, 1, 2, 255]);
+    }
+
+    #[test]
+    fn entry_names_stay_in_the_directory() {
+        let out = TempDir::new();
+        let dir = TempDir::new();
+        let archive = out.0.join("library.pack");
+
+        for name in [
+            "../escape",
+            "..",
+            "",
+            "sub/file",
+            "sub\\file",
+            "/etc/escape",
+        ] {
+            fs::write(&archive, self::archive(VERSION, &[(name, b"contents")])).unwrap();
+            let error = extract(&archive, &dir.0).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{name:?}");
+        }
+        assert!(!out.0.join("escape").exists());
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn truncated_archives() {
+        let out = TempDir::new();
+        let dir = TempDir::new();
+        let archive = out.0.join("library.pack");
+        let data = self::archive(
+            VERSION,
+            &[("meta.json", b"{}"), ("abc.store", b"0123456789")],
+        );
+
+        // Cut anywhere, even between files, the archive is refused.
+        for len in 0..data.len() {
+            fs::write(&archive, &data[..len]).unwrap();
+            let read = read_manifest::<Value>(&archive).and_then(|_| extract(&archive, &dir.0));
+            let error = read.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{len}: {error}");
+        }
+
+        fs::write(&archive, &data).unwrap();
+        extract(&archive, &dir.0).unwrap();
+    }
+
+    #[test]
+    fn unsupported_versions() {
+        let out = TempDir::new();
+        let dir = TempDir::new();
+        let archive = out.0.join("library.pack");
+
+        fs::write(
+            &archive,
+            self::archive(VERSION + 1, &[("meta.json", b"{}")]),
+        )
+        .unwrap();
+        let error = read_manifest::<Value>(&archive).unwrap_err();
+        assert!(error.to_string().contains("isn't supported"), "{error}");
+        assert!(extract(&archive, &dir.0).is_err());
+
+        fs::write(&archive, b"PK\x03\x04 not an index").unwrap();
+        assert!(read_manifest::<Value>(&archive).is_err());
+    }
+}
@@ -0,0 +1,573 @@
+use std::fmt;
+
+use crate::inflate;
+
+/// Filters we can't undo. Streams using them hold images or other data with no text in it.
+static UNSUPPORTED_FILTERS: &[&[u8]] = &[
+    b"ASCII85Decode",
+    b"ASCIIHexDecode",
+    b"LZWDecode",
+    b"RunLengthDecode",
+    b"CCITTFaxDecode",
+    b"JBIG2Decode",
+    b"DCTDecode",
+    b"JPXDecode",
+    b"Crypt",
+];
+
+/// A pdf that couldn't be read: it's malformed, encrypted or otherwise beyond us.
+#[derive(Clone, Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unable to read pdf: {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<inflate::Error> for Error {
+    fn from(e: inflate::Error) -> Self {
+        Error(e.to_string())
+    }
+}
+
+pub fn is_pdf(data: &[u8]) -> bool {
+    find(&data[..data.len().min(1024)], b"%PDF-").is_some()
+}
+
+/// Extracts the text shown by a pdf's content streams.
+///
+/// This understands the file structure and the text operators and no more. Strings are read as
+/// Latin-1, which suits the simple fonts most documents use for Western text; text set in
+/// composite fonts, which needs the font's own mapping to become characters, comes out as
+/// nothing useful. Scanned documents have no text to find.
+pub fn extract(data: &[u8]) -> Result<String, Error> {
+    if !is_pdf(data) {
+        return Err(Error(String::from("missing pdf header")));
+    }
+    if find(data, b"/Encrypt").is_some() {
+        return Err(Error(String::from("encrypted documents aren't supported")));
+    }
+
+    let mut text = String::new();
+    for stream in streams(data) {
+        let Some(content) = decode(&stream)? else {
+            continue;
+        };
+        show_text(&content, &mut text);
+    }
+
+    Ok(text)
+}
+
+/// A stream object: its dictionary and its raw, still encoded, data.
+struct Stream<'a> {
+    dict: &'a [u8],
+    data: &'a [u8],
+}
+
+/// Finds every stream in a file by scanning for the `stream` keyword rather than by reading the
+/// cross-reference table, which is as often wrong as right in the files people actually have.
+fn streams(data: &[u8]) -> impl Iterator<Item = Stream<'_>> {
+    let mut cursor = 0;
+    std::iter::from_fn(move || loop {
+        let idx = cursor + find(&data[cursor..], b"stream")?;
+        cursor = idx + b"stream".len();
+
+        // The keyword also ends `endstream`, and must follow a dictionary and precede an end
+        // of line.
+        let before = trim_end(&data[..idx]);
+        if !before.ends_with(b">>") {
+            continue;
+        }
+        let start = match &data[cursor..] {
+            [b'\r', b'\n', ..] => cursor + 2,
+            [b'\n', ..] | [b'\r', ..] => cursor + 1,
+            _ => continue,
+        };
+
+        let dict = match rfind(before, b"obj") {
+            Some(obj) => &before[obj + b"obj".len()..],
+            None => before,
+        };
+
+        // The length is usually given directly, but may be an indirect reference we don't
+        // follow, or simply wrong, so it's checked against the end of the stream. A stream
+        // with no end at all was cut short, and runs to the end of the file.
+        let end = integer(dict, b"/Length")
+            .and_then(|len| start.checked_add(len))
+            .filter(|&end| {
+                data.get(end..)
+                    .is_some_and(|rest| trim_start(rest).starts_with(b"endstream"))
+            })
+            .or_else(|| find(&data[start..], b"endstream").map(|end| start + end))
+            .unwrap_or(data.len());
+
+        cursor = end;
+        return Some(Stream {
+            dict,
+            data: &data[start..end],
+        });
+    })
+}
+
+/// Decodes a stream that might hold text, skipping those that can't.
+fn decode(stream: &Stream) -> Result<Option<Vec<u8>>, Error> {
+    let dict = stream.dict;
+
+    // Images, fonts, metadata and the like have no text of their own. Form XObjects are content
+    // streams in their own right.
+
+    if name(dict, b"/Subtype").is_some_and(|subtype| subtype != b"Form")
+        || matches!(
+            name(dict, b"/Type"),
+            Some(b"XRef" | b"ObjStm" | b"Metadata")
+        )
+        || find(dict, b"/Length1").is_some()
+        || UNSUPPORTED_FILTERS
+            .iter()
+            .any(|filter| find(dict, filter).is_some())
+    {
+        return Ok(None);
+    }
+
+    if find(dict, b"/FlateDecode").is_some() {
+        Ok(Some(inflate::zlib(stream.data)?))
+    } else if find(dict, b"/Filter").is_some() {
+        Ok(None)
+    } else {
+        Ok(Some(stream.data.to_vec()))
+    }
+}
+
+#[derive(Debug)]
+enum Operand {
+    String(Vec<u8>),
+    Number(f32),
+    Array(Vec<Operand>),
+    ArrayStart,
+    Other,
+}
+
+/// Appends the text shown by a content stream, starting a new line wherever the stream moves
+/// down the page and a new word wherever it moves along.
+fn show_text(content: &[u8], text: &mut String) {
+    let mut tokens = Tokens {
+        data: content,
+        pos: 0,
+    };
+    let mut operands = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        let operator = match token {
+            Token::Operand(operand) => {
+                operands.push(operand);
+                continue;
+            }
+            Token::ArrayEnd => {
+                let start = operands
+                    .iter()
+                    .rposition(|operand| matches!(operand, Operand::ArrayStart));
+                let array = start
+                    .map(|start| operands.split_off(start).into_iter().skip(1).collect())
+                    .unwrap_or_default();
+                operands.push(Operand::Array(array));
+                continue;
+            }
+            Token::Operator(operator) => operator,
+        };
+
+        match operator {
+            b"Tj" => push_strings(&operands, text),
+            b"'" | b"\"" => {
+                separate(text, '\n');
+                push_strings(&operands, text);
+            }
+            b"TJ" => {
+                if let Some(Operand::Array(array)) = operands.last() {
+                    for item in array {
+                        match item {
+                            Operand::String(s) => push_latin1(s, text),
+
+                            // Kerning is given in thousandths of an em; a gap this wide is a
+                            // space set by hand.
+                            Operand::Number(n) if *n < -200.0 => separate(text, ' '),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            b"Td" | b"TD" => match operands[..] {
+                [.., Operand::Number(_), Operand::Number(y)] if y != 0.0 => separate(text, '\n'),
+                _ => separate(text, ' '),
+            },
+            b"T*" | b"ET" => separate(text, '\n'),
+            b"Tm" => separate(text, ' '),
+
+            // Inline images are binary and end at the first `EI` that stands alone.
+            b"ID" => tokens.skip_inline_image(),
+            _ => {}
+        }
+
+        operands.clear();
+    }
+}
+
+fn push_strings(operands: &[Operand], text: &mut String) {
+    for operand in operands {
+        if let Operand::String(s) = operand {
+            push_latin1(s, text);
+        }
+    }
+}
+
+fn push_latin1(s: &[u8], text: &mut String) {
+    text.extend(
+        s.iter()
+            .map(|&b| char::from(b))
+            .filter(|c| !c.is_control() || c.is_whitespace()),
+    );
+}
+
+fn separate(text: &mut String, separator: char) {
+    match text.chars().last() {
+        None => {}
+        Some('\n') => {}
+        Some(' ') if separator == ' ' => {}
+        Some(' ') => {
+            text.pop();
+            text.push(separator);
+        }
+        Some(_) => text.push(separator),
+    }
+}
+
+enum Token<'a> {
+    Operand(Operand),
+    ArrayEnd,
+    Operator(&'a [u8]),
+}
+
+struct Tokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let &b = self.data.get(self.pos)?;
+            match b {
+                b if b.is_ascii_whitespace() || b == 0 => self.pos += 1,
+                b'%' => {
+                    while self
+                        .data
+                        .get(self.pos)
+                        .is_some_and(|&b| b != b'\n' && b != b'\r')
+                    {
+                        self.pos += 1;
+                    }
+                }
+                b'(' => return Some(Token::Operand(Operand::String(self.literal()))),
+                b'<' if self.data.get(self.pos + 1) == Some(&b'<') => {
+                    self.pos += 2;
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'<' => return Some(Token::Operand(Operand::String(self.hex()))),
+                b'>' => {
+                    self.pos += 1;
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'[' => {
+                    self.pos += 1;
+                    return Some(Token::Operand(Operand::ArrayStart));
+                }
+                b']' => {
+                    self.pos += 1;
+                    return Some(Token::ArrayEnd);
+                }
+                b'/' => {
+                    self.pos += 1;
+                    self.regular();
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'{' | b'}' | b')' => {
+                    self.pos += 1;
+                    return Some(Token::Operand(Operand::Other));
+                }
+                _ => {
+                    let word = self.regular();
+                    let number = std::str::from_utf8(word)
+                        .ok()
+                        .and_then(|word| word.parse().ok());
+                    return Some(match number {
+                        Some(n) => Token::Operand(Operand::Number(n)),
+                        None => Token::Operator(word),
+                    });
+                }
+            }
+        }
+    }
+
+    fn regular(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(|&b| is_regular(b)) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    fn literal(&mut self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut depth = 0;
+        self.pos += 1;
+
+        while let Some(&b) = self.data.get(self.pos) {
+            self.pos += 1;
+            match b {
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                b'\\' => {
+                    let Some(&escaped) = self.data.get(self.pos) else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => buf.push(b'\n'),
+                        b'r' => buf.push(b'\r'),
+                        b't' => buf.push(b'\t'),
+                        b'b' => buf.push(8),
+                        b'f' => buf.push(12),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.data.get(self.pos) {
+                                    Some(&digit @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(digit - b'0');
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            buf.push(value as u8);
+                        }
+
+                        // A backslash at the end of a line continues the string on the next.
+                        b'\r' => {
+                            if self.data.get(self.pos) == Some(&b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        escaped => buf.push(escaped),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            buf.push(b);
+        }
+
+        buf
+    }
+
+    fn hex(&mut self) -> Vec<u8> {
+        self.pos += 1;
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(|&b| b != b'>') {
+            self.pos += 1;
+        }
+        let digits: Vec<_> = self.data[start..self.pos]
+            .iter()
+            .filter_map(|&b| char::from(b).to_digit(16))
+            .map(|digit| digit as u8)
+            .collect();
+        self.pos += 1;
+
+        // An odd digit out is followed by an implied zero.
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+            .collect()
+    }
+
+    fn skip_inline_image(&mut self) {
+        while self.pos < self.data.len() {
+            let rest = &self.data[self.pos..];
+            if rest.starts_with(b"EI")
+                && self.data[self.pos - 1].is_ascii_whitespace()
+                && rest.get(2).is_none_or(|b| b.is_ascii_whitespace())
+            {
+                self.pos += 2;
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+fn is_regular(b: u8) -> bool {
+    !b.is_ascii_whitespace() && b != 0 && !b"()<>[]{}/%".contains(&b)
+}
+
+/// Reads a direct integer value from a dictionary, ignoring indirect references.
+fn integer(dict: &[u8], key: &[u8]) -> Option<usize> {
+    let rest = trim_start(&dict[find(dict, key)? + key.len()..]);
+    let len = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let value = std::str::from_utf8(&rest[..len]).ok()?.parse().ok()?;
+
+    // `12 0 R` refers to object 12; it isn't the number 12.
+    let after = trim_start(&rest[len..]);
+    let is_reference = after.first().is_some_and(u8::is_ascii_digit)
+        && trim_start(&after[after.iter().take_while(|b| b.is_ascii_digit()).count()..])
+            .starts_with(b"R");
+    (!is_reference).then_some(value)
+}
+
+/// Reads a name value from a dictionary, without its leading slash.
+fn name<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut cursor = 0;
+    loop {
+        let idx = cursor + find(&dict[cursor..], key)?;
+        cursor = idx + key.len();
+
+        // `/Type` is a prefix of other keys, so make sure we've found the whole key.
+        if dict.get(cursor).is_some_and(|&b| is_regular(b)) {
+            continue;
+        }
+
+        let rest = trim_start(&dict[cursor..]).strip_prefix(b"/")?;
+        let len = rest.iter().take_while(|&&b| is_regular(b)).count();
+        return Some(&rest[..len]);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+fn trim_start(s: &[u8]) -> &[u8] {
+    let len = s.iter().take_while(|b| b.is_ascii_whitespace()).count();
+    &s[len..]
+}
+
+fn trim_end(s: &[u8]) -> &[u8] {
+    let len = s
+        .iter()
+        .rev()
+        .take_while(|b| b.is_ascii_whitespace())
+        .count();
+    &s[..s.len() - len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `BT (Deflated) Tj ET`, zlib compressed.
+    static DEFLATED: &[u8] = &[
+        120, 156, 115, 10, 81, 208, 112, 73, 77, 203, 73, 44, 73, 77, 209, 84, 8, 201, 82, 112, 13,
+        1, 0, 57, 10, 5, 184,
+    ];
+
+    /// Builds a pdf holding a stream for each of the given dictionaries and data.
+    fn pdf(streams: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = b"%PDF-1.4\n".to_vec();
+        for (idx, (dict, content)) in streams.iter().enumerate() {
+            let header = format!(
+                "{} 0 obj\n<< {dict} /Length {} >>\nstream\n",
+                idx + 1,
+                content.len()
+            );
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(content);
+            data.extend_from_slice(b"\nendstream\nendobj\n");
+        }
+        data.extend_from_slice(b"trailer\n<< /Root 1 0 R >>\n%%EOF\n");
+        data
+    }
+
+    #[test]
+    fn text_operators() {
+        let content = b"BT /F1 12 Tf 72 720 Td (Hello) Tj 40 0 Td (there) Tj\n\
+                        0 -14 Td [(Ker) -20 (ned) -400 (words)] TJ\n\
+                        T* (esc\\(aped\\) \\101) Tj ET";
+        let text = extract(&pdf(&[("", content)])).unwrap();
+        assert_eq!(text, "Hello there\nKerned words\nesc(aped) A\n");
+    }
+
+    #[test]
+    fn deflated_streams() {
+        let data = pdf(&[("/Filter /FlateDecode", DEFLATED)]);
+        assert_eq!(extract(&data).unwrap(), "Deflated\n");
+    }
+
+    #[test]
+    fn skipped_streams() {
+        let data = pdf(&[
+            ("/Subtype /Image /Filter /DCTDecode", b"\xff\xd8 (not) Tj"),
+            ("/Filter /ASCII85Decode", b"(nor) Tj"),
+            ("", b"BT (shown) Tj ET"),
+        ]);
+        assert_eq!(extract(&data).unwrap(), "shown\n");
+    }
+
+    #[test]
+    fn unreadable_files() {
+        assert!(extract(b"").is_err());
+        assert!(extract(b"not a pdf at all").is_err());
+        assert!(extract(b"%PDF-1.4\ntrailer << /Encrypt 2 0 R >>").is_err());
+
+        let mut corrupt = DEFLATED.to_vec();
+        corrupt[1] ^= 1;
+        assert!(extract(&pdf(&[("/Filter /FlateDecode", &corrupt)])).is_err());
+        corrupt[1] ^= 1;
+        corrupt[2] = 0x07;
+        assert!(extract(&pdf(&[("/Filter /FlateDecode", &corrupt)])).is_err());
+    }
+
+    #[test]
+    fn truncated_files() {
+        let data = pdf(&[("/Filter /FlateDecode", DEFLATED)]);
+
+        // Cut inside the compressed data, the stream can't be inflated.
+        let start = find(&data, b"stream\n").unwrap() + b"stream\n".len();
+        for len in start + 2..start + DEFLATED.len() - 4 {
+            assert!(extract(&data[..len]).is_err(), "{len}");
+        }
+
+        // Cut anywhere, it mustn't panic.
+        let data = pdf(&[
+            ("", b"BT (Hello) Tj [(a) -300 (b)] TJ <48 65> Tj ET"),
+            ("/Filter /FlateDecode", DEFLATED),
+            ("", b"q BI /W 1 /H 1 ID \x00\x01 EI Q (x\\"),
+        ]);
+        for len in 0..data.len() {
+            let _ = extract(&data[..len]);
+        }
+    }
+
+    #[test]
+    fn corrupt_lengths() {
+        let content = b"BT (Hello) Tj ET";
+        for length in ["0", "3", "9999", "18446744073709551615", "2 0 R"] {
+            let mut data = b"%PDF-1.4\n1 0 obj\n<< /Length ".to_vec();
+            data.extend_from_slice(length.as_bytes());
+            data.extend_from_slice(b" >>\nstream\n");
+            data.extend_from_slice(content);
+            data.extend_from_slice(b"\nendstream\nendobj\n");
+            assert_eq!(extract(&data).unwrap(), "Hello\n", "/Length {length}");
+        }
+    }
+}
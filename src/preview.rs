@@ -0,0 +1,127 @@
+use std::{
+    io::{self, Read},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// The placeholder replaced by the path of the document previewed.
+const PLACEHOLDER: &str = "{path}";
+
+/// A shell command that renders a preview of a document, such as `bat --color=never {path}`.
+///
+/// The command is run by the shell, so it may be a pipeline. `{path}` is replaced by the
+/// document's path, quoted for the shell; a command without it gets the path as its last
+/// argument. Whatever the command writes to standard output is the preview.
+#[derive(Clone, Debug)]
+pub struct PreviewCommand {
+    template: String,
+    timeout: Duration,
+}
+
+impl PreviewCommand {
+    pub fn new(template: String, timeout: Duration) -> Self {
+        Self { template, timeout }
+    }
+
+    /// Runs the command for a document, returning the lines of its output.
+    ///
+    /// A command that runs past the timeout is killed, and whatever it wrote by then is lost. The
+    /// preview says so rather than leaving the user looking at nothing.
+    pub fn run(&self, path: &str) -> io::Result<Vec<String>> {
+        let quoted = quote(path);
+        let command = if self.template.contains(PLACEHOLDER) {
+            self.template.replace(PLACEHOLDER, &quoted)
+        } else {
+            format!("{} {quoted}", self.template)
+        };
+
+        let mut child = shell(&command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Reading happens on its own thread so that a command that never finishes writing can
+        // be abandoned.
+
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = tx.send(stdout.read_to_end(&mut buf).map(|_| buf));
+        });
+
+        match rx.recv_timeout(self.timeout) {
+            Ok(output) => {
+                child.wait()?;
+                let output = String::from_utf8_lossy(&output?).into_owned();
+                Ok(strip_escapes(&output)
+                    .lines()
+                    .map(ToOwned::to_owned)
+                    .collect())
+            }
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Ok(vec![format!(
+                    "(preview command timed out after {:.1}s)",
+                    self.timeout.as_secs_f64()
+                )])
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("/bin/sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(unix)]
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn quote(path: &str) -> String {
+    format!("\"{path}\"")
+}
+
+/// Removes the ANSI escape sequences that tools like `bat` use for color, which the finder
+/// can't display.
+fn strip_escapes(text: &str) -> String {
+    let mut buf = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            buf.push(c);
+            continue;
+        }
+
+        // A control sequence runs from `ESC [` to a final byte in the range `@` to `~`; any
+        // other escape is two characters long.
+        if chars.next_if_eq(&'[').is_some() {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            chars.next();
+        }
+    }
+
+    buf
+}
@@ -0,0 +1,55 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use serde::Deserialize;
+
+/// Named ranking profiles, as read from `profiles.toml` in the config directory.
+///
+/// Each table in the file is a profile:
+///
+/// ```toml
+/// [prose]
+/// fields = ["text"]
+/// boosts = { text = 2.0 }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Profiles {
+    profiles: HashMap<String, Profile>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// fields searched by terms that don't name a field
+    pub fields: Option<Vec<String>>,
+
+    /// per-field score multipliers
+    #[serde(default)]
+    pub boosts: HashMap<String, f32>,
+}
+
+impl Profiles {
+    pub fn from_path(path: &Path) -> io::Result<Profiles> {
+        let path = path.join("profiles.toml");
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        let text = fs::read_to_string(&path)?;
+        toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unable to read {}: {e}", path.display()),
+            )
+        })
+    }
+
+    pub fn get(&self, name: &str) -> io::Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no ranking profile named {name:?}"),
+            )
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+/// The width of the bar, in characters.
+const WIDTH: usize = 30;
+
+/// How often the bar is redrawn, at most.
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// Reports the progress of indexing on standard error.
+///
+/// On a terminal, this is a bar redrawn in place. Elsewhere, as when output goes to a log, it's a
+/// line every `every` files, so that the log shows progress without filling up with bars.
+pub struct Progress {
+    total: usize,
+    done: usize,
+    style: Style,
+    drawn: Option<Instant>,
+}
+
+#[derive(Clone, Copy)]
+enum Style {
+    Bar,
+    Lines { every: usize },
+    Quiet,
+}
+
+impl Progress {
+    pub fn new(total: usize, every: usize, quiet: bool) -> Self {
+        let style = if quiet {
+            Style::Quiet
+        } else if io::stderr().is_terminal() {
+            Style::Bar
+        } else {
+            Style::Lines { every }
+        };
+
+        Self {
+            total,
+            done: 0,
+            style,
+            drawn: None,
+        }
+    }
+
+    /// Records that one more file has been dealt with.
+    pub fn advance(&mut self) {
+        self.done += 1;
+        match self.style {
+            Style::Bar if self.drawn.is_none_or(|drawn| drawn.elapsed() >= REDRAW) => {
+                self.draw();
+                self.drawn = Some(Instant::now());
+            }
+            Style::Lines { every } if self.done.is_multiple_of(every) => {
+                eprintln!("{} of {} files", self.done, self.total);
+            }
+            _ => {}
+        }
+    }
+
+    /// Finishes the report with a summary of what was done.
+    pub fn finish(&mut self, indexed: usize, skipped: usize) {
+        match self.style {
+            Style::Quiet => return,
+            Style::Bar => {
+                self.draw();
+                eprintln!();
+            }
+            Style::Lines { .. } => {}
+        }
+        eprintln!("{indexed} indexed, {skipped} skipped");
+    }
+
+    fn draw(&self) {
+        let filled = (self.done * WIDTH).checked_div(self.total).unwrap_or(WIDTH);
+        let percent = (self.done * 100).checked_div(self.total).unwrap_or(100);
+        eprint!(
+            "\r[{}{}] {}/{} ({percent}%)",
+            "#".repeat(filled),
+            "-".repeat(WIDTH - filled),
+            self.done,
+            self.total,
+        );
+        let _ = io::stderr().flush();
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+/// A record of the queries searched, stored in the storage directory as `queries.log`.
+///
+/// Each line holds the time of a search (UTC), the library searched, the number of documents
+/// found and the query, separated by tabs. The log is only kept when the user asks for it.
+pub struct QueryLog<'a> {
+    storage_path: &'a Path,
+}
+
+impl<'a> QueryLog<'a> {
+    pub fn new(storage_path: &'a Path) -> Self {
+        Self { storage_path }
+    }
+
+    pub fn append(&self, library: &str, query: &str, total: usize) -> io::Result<()> {
+        let query = query.replace(['\t', '\n', '\r'], " ");
+        let line = format!(
+            "{}\t{library}\t{total}\t{query}\n",
+            timestamp(SystemTime::now())
+        );
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.storage_path.join("queries.log"))?;
+        file.write_all(line.as_bytes())
+    }
+
+    pub fn read(&self) -> io::Result<String> {
+        match fs::read_to_string(self.storage_path.join("queries.log")) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            result => result,
+        }
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(self.storage_path.join("queries.log")) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, e.g. `2023-07-14T09:30:00Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Converts a count of days since the unix epoch to a date in the proleptic Gregorian calendar.
+///
+/// This is Howard Hinnant's `civil_from_days`, which works in 400-year eras so that leap years
+/// fall out of the arithmetic.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,81 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use tantivy::{
+    collector::DocSetCollector,
+    index::SegmentId,
+    query::{
+        BitSetDocSet, ConstScorer, EmptyScorer, EnableScoring, Explanation, Query, Scorer, Weight,
+    },
+    DocId, Score, Searcher, SegmentReader, TantivyError,
+};
+use tantivy_common::BitSet;
+
+/// Matches exactly the documents found by an earlier search, so that a new query can be limited
+/// to them.
+///
+/// Documents are identified by segment, so a result set is only good for searches made with the
+/// searcher that found it.
+#[derive(Clone)]
+pub struct ResultSet {
+    docs: Arc<HashMap<SegmentId, Vec<DocId>>>,
+}
+
+impl ResultSet {
+    /// Finds every document matching a query, not just the best.
+    pub fn collect(searcher: &Searcher, query: &dyn Query) -> tantivy::Result<Self> {
+        let mut docs: HashMap<_, Vec<_>> = HashMap::new();
+        for address in searcher.search(query, &DocSetCollector)? {
+            let segment = searcher.segment_reader(address.segment_ord).segment_id();
+            docs.entry(segment).or_default().push(address.doc_id);
+        }
+
+        Ok(Self {
+            docs: Arc::new(docs),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.values().map(Vec::len).sum()
+    }
+}
+
+impl fmt::Debug for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResultSet({} documents)", self.len())
+    }
+}
+
+impl Query for ResultSet {
+    fn weight(&self, _enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl Weight for ResultSet {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let Some(ids) = self.docs.get(&reader.segment_id()) else {
+            return Ok(Box::new(EmptyScorer));
+        };
+
+        let mut docs = BitSet::with_max_value(reader.max_doc());
+        for &doc in ids {
+            docs.insert(doc);
+        }
+        Ok(Box::new(ConstScorer::new(BitSetDocSet::from(docs), boost)))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let found = self
+            .docs
+            .get(&reader.segment_id())
+            .is_some_and(|ids| ids.contains(&doc));
+
+        if found {
+            Ok(Explanation::new("in the previous results", 1.0))
+        } else {
+            Err(TantivyError::InvalidArgument(format!(
+                "document {doc} wasn't in the previous results"
+            )))
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use tantivy::{
+    query::Query,
+    schema::Field,
+    tokenizer::{TextAnalyzer, TokenStream},
+    Score, Searcher,
+};
+
+/// Builds snippets from document text.
+///
+/// This does the same job as tantivy's `SnippetGenerator`, except that the amount of context on
+/// either side of a match is configurable and a document can yield more than one fragment.
+pub struct Snippets {
+    terms: BTreeMap<String, Score>,
+    tokenizer: TextAnalyzer,
+    before: usize,
+    after: usize,
+    count: usize,
+}
+
+/// A window of document text surrounding one or more matches.
+#[derive(Clone)]
+pub struct Fragment<'a> {
+    text: &'a str,
+    highlighted: Vec<Range<usize>>,
+    leading: bool,
+    trailing: bool,
+}
+
+impl Snippets {
+    pub fn create(
+        searcher: &Searcher,
+        query: &dyn Query,
+        field: Field,
+        before: usize,
+        after: usize,
+        count: usize,
+    ) -> tantivy::Result<Self> {
+        let mut terms = Vec::new();
+        query.query_terms(&mut |term, _| {
+            if term.field() == field {
+                terms.push(term.clone());
+            }
+        });
+
+        // Rarer terms make for more interesting snippets, so each term is weighted by the inverse
+        // of its document frequency, just as tantivy does.
+
+        let mut weights = BTreeMap::new();
+        for term in terms {
+            let doc_freq = searcher.doc_freq(&term)?;
+            if let (Some(text), true) = (term.value().as_str(), doc_freq > 0) {
+                weights.insert(text.to_owned(), 1.0 / (1.0 + doc_freq as Score));
+            }
+        }
+
+        Ok(Self {
+            terms: weights,
+            tokenizer: searcher.index().tokenizer_for_field(field)?,
+            before,
+            after,
+            count,
+        })
+    }
+
+    /// Returns the location of the first occurrence of the most significant matching term.
+    pub fn first_match(&self, text: &str) -> Option<Range<usize>> {
+        let mut tokenizer = self.tokenizer.clone();
+        let mut stream = tokenizer.token_stream(text);
+        let mut best: Option<(Range<usize>, Score)> = None;
+
+        while stream.advance() {
+            let token = stream.token();
+            if let Some(&weight) = self.terms.get(&token.text) {
+                if best.as_ref().is_none_or(|(_, best)| weight > *best) {
+                    best = Some((token.offset_from..token.offset_to, weight));
+                }
+            }
+        }
+
+        best.map(|(range, _)| range)
+    }
+
+    /// Returns the best fragments of the text, in document order.
+    pub fn fragments<'a>(&self, text: &'a str) -> Vec<Fragment<'a>> {
+        let mut tokenizer = self.tokenizer.clone();
+        let mut stream = tokenizer.token_stream(text);
+        let mut matches: Vec<(Range<usize>, Score)> = Vec::new();
+
+        // Tokens may overlap, as an identifier overlaps the words it's made of, in which case the
+        // matches are highlighted as one.
+
+        while stream.advance() {
+            let token = stream.token();
+            let Some(&weight) = self.terms.get(&token.text) else {
+                continue;
+            };
+            match matches.last_mut() {
+                Some((range, best)) if token.offset_from < range.end => {
+                    range.end = range.end.max(token.offset_to);
+                    *best = best.max(weight);
+                }
+                _ => matches.push((token.offset_from..token.offset_to, weight)),
+            }
+        }
+
+        // Matches close enough together that their context would overlap share a window.
+
+        let mut windows: Vec<(Range<usize>, Vec<Range<usize>>, Score)> = Vec::new();
+        for (range, weight) in matches {
+            let start = floor_boundary(text, range.start.saturating_sub(self.before));
+            let end = ceil_boundary(text, range.end.saturating_add(self.after));
+
+            match windows.last_mut() {
+                Some((window, highlighted, score)) if start <= window.end => {
+                    window.end = window.end.max(end);
+                    highlighted.push(range);
+                    *score += weight;
+                }
+                _ => windows.push((start..end, vec![range], weight)),
+            }
+        }
+
+        windows.sort_by(|a, b| b.2.total_cmp(&a.2));
+        windows.truncate(self.count);
+        windows.sort_by_key(|(window, ..)| window.start);
+
+        windows
+            .into_iter()
+            .map(|(window, highlighted, _)| {
+                let window = trim_partial_words(text, window, &highlighted);
+                Fragment {
+                    text: &text[window.clone()],
+                    highlighted: highlighted
+                        .into_iter()
+                        .map(|range| range.start - window.start..range.end - window.start)
+                        .collect(),
+                    leading: window.start > 0,
+                    trailing: window.end < text.len(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Fragment<'_> {
+    /// Renders the fragment on a single line, wrapping each match in `prefix` and `suffix`.
+    ///
+    /// Text from the document passes through `escape` before being written.
+    pub fn render(&self, prefix: &str, suffix: &str, escape: impl Fn(&str) -> String) -> String {
+        let mut buf = String::new();
+        let mut last = 0;
+        for range in &self.highlighted {
+            buf += &escape(&collapse_whitespace(&self.text[last..range.start]));
+            buf += prefix;
+            buf += &escape(&self.text[range.clone()]);
+            buf += suffix;
+            last = range.end;
+        }
+        buf += &escape(&collapse_whitespace(&self.text[last..]));
+
+        let leading = if self.leading { "…" } else { "" };
+        let trailing = if self.trailing { "…" } else { "" };
+        format!("{leading}{}{trailing}", buf.trim())
+    }
+}
+
+/// Renders several fragments on a single line, joined by `separator`.
+///
+/// The separator stands in for the text between fragments, so fragments are only marked as
+/// incomplete at the start and end of the line.
+pub fn join(
+    fragments: &[Fragment],
+    separator: &str,
+    prefix: &str,
+    suffix: &str,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let last = fragments.len().saturating_sub(1);
+    fragments
+        .iter()
+        .enumerate()
+        .map(|(idx, fragment)| {
+            let fragment = Fragment {
+                leading: fragment.leading && idx == 0,
+                trailing: fragment.trailing && idx == last,
+                ..fragment.clone()
+            };
+            fragment.render(prefix, suffix, &escape)
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Pulls the edges of a window in to the nearest whitespace so that it doesn't begin or end in
+/// the middle of a word, so long as doing so doesn't cut off a match.
+fn trim_partial_words(text: &str, window: Range<usize>, matches: &[Range<usize>]) -> Range<usize> {
+    let first = matches
+        .first()
+        .map(|range| range.start)
+        .unwrap_or(window.start);
+    let last = matches.last().map(|range| range.end).unwrap_or(window.end);
+
+    let start = if window.start == 0 {
+        0
+    } else {
+        text[window.start..first]
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(idx, c)| window.start + idx + c.len_utf8())
+            .unwrap_or(window.start)
+    };
+
+    let end = if window.end == text.len() {
+        window.end
+    } else {
+        text[last..window.end]
+            .rfind(char::is_whitespace)
+            .map(|idx| last + idx)
+            .unwrap_or(window.end)
+    };
+
+    start..end
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    let mut space = false;
+
+    for c in s.chars() {
+        if c.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                buf.push(' ');
+                space = false;
+            }
+            buf.push(c);
+        }
+    }
+
+    if space {
+        buf.push(' ');
+    }
+
+    buf
+}
+
+fn floor_boundary(text: &str, mut idx: usize) -> usize {
+    while !text.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+fn ceil_boundary(text: &str, mut idx: usize) -> usize {
+    if idx >= text.len() {
+        return text.len();
+    }
+
+    while !text.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
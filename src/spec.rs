@@ -0,0 +1,324 @@
+use std::{io, iter::Peekable, str::CharIndices};
+
+use tantivy::{
+    query::{BooleanQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, RegexQuery, TermQuery},
+    schema::{FieldType, IndexRecordOption},
+    tokenizer::TokenStream,
+    Index, Term,
+};
+
+/// The pseudo-field matching the name of a document's file.
+const FILENAME: &str = "filename";
+
+/// The edit distance allowed by a fuzzy match.
+const FUZZY_DISTANCE: u8 = 1;
+
+/// One clause of a query spec, such as `+text:"exact phrase"`.
+#[derive(Debug)]
+struct Clause {
+    occur: Occur,
+    field: String,
+    fuzzy: bool,
+    value: String,
+}
+
+/// Parses a query spec (see --query-spec) into a query combining a sub-query for each clause.
+///
+/// A spec is a list of clauses separated by whitespace:
+///
+/// ```text
+/// spec   := clause (' ' clause)*
+/// clause := ['+' | '-'] field (':' | '~') value
+/// value  := word | '"' words '"'
+/// ```
+///
+/// `field:value` matches the value exactly: a single word as a term, several as a phrase.
+/// `field~word` matches the word within an edit distance of one. Any indexed text field may be
+/// named, along with `filename`, which matches the name of a document's file: exactly with `:`,
+/// or as a case-insensitive substring with `~`. Clauses marked `+` must match and those marked
+/// `-` must not; documents need to match at least one of any other clauses.
+pub fn parse(spec: &str, index: &Index) -> io::Result<Box<dyn Query>> {
+    let clauses = lex(spec)?;
+    if clauses.iter().all(|clause| clause.occur == Occur::MustNot) {
+        return Err(malformed("every clause is negated, so nothing can match"));
+    }
+
+    let subqueries = clauses
+        .into_iter()
+        .map(|clause| Ok((clause.occur, build(&clause, index)?)))
+        .collect::<io::Result<_>>()?;
+    Ok(Box::new(BooleanQuery::new(subqueries)))
+}
+
+fn lex(spec: &str) -> io::Result<Vec<Clause>> {
+    let mut chars = spec.char_indices().peekable();
+    let mut clauses = Vec::new();
+
+    loop {
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let Some(&(start, c)) = chars.peek() else {
+            break;
+        };
+
+        let occur = match c {
+            '+' => Occur::Must,
+            '-' => Occur::MustNot,
+            _ => Occur::Should,
+        };
+        if occur != Occur::Should {
+            chars.next();
+        }
+
+        let field = take_while(&mut chars, |c| c.is_alphanumeric() || c == '_');
+        if field.is_empty() {
+            return Err(malformed(format!(
+                "expected a field name at column {}",
+                start + 1
+            )));
+        }
+
+        let fuzzy = match chars.next() {
+            Some((_, ':')) => false,
+            Some((_, '~')) => true,
+            _ => return Err(malformed(format!("expected ':' or '~' after {field:?}"))),
+        };
+
+        let value = if chars.next_if(|&(_, c)| c == '"').is_some() {
+            let value = take_while(&mut chars, |c| c != '"');
+            if chars.next().is_none() {
+                return Err(malformed(format!("unterminated quote after {field:?}")));
+            }
+            value
+        } else {
+            take_while(&mut chars, |c| !c.is_whitespace())
+        };
+
+        if value.is_empty() {
+            return Err(malformed(format!("expected a value after {field:?}")));
+        }
+
+        clauses.push(Clause {
+            occur,
+            field,
+            fuzzy,
+            value,
+        });
+    }
+
+    if clauses.is_empty() {
+        return Err(malformed("the spec is empty"));
+    }
+
+    Ok(clauses)
+}
+
+fn take_while(chars: &mut Peekable<CharIndices>, f: impl Fn(char) -> bool) -> String {
+    let mut buf = String::new();
+    while let Some((_, c)) = chars.next_if(|&(_, c)| f(c)) {
+        buf.push(c);
+    }
+    buf
+}
+
+fn build(clause: &Clause, index: &Index) -> io::Result<Box<dyn Query>> {
+    let schema = index.schema();
+
+    // File names aren't indexed on their own, so they're matched with a pattern over the whole
+    // path.
+
+    if clause.field == FILENAME {
+        let name = regex::escape(&clause.value);
+        let pattern = if clause.fuzzy {
+            format!(r"(?i).*[/\\][^/\\]*{name}[^/\\]*")
+        } else {
+            format!(r".*[/\\]{name}")
+        };
+
+        let path = schema.get_field("path").map_err(invalid)?;
+        return Ok(Box::new(
+            RegexQuery::from_pattern(&pattern, path).map_err(invalid)?,
+        ));
+    }
+
+    let field = schema
+        .get_field(&clause.field)
+        .map_err(|_| malformed(format!("no field named {:?}", clause.field)))?;
+    let searchable = match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options.get_indexing_options().is_some(),
+        _ => false,
+    };
+    if !searchable {
+        return Err(malformed(format!(
+            "field {:?} can't be searched",
+            clause.field
+        )));
+    }
+
+    // The code tokenizer follows an identifier such as `parse_query` with its words, at the
+    // positions it spans. The identifier alone says all they do, and keeps a single identifier a
+    // single term; a phrase takes each term at its own position, since an identifier's words
+    // leave gaps between it and what follows.
+
+    let mut tokenizer = index.tokenizer_for_field(field).map_err(invalid)?;
+    let mut stream = tokenizer.token_stream(&clause.value);
+    let mut terms = Vec::new();
+    let mut span = None;
+    while stream.advance() {
+        let token = stream.token();
+        if span.is_some_and(|(from, to)| token.offset_from >= from && token.offset_to <= to) {
+            continue;
+        }
+        span = Some((token.offset_from, token.offset_to));
+        terms.push((token.position, Term::from_field_text(field, &token.text)));
+    }
+
+    match (terms.len(), clause.fuzzy) {
+        (0, _) => Err(malformed(format!(
+            "nothing to search for in {:?}",
+            clause.value
+        ))),
+        (1, false) => Ok(Box::new(TermQuery::new(
+            terms.remove(0).1,
+            IndexRecordOption::WithFreqs,
+        ))),
+        (1, true) => Ok(Box::new(FuzzyTermQuery::new(
+            terms.remove(0).1,
+            FUZZY_DISTANCE,
+            true,
+        ))),
+        (_, false) => Ok(Box::new(PhraseQuery::new_with_offset(terms))),
+        (_, true) => Err(malformed(format!(
+            "fuzzy matches take a single word, not {:?}",
+            clause.value
+        ))),
+    }
+}
+
+fn malformed(message: impl Into<String>) -> io::Error {
+    let message = message.into();
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("malformed query spec: {message}"),
+    )
+}
+
+fn invalid(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::{
+        collector::Count,
+        doc,
+        schema::{Schema, STORED, STRING, TEXT},
+    };
+
+    use super::*;
+
+    /// An index of two documents, with a text field, a path and a field that's only stored.
+    fn index() -> Index {
+        let mut builder = Schema::builder();
+        let text = builder.add_text_field("text", TEXT);
+        let path = builder.add_text_field("path", STRING | STORED);
+        let stored = builder.add_text_field("stored", STORED);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut writer = index.writer(15_000_000).unwrap();
+        writer
+            .add_document(doc!(
+                text => "the quick brown fox",
+                path => "/notes/Fox.txt",
+                stored => "x",
+            ))
+            .unwrap();
+        writer
+            .add_document(doc!(
+                text => "a lazy brown dog",
+                path => "/notes/dog.txt",
+                stored => "y",
+            ))
+            .unwrap();
+        writer.commit().unwrap();
+        index
+    }
+
+    fn count(spec: &str) -> usize {
+        let index = index();
+        let query = parse(spec, &index).unwrap();
+        index
+            .reader()
+            .unwrap()
+            .searcher()
+            .search(&query, &Count)
+            .unwrap()
+    }
+
+    fn error(spec: &str) -> String {
+        match parse(spec, &index()) {
+            Ok(query) => panic!("{spec:?} parsed as {query:?}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn clauses() {
+        let clauses = lex(r#"  +text:"quick  brown" -title~fxo path:x  "#).unwrap();
+        let clauses: Vec<_> = clauses
+            .iter()
+            .map(|clause| (clause.occur, &*clause.field, clause.fuzzy, &*clause.value))
+            .collect();
+        assert_eq!(
+            clauses,
+            [
+                (Occur::Must, "text", false, "quick  brown"),
+                (Occur::MustNot, "title", true, "fxo"),
+                (Occur::Should, "path", false, "x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn terms_phrases_and_fuzzy_matches() {
+        assert_eq!(count("text:brown"), 2);
+        assert_eq!(count(r#"text:"quick brown""#), 1);
+        assert_eq!(count(r#"text:"brown quick""#), 0);
+        assert_eq!(count("text~quikc"), 1);
+        assert_eq!(count("text:quikc"), 0);
+        assert_eq!(count("+text:brown -text:fox"), 1);
+        assert_eq!(count("text:fox text:dog"), 2);
+        assert_eq!(count("filename:Fox.txt"), 1);
+        assert_eq!(count("filename:fox.txt"), 0);
+        assert_eq!(count("filename~FOX"), 1);
+    }
+
+    #[test]
+    fn unknown_and_unsearchable_fields() {
+        assert!(error("nope:fox").contains(r#"no field named "nope""#));
+        assert!(error("stored:x").contains("can't be searched"));
+    }
+
+    #[test]
+    fn malformed_specs() {
+        for (spec, message) in [
+            (r#"text:"quick brown"#, "unterminated quote"),
+            ("", "the spec is empty"),
+            ("   ", "the spec is empty"),
+            ("text", "expected ':' or '~'"),
+            (":fox", "expected a field name at column 1"),
+            ("text:fox +:dog", "expected a field name at column 10"),
+            ("text:", "expected a value"),
+            (r#"text:"""#, "expected a value"),
+            ("-text:fox", "every clause is negated"),
+            ("text:--", "nothing to search for"),
+            (r#"text~"quick brown""#, "fuzzy matches take a single word"),
+        ] {
+            let error = error(spec);
+            assert!(
+                error.starts_with("malformed query spec: "),
+                "{spec:?}: {error}"
+            );
+            assert!(error.contains(message), "{spec:?}: {error}");
+        }
+    }
+}
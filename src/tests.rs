@@ -0,0 +1,661 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use super::*;
+
+/// A library of files written to a directory of its own and indexed in memory, file by file, as
+/// create-index would index them.
+struct Library {
+    root: PathBuf,
+    index: Index,
+    fields: SearchFields,
+    config: IndexConfig,
+}
+
+impl Library {
+    fn new(files: &[(&str, &str)]) -> Library {
+        Library::with_config(IndexConfig::default(), files)
+    }
+
+    fn with_config(config: IndexConfig, files: &[(&str, &str)]) -> Library {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let root = env::temp_dir().join(format!("search-test-{}-{id}", process::id()));
+        for (name, contents) in files {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let (schema, fields) = build_schema_stemmed(config.stem);
+        let mut library = Library {
+            root,
+            index: Index::create_in_ram(schema),
+            fields,
+            config,
+        };
+        library.reindex();
+        library
+    }
+
+    /// Indexes the library's files afresh, as after changing them.
+    fn reindex(&mut self) {
+        let (schema, _) = build_schema_stemmed(self.config.stem);
+        self.index = Index::create_in_ram(schema);
+        tokenizer::register(&self.index);
+
+        let mut writer: IndexWriter = self.index.writer(15_000_000).unwrap();
+        for path in read_paths(&self.root, &self.config) {
+            let data = fs::read(&path).unwrap();
+            let documents =
+                documents_from_data(&path, &data, &self.root, &self.config, &self.fields);
+            for document in documents.unwrap() {
+                writer.add_document(document).unwrap();
+            }
+        }
+        writer.commit().unwrap();
+    }
+
+    /// Searches the library as the command line would, returning the names of the files found
+    /// relative to the library root, best first.
+    fn search(&self, command_line: &[&str]) -> Vec<String> {
+        self.search_with_total(command_line).0
+    }
+
+    /// Searches the library, returning the files found and the total number of matches.
+    fn search_with_total(&self, command_line: &[&str]) -> (Vec<String>, usize) {
+        let args = Args::try_parse_from(["search"].iter().chain(command_line)).unwrap();
+        let searcher = self.index.reader().unwrap().searcher();
+        let parser = build_search_parser(&self.index, &self.fields, &self.config, &args).unwrap();
+        let query = build_query(&self.index, &parser, &self.fields, &self.config, &args).unwrap();
+        let (docs, total) = search(&searcher, &parser, &*query, &self.fields, &args, None).unwrap();
+
+        let names = docs
+            .iter()
+            .filter_map(|(_, doc)| get_path(doc, self.fields.path))
+            .map(|path| {
+                let path = Path::new(path);
+                let path = path.strip_prefix(&self.root).unwrap_or(path);
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        (names, total)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn extract_html_includes_alt_text() {
+    let (text, _) = extract_html(
+        r#"<p>A photo:</p><img src="fox.jpg" alt=" a red fox in the snow "><img src="x.png">"#,
+    );
+    assert!(text.contains("A photo:"), "{text:?}");
+    assert!(text.contains("a red fox in the snow"), "{text:?}");
+}
+
+#[test]
+fn alt_text_is_searchable() {
+    let library = Library::new(&[
+        (
+            "fox.html",
+            r#"<p>Our trip.</p><img src="fox.jpg" alt="a vixen asleep">"#,
+        ),
+        ("plain.html", "<p>Our trip, with no pictures.</p>"),
+    ]);
+    assert_eq!(library.search(&["vixen"]), ["fox.html"]);
+}
+
+#[test]
+fn one_per_term_finds_the_best_match_for_each_word() {
+    let library = Library::new(&[
+        ("both.txt", "apple banana"),
+        ("apples.txt", "apple apple apple cherry"),
+        ("bananas.txt", "banana banana banana"),
+    ]);
+    let mut found = library.search(&["--one-per-term", "apple", "banana"]);
+    found.sort();
+    assert_eq!(found, ["apples.txt", "bananas.txt"]);
+}
+
+#[test]
+fn one_per_term_respects_the_query_as_a_whole() {
+    let library = Library::new(&[
+        ("both.txt", "apple banana"),
+        ("apples.txt", "apple apple apple cherry"),
+        ("bananas.txt", "banana banana banana"),
+    ]);
+    assert_eq!(
+        library.search(&["--one-per-term", "\"apple banana\""]),
+        ["both.txt"]
+    );
+    assert_eq!(
+        library.search(&["--one-per-term", "apple AND banana"]),
+        ["both.txt"]
+    );
+    assert_eq!(
+        library.search(&["--one-per-term", "--", "apple", "-cherry"]),
+        ["both.txt"]
+    );
+}
+
+#[test]
+fn file_urls_are_percent_encoded() {
+    assert_eq!(file_url("/notes/a b.html"), "file:///notes/a%20b.html");
+    assert_eq!(
+        file_url("/notes/#1 50%?.html"),
+        "file:///notes/%231%2050%25%3F.html"
+    );
+}
+
+/// Twelve documents, of which those in `archive/` mention the word more often and so rank first.
+fn archived_library() -> Library {
+    let mut files = Vec::new();
+    for i in 0..6 {
+        files.push((format!("archive/old{i}.txt"), "wombat wombat wombat"));
+        files.push((format!("new{i}.txt"), "wombat"));
+    }
+    let files: Vec<_> = files
+        .iter()
+        .map(|(name, text)| (name.as_str(), *text))
+        .collect();
+    Library::new(&files)
+}
+
+#[test]
+fn excluded_paths_leave_full_pages_and_true_totals() {
+    let library = archived_library();
+    let (found, total) =
+        library.search_with_total(&["--exclude-path", "^.*/archive/", "--take", "4", "wombat"]);
+    assert_eq!(total, 6);
+    assert_eq!(found.len(), 4);
+    assert!(
+        found.iter().all(|name| name.starts_with("new")),
+        "{found:?}"
+    );
+
+    let (found, _) = library.search_with_total(&[
+        "--exclude-path",
+        "archive",
+        "--take",
+        "4",
+        "--page",
+        "1",
+        "wombat",
+    ]);
+    assert_eq!(found.len(), 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn mode_filter_leaves_full_pages_and_true_totals() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut library = archived_library();
+    for i in 0..6 {
+        let path = library.root.join(format!("new{i}.txt"));
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    library.reindex();
+
+    let (found, total) = library.search_with_total(&["--mode", "600", "--take", "4", "wombat"]);
+    assert_eq!(total, 6);
+    assert_eq!(found.len(), 4);
+    assert!(
+        found.iter().all(|name| name.starts_with("new")),
+        "{found:?}"
+    );
+}
+
+/// Times the searches that read modification times and sizes from fast fields, against ordering
+/// by path, which loads every match, in a library of 50,000 documents. Run it with
+///
+/// ```text
+/// cargo test --release -- --ignored --nocapture fast_field_timing
+/// ```
+#[test]
+#[ignore]
+fn fast_field_timing() {
+    const DOCS: u64 = 50_000;
+
+    let library = Library::new(&[]);
+    let fields = &library.fields;
+    let mut writer: IndexWriter = library.index.writer(50_000_000).unwrap();
+    for i in 0..DOCS {
+        writer
+            .add_document(doc! {
+                fields.path => format!("/bench/{i}.txt"),
+                fields.text => "numbat",
+                fields.modified => i * 7919 % DOCS,
+                fields.size => i * 104_729 % DOCS,
+                fields.priority => 1.0,
+            })
+            .unwrap();
+    }
+    writer.commit().unwrap();
+
+    let time = |command_line: &[&str]| {
+        let start = Instant::now();
+        let (found, total) = library.search_with_total(command_line);
+        let elapsed = start.elapsed();
+        assert_eq!(found.len(), 10);
+        println!("{:>8.1?}  {total:>6}  {}", elapsed, command_line.join(" "));
+        elapsed
+    };
+
+    let modified = time(&["--sort", "modified", "numbat"]);
+    let size = time(&["--sort", "size", "numbat"]);
+    time(&["--larger-than", "25000", "numbat"]);
+    time(&["--sort", "size", "--smaller-than", "1000", "numbat"]);
+    let path = time(&["--sort", "path", "numbat"]);
+
+    assert!(modified < path && size < path);
+}
+
+#[test]
+fn dir_terms_index_the_directories_above_a_file() {
+    let files = [
+        ("recipes/desserts/cake.txt", "flour and sugar"),
+        ("notes.txt", "nothing about food"),
+    ];
+    let config = IndexConfig {
+        dir_terms: true,
+        ..Default::default()
+    };
+    let library = Library::with_config(config, &files);
+
+    let path = library.root.join("recipes/desserts/cake.txt");
+    let data = fs::read(&path).unwrap();
+    let documents = documents_from_data(
+        &path,
+        &data,
+        &library.root,
+        &library.config,
+        &library.fields,
+    );
+    let terms: Vec<_> = documents.unwrap()[0]
+        .get_all(library.fields.path_terms)
+        .filter_map(|value| value.as_str().map(ToOwned::to_owned))
+        .collect();
+    assert_eq!(terms, ["recipes", "desserts"]);
+
+    assert_eq!(library.search(&["desserts"]), ["recipes/desserts/cake.txt"]);
+    assert!(Library::new(&files).search(&["desserts"]).is_empty());
+}
+
+#[test]
+fn hidden_and_ignored_files_are_skipped() {
+    let files = [
+        ("visible.txt", "wombat"),
+        (".secret.txt", "wombat"),
+        (".notes/todo.txt", "wombat"),
+        ("build/out.txt", "wombat"),
+        (".gitignore", "build/\n"),
+    ];
+    let library = Library::new(&files);
+    assert_eq!(library.search(&["wombat"]), ["visible.txt"]);
+
+    let config = IndexConfig {
+        include_hidden: true,
+        ..Default::default()
+    };
+    let library = Library::with_config(config, &files);
+    let mut found = library.search(&["wombat"]);
+    found.sort();
+    assert_eq!(found, [".notes/todo.txt", ".secret.txt", "visible.txt"]);
+}
+
+#[test]
+fn markdown_frontmatter_is_read() {
+    let config = IndexConfig {
+        extensions: Some(vec!["md".to_owned()]),
+        ..Default::default()
+    };
+    let library = Library::with_config(
+        config,
+        &[(
+            "post.md",
+            "---\nid: post-1\npriority: 2\ntags: [draft]\n---\n# Echidna\n\nSpines.\n",
+        )],
+    );
+
+    let path = library.root.join("post.md");
+    let data = fs::read(&path).unwrap();
+    let documents = documents_from_data(
+        &path,
+        &data,
+        &library.root,
+        &library.config,
+        &library.fields,
+    );
+    let document = &documents.unwrap()[0];
+    let fields = &library.fields;
+    let id = document.get_first(fields.id).and_then(|x| x.as_str());
+    assert_eq!(id, Some("post-1"));
+    let priority = document.get_first(fields.priority).and_then(|x| x.as_f64());
+    assert_eq!(priority, Some(2.0));
+    assert_eq!(library.search(&["--tag", "draft", "spines"]), ["post.md"]);
+}
+
+#[test]
+fn tag_filter_excludes_untagged_documents() {
+    let config = IndexConfig {
+        extensions: Some(vec!["md".to_owned(), "txt".to_owned()]),
+        ..Default::default()
+    };
+    let files = [
+        ("front.md", "---\ntags: [draft, idea]\n---\nplatypus"),
+        ("sidecar.txt", "platypus"),
+        ("sidecar.txt.tags", "draft\n"),
+        ("untagged.txt", "platypus"),
+        ("other.md", "---\ntags: [final]\n---\nplatypus"),
+    ];
+    let library = Library::with_config(config, &files);
+
+    let mut found = library.search(&["--tag", "draft", "platypus"]);
+    found.sort();
+    assert_eq!(found, ["front.md", "sidecar.txt"]);
+
+    assert_eq!(
+        library.search(&["--tag", "draft", "--tag", "idea", "platypus"]),
+        ["front.md"]
+    );
+    assert_eq!(library.search(&["platypus"]).len(), 4);
+}
+
+#[test]
+fn default_operator_decides_between_any_and_all_words() {
+    let files = [
+        ("both.txt", "koala emu"),
+        ("koala.txt", "koala"),
+        ("emu.txt", "emu"),
+    ];
+    let sorted = |mut found: Vec<String>| {
+        found.sort();
+        found
+    };
+
+    let library = Library::new(&files);
+    assert_eq!(
+        sorted(library.search(&["koala", "emu"])),
+        ["both.txt", "emu.txt", "koala.txt"]
+    );
+    assert_eq!(library.search(&["--all", "koala", "emu"]), ["both.txt"]);
+
+    let config = IndexConfig {
+        default_and: true,
+        ..Default::default()
+    };
+    let library = Library::with_config(config, &files);
+    assert_eq!(library.search(&["koala", "emu"]), ["both.txt"]);
+    assert_eq!(
+        sorted(library.search(&["--any", "koala", "emu"])),
+        ["both.txt", "emu.txt", "koala.txt"]
+    );
+}
+
+#[test]
+fn comments_only_indexes_comments_and_not_code() {
+    let config = IndexConfig {
+        comments_only: true,
+        ..Default::default()
+    };
+    let library = Library::with_config(
+        config,
+        &[(
+            "lib.rs",
+            "/// Parses a wallaby.\nfn quokka<'a>(s: &'a str) -> &'a str { \"// dingo\" }\n",
+        )],
+    );
+    assert_eq!(library.search(&["wallaby"]), ["lib.rs"]);
+    assert!(library.search(&["quokka"]).is_empty());
+    assert!(library.search(&["dingo"]).is_empty());
+}
+
+#[test]
+fn boost_exact_ranks_the_exact_word_form_first() {
+    let config = IndexConfig {
+        stem: true,
+        ..Default::default()
+    };
+    let library = Library::with_config(
+        config,
+        &[("a.txt", "runs"), ("b.txt", "running late again today")],
+    );
+
+    // Both documents stem to the same word, and the shorter one wins on length alone. (They're
+    // named so that their titles match nothing.)
+    assert_eq!(library.search(&["running"]), ["a.txt", "b.txt"]);
+    assert_eq!(
+        library.search(&["--boost-exact", "running"]),
+        ["b.txt", "a.txt"]
+    );
+}
+
+#[test]
+fn priority_lifts_a_document_over_an_equal_one() {
+    // Each document is boosted in turn, so that neither can come first by chance.
+
+    for (boosted, other) in [("a.txt", "b.txt"), ("b.txt", "a.txt")] {
+        let sidecar = format!("{boosted}.priority");
+        let library = Library::new(&[("a.txt", "bilby"), ("b.txt", "bilby"), (&sidecar, "2")]);
+        assert_eq!(library.search(&["bilby"]), [boosted, other]);
+    }
+
+    for (boosted, other) in [("a.txt", "b.txt"), ("b.txt", "a.txt")] {
+        let files: Vec<_> = [boosted, other]
+            .into_iter()
+            .zip(["3", "1"])
+            .map(|(name, priority)| (name, format!("---\npriority: {priority}\n---\nbilby")))
+            .collect();
+        let files: Vec<_> = files
+            .iter()
+            .map(|(name, text)| (*name, text.as_str()))
+            .collect();
+        assert_eq!(Library::new(&files).search(&["bilby"]), [boosted, other]);
+    }
+}
+
+#[test]
+fn wildcard_matches_every_word_with_the_prefix() {
+    let library = Library::new(&[
+        ("a.txt", "the config file"),
+        ("b.txt", "a configuration option"),
+        ("c.txt", "a confident guess"),
+    ]);
+    for command_line in [&["--wildcard", "config*"][..], &["config*"]] {
+        let mut found = library.search(command_line);
+        found.sort();
+        assert_eq!(found, ["a.txt", "b.txt"], "{command_line:?}");
+    }
+}
+
+#[test]
+fn prefixes_match_titles_and_directories_too() {
+    let config = IndexConfig {
+        dir_terms: true,
+        ..IndexConfig::default()
+    };
+    let library = Library::with_config(
+        config,
+        &[
+            ("a.txt", "the config file"),
+            ("configurator.txt", "a tool for settings"),
+            ("configs/e.txt", "plain settings"),
+            ("c.txt", "a confident guess"),
+        ],
+    );
+
+    let mut found = library.search(&["config*"]);
+    found.sort();
+    assert_eq!(found, ["a.txt", "configs/e.txt", "configurator.txt"]);
+
+    // The fields a prefix is matched in are grouped, so that requiring every word requires the
+    // prefix in any one of them.
+    let mut found = library.search(&["--all", "config*", "settings"]);
+    found.sort();
+    assert_eq!(found, ["configs/e.txt", "configurator.txt"]);
+    assert_eq!(library.search(&["title:config*"]), ["configurator.txt"]);
+
+    let found = library.search(&["--one-per-term", "confident", "config*"]);
+    assert_eq!(found.len(), 2, "{found:?}");
+    assert_eq!(found[0], "c.txt");
+}
+
+/// Packs a directory holding an index file under a manifest naming the library and describing
+/// its schema, returning the fixture holding the archive and the archive's path.
+fn packed_library(name: &str, schema: Schema) -> (Library, PathBuf) {
+    let index = Library::new(&[("meta.json", "{}")]);
+    let out = Library::new(&[("README", "")]);
+    let archive = out.root.join("library.pack");
+    let manifest = PackManifest {
+        name: name.to_owned(),
+        roots: vec![index.root.clone()],
+        config: IndexConfig::default(),
+        schema,
+    };
+    pack::write(&archive, &manifest, &index.root).unwrap();
+    (out, archive)
+}
+
+#[test]
+fn unpack_refuses_names_outside_the_storage_dir() {
+    let schema = build_schema_stemmed(false).0;
+    for name in ["../../escape", "/tmp/escape", "..", ""] {
+        let (_out, archive) = packed_library(name, schema.clone());
+        let unpack = UnpackCmd {
+            archive,
+            name: None,
+            root: Vec::new(),
+            force: true,
+        };
+        let error = unpack_library(&unpack).unwrap_err().to_string();
+        assert!(error.contains("can't be used as a library name"), "{error}");
+    }
+
+    let (_out, archive) = packed_library("fine", schema);
+    let unpack = UnpackCmd {
+        archive,
+        name: Some(String::from("../escape")),
+        root: Vec::new(),
+        force: true,
+    };
+    let error = unpack_library(&unpack).unwrap_err().to_string();
+    assert!(error.contains("can't be used as a library name"), "{error}");
+}
+
+#[test]
+fn unpack_refuses_an_incompatible_schema() {
+    let mut builder = Schema::builder();
+    builder.add_text_field("contents", schema::TEXT | schema::STORED);
+    let (_out, archive) = packed_library("incompatible", builder.build());
+
+    let unpack = UnpackCmd {
+        archive,
+        name: None,
+        root: Vec::new(),
+        force: true,
+    };
+    let error = unpack_library(&unpack).unwrap_err().to_string();
+    assert!(error.contains("incompatible version"), "{error}");
+}
+
+#[test]
+fn query_specs_match_identifiers() {
+    let library = Library::new(&[
+        ("a.txt", "call parse_query then parseQuery on the input"),
+        ("b.txt", "parse the query by hand"),
+    ]);
+    assert_eq!(library.search(&["parse_query"]), ["a.txt"]);
+    assert_eq!(
+        library.search(&["--query-spec", "text:parse_query"]),
+        ["a.txt"]
+    );
+    assert_eq!(
+        library.search(&["--query-spec", "text:parseQuery"]),
+        ["a.txt"]
+    );
+    assert_eq!(
+        library.search(&["--query-spec", r#"text:"parse_query then""#]),
+        ["a.txt"]
+    );
+    assert_eq!(
+        library.search(&["--query-spec", r#"text:"parseQuery on the""#]),
+        ["a.txt"]
+    );
+    assert_eq!(
+        library.search(&["--query-spec", "text~parseQeury"]),
+        ["a.txt"]
+    );
+    assert!(library
+        .search(&["--query-spec", r#"text:"parse_query on""#])
+        .is_empty());
+}
+
+#[test]
+fn sidecar_edits_change_stamps_and_hashes() {
+    let library = Library::new(&[("a.txt", "some words")]);
+    let path = library.root.join("a.txt");
+    let data = fs::read(&path).unwrap();
+
+    let bare = FileStamp::with_hash(&path, &data).unwrap();
+    assert_eq!(bare.hash, Some(document_hash(&path, &data)));
+    assert!(bare.sidecars.is_empty());
+
+    let mut previous = bare.clone();
+    for (extension, contents) in [
+        ("tags", "draft"),
+        ("tags", "draft\nfinal"),
+        ("priority", "2"),
+    ] {
+        fs::write(sidecar_path(&path, extension), contents).unwrap();
+        let stamp = FileStamp::with_hash(&path, &data).unwrap();
+        assert!(!previous.matches(&stamp), "{extension}: {contents}");
+        assert_ne!(previous.hash, stamp.hash, "{extension}: {contents}");
+        previous = stamp;
+    }
+
+    // Without its sidecars, the file is as it was to begin with.
+    fs::remove_file(sidecar_path(&path, "tags")).unwrap();
+    fs::remove_file(sidecar_path(&path, "priority")).unwrap();
+    let stamp = FileStamp::with_hash(&path, &data).unwrap();
+    assert!(bare.matches(&stamp));
+    assert_eq!(bare.hash, stamp.hash);
+}
+
+#[test]
+#[cfg(unix)]
+fn one_per_term_respects_the_mode_filter() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut library = Library::new(&[
+        ("a.txt", "apple apple apple banana"),
+        ("b.txt", "apple banana banana banana"),
+        ("c.txt", "apple and a banana"),
+    ]);
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        let mode = if name == "c.txt" { 0o600 } else { 0o644 };
+        let path = library.root.join(name);
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+    library.reindex();
+
+    assert_eq!(
+        library.search(&["--one-per-term", "apple banana"]),
+        ["a.txt", "b.txt"]
+    );
+    assert_eq!(
+        library.search(&["--one-per-term", "--mode", "600", "apple banana"]),
+        ["c.txt"]
+    );
+    assert!(library
+        .search(&["--one-per-term", "--mode", "700", "apple banana"])
+        .is_empty());
+}
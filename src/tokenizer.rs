@@ -0,0 +1,253 @@
+use tantivy::tokenizer::{
+    Language, LowerCaser, RemoveLongFilter, Stemmer, TextAnalyzer, Token, TokenStream, Tokenizer,
+};
+
+/// The name under which the code-friendly tokenizer is registered with each index.
+pub const CODE: &str = "code";
+
+/// The code-friendly tokenizer followed by the english stemmer, for libraries created with
+/// --stem.
+pub const CODE_STEM: &str = "code_stem";
+
+/// Registers the tokenizers the schema names with an index. Tantivy only knows its own
+/// tokenizers, so this must be done whenever an index is opened or created, before it's searched
+/// or written.
+pub fn register(index: &tantivy::Index) {
+    let code = || {
+        TextAnalyzer::builder(CodeTokenizer)
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+    };
+    index.tokenizers().register(CODE, code().build());
+    index.tokenizers().register(
+        CODE_STEM,
+        code().filter(Stemmer::new(Language::English)).build(),
+    );
+}
+
+/// Splits text into words as tantivy's simple tokenizer does, except that underscores are part
+/// of a word, and an identifier made up of several words is indexed both whole and as its words.
+///
+/// `parse_query`, `parseQuery` and `ParseQuery` each yield the identifier itself followed by
+/// `parse` and `query`. The identifier shares its position with its first word, so that
+/// searching for the identifier (which is tokenized the same way) matches it as a phrase, while
+/// searching for either word alone still finds it. Words are split at underscores, at a change
+/// from lower case or a digit to upper case, and before the last capital of a run of capitals
+/// followed by lower case, so that `HTTPServer` is `http` and `server`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CodeTokenizer;
+
+pub struct CodeTokenStream {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Tokenizer for CodeTokenizer {
+    type TokenStream<'a> = CodeTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CodeTokenStream {
+        let mut tokens = Vec::new();
+        let mut position = 0;
+
+        for (start, word) in words(text) {
+            let parts = parts(word);
+            if parts.len() > 1 || parts.first().is_some_and(|&(_, part)| part != word) {
+                tokens.push(token(start, word, position));
+            }
+            for (offset, part) in parts {
+                tokens.push(token(start + offset, part, position));
+                position += 1;
+            }
+        }
+
+        CodeTokenStream { tokens, next: 0 }
+    }
+}
+
+impl TokenStream for CodeTokenStream {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+fn token(offset: usize, text: &str, position: usize) -> Token {
+    Token {
+        offset_from: offset,
+        offset_to: offset + text.len(),
+        position,
+        text: text.to_owned(),
+        position_length: 1,
+    }
+}
+
+/// Finds the runs of letters, digits and underscores in a text, with their offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+/// Splits an identifier into its words, with their offsets within it.
+fn parts(word: &str) -> Vec<(usize, &str)> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = None;
+
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        if c == '_' {
+            if let Some(from) = start.take() {
+                parts.push((from, &word[from..offset]));
+            }
+            continue;
+        }
+
+        let prev = i.checked_sub(1).map(|i| chars[i].1);
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|prev| {
+                prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(char::is_lowercase))
+            });
+
+        match start {
+            Some(from) if boundary => {
+                parts.push((from, &word[from..offset]));
+                start = Some(offset);
+            }
+            None => start = Some(offset),
+            Some(_) => {}
+        }
+    }
+    if let Some(from) = start {
+        parts.push((from, &word[from..]));
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text and position of each token of a text, before any filters.
+    fn tokens(text: &str) -> Vec<(String, usize)> {
+        let mut stream = CodeTokenizer.token_stream(text);
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            let token = stream.token();
+            assert_eq!(&text[token.offset_from..token.offset_to], token.text);
+            tokens.push((token.text.clone(), token.position));
+        }
+        tokens
+    }
+
+    fn expect(expected: &[(&str, usize)]) -> Vec<(String, usize)> {
+        expected
+            .iter()
+            .map(|&(text, position)| (text.to_owned(), position))
+            .collect()
+    }
+
+    #[test]
+    fn identifiers_are_kept_whole_and_split() {
+        assert_eq!(
+            tokens("parse_query"),
+            expect(&[("parse_query", 0), ("parse", 0), ("query", 1)])
+        );
+        assert_eq!(
+            tokens("parseQuery"),
+            expect(&[("parseQuery", 0), ("parse", 0), ("Query", 1)])
+        );
+        assert_eq!(
+            tokens("ParseQuery"),
+            expect(&[("ParseQuery", 0), ("Parse", 0), ("Query", 1)])
+        );
+        assert_eq!(
+            tokens("HTTPServer"),
+            expect(&[("HTTPServer", 0), ("HTTP", 0), ("Server", 1)])
+        );
+        assert_eq!(
+            tokens("__init__ SCREAMING_CASE"),
+            expect(&[
+                ("__init__", 0),
+                ("init", 0),
+                ("SCREAMING_CASE", 1),
+                ("SCREAMING", 1),
+                ("CASE", 2)
+            ])
+        );
+    }
+
+    #[test]
+    fn plain_words_and_positions() {
+        assert_eq!(
+            tokens("call parse_query, then stop."),
+            expect(&[
+                ("call", 0),
+                ("parse_query", 1),
+                ("parse", 1),
+                ("query", 2),
+                ("then", 3),
+                ("stop", 4)
+            ])
+        );
+        assert_eq!(tokens("_ -- __"), expect(&[]));
+    }
+
+    #[test]
+    fn digits() {
+        assert_eq!(
+            tokens("utf8Decode x86_64 v2"),
+            expect(&[
+                ("utf8Decode", 0),
+                ("utf8", 0),
+                ("Decode", 1),
+                ("x86_64", 2),
+                ("x86", 2),
+                ("64", 3),
+                ("v2", 4)
+            ])
+        );
+    }
+
+    #[test]
+    fn non_ascii() {
+        assert_eq!(
+            tokens("naïveÉcole straße ÆtherΣίσυφος"),
+            expect(&[
+                ("naïveÉcole", 0),
+                ("naïve", 0),
+                ("École", 1),
+                ("straße", 2),
+                ("ÆtherΣίσυφος", 3),
+                ("Æther", 3),
+                ("Σίσυφος", 4)
+            ])
+        );
+    }
+
+    #[test]
+    fn registered_analyzer_lowercases() {
+        let schema = tantivy::schema::Schema::builder().build();
+        let index = tantivy::Index::create_in_ram(schema);
+        register(&index);
+
+        let mut analyzer = index.tokenizers().get(CODE).unwrap();
+        let mut stream = analyzer.token_stream("HTTPServer");
+        let mut texts = Vec::new();
+        while stream.advance() {
+            texts.push(stream.token().text.clone());
+        }
+        assert_eq!(texts, ["httpserver", "http", "server"]);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{encoding, inflate};
+
+/// A zip archive that couldn't be read, or a file missing from it.
+#[derive(Clone, Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<inflate::Error> for Error {
+    fn from(e: inflate::Error) -> Self {
+        Error(e.to_string())
+    }
+}
+
+/// The files of a zip archive, found through its central directory.
+///
+/// Only what epubs and Office documents use is supported: files stored or deflated, in an archive
+/// small enough not to need the zip64 extensions.
+pub struct Archive<'a> {
+    data: &'a [u8],
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    method: u16,
+    compressed: usize,
+    offset: usize,
+}
+
+impl<'a> Archive<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        // The central directory ends with a record of 22 bytes and a comment of up to 64K, so
+        // the record is found by searching backward from the end.
+
+        let last = data.len().checked_sub(22).ok_or_else(not_zip)?;
+        let first = last.saturating_sub(0xffff);
+        let end = (first..=last)
+            .rev()
+            .find(|&at| data[at..].starts_with(b"PK\x05\x06"))
+            .ok_or_else(not_zip)?;
+
+        let count = read_u16(data, end + 10)?;
+        let mut at = read_u32(data, end + 16)? as usize;
+        let mut entries = HashMap::new();
+
+        for _ in 0..count {
+            if !data
+                .get(at..)
+                .is_some_and(|rest| rest.starts_with(b"PK\x01\x02"))
+            {
+                return Err(Error("the central directory is corrupt".into()));
+            }
+
+            let compressed = read_u32(data, at + 20)?;
+            if compressed == u32::MAX {
+                return Err(Error("zip64 archives aren't supported".into()));
+            }
+
+            let name_len = usize::from(read_u16(data, at + 28)?);
+            let extra_len = usize::from(read_u16(data, at + 30)?);
+            let comment_len = usize::from(read_u16(data, at + 32)?);
+            let name = data
+                .get(at + 46..at + 46 + name_len)
+                .ok_or_else(truncated)?;
+
+            entries.insert(
+                String::from_utf8_lossy(name).into_owned(),
+                Entry {
+                    method: read_u16(data, at + 10)?,
+                    compressed: compressed as usize,
+                    offset: read_u32(data, at + 42)? as usize,
+                },
+            );
+            at += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(Self { data, entries })
+    }
+
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| Error(format!("{name} is missing")))?;
+
+        // The local header repeats the name and has its own extra field, which needn't match the
+        // central directory's, so the contents start after both.
+
+        let at = entry.offset;
+        if !self
+            .data
+            .get(at..)
+            .is_some_and(|rest| rest.starts_with(b"PK\x03\x04"))
+        {
+            return Err(Error(format!("{name} is corrupt")));
+        }
+        let name_len = usize::from(read_u16(self.data, at + 26)?);
+        let extra_len = usize::from(read_u16(self.data, at + 28)?);
+        let start = at + 30 + name_len + extra_len;
+        let contents = self
+            .data
+            .get(start..start + entry.compressed)
+            .ok_or_else(truncated)?;
+
+        match entry.method {
+            0 => Ok(contents.to_vec()),
+            8 => Ok(inflate::inflate(contents)?),
+            method => Err(Error(format!(
+                "{name} uses unsupported compression method {method}"
+            ))),
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Result<String, Error> {
+        Ok(encoding::decode(&self.read(name)?, true))
+    }
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, Error> {
+    let bytes = data.get(at..at + 2).ok_or_else(truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, Error> {
+    let bytes = data.get(at..at + 4).ok_or_else(truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn not_zip() -> Error {
+    Error("not a zip archive".into())
+}
+
+fn truncated() -> Error {
+    Error("the archive is truncated".into())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// "hello hello hello", deflated.
+    static DEFLATED: &[u8] = &[203, 72, 205, 201, 201, 87, 200, 64, 144, 0];
+
+    /// Builds an archive of files, each given with its compression method and its contents as
+    /// stored. Checksums are left as zero, since they're never checked.
+    pub(crate) fn archive(files: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+
+        for &(name, method, contents) in files {
+            let offset = data.len() as u32;
+            let (name_len, size) = (name.len() as u16, contents.len() as u32);
+
+            data.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00");
+            data.extend_from_slice(&method.to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&name_len.to_le_bytes());
+            data.extend_from_slice(&[0; 2]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(contents);
+
+            directory.extend_from_slice(b"PK\x01\x02\x14\x00\x14\x00\x00\x00");
+            directory.extend_from_slice(&method.to_le_bytes());
+            directory.extend_from_slice(&[0; 8]);
+            directory.extend_from_slice(&size.to_le_bytes());
+            directory.extend_from_slice(&size.to_le_bytes());
+            directory.extend_from_slice(&name_len.to_le_bytes());
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let (count, start) = (files.len() as u16, data.len() as u32);
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+        data.extend_from_slice(&count.to_le_bytes());
+        data.extend_from_slice(&count.to_le_bytes());
+        data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        data.extend_from_slice(&start.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    fn sample() -> Vec<u8> {
+        archive(&[
+            ("stored.txt", 0, b"as it stands"),
+            ("dir/deflated.txt", 8, DEFLATED),
+        ])
+    }
+
+    #[test]
+    fn stored_and_deflated_files() {
+        let data = sample();
+        let archive = Archive::new(&data).unwrap();
+        assert_eq!(archive.read("stored.txt").unwrap(), b"as it stands");
+        assert_eq!(
+            archive.text("dir/deflated.txt").unwrap(),
+            "hello hello hello"
+        );
+        assert!(archive.read("missing.txt").is_err());
+    }
+
+    #[test]
+    fn archive_comment() {
+        let mut data = sample();
+        let len = data.len();
+        data[len - 2..].copy_from_slice(&7u16.to_le_bytes());
+        data.extend_from_slice(b"comment");
+        let archive = Archive::new(&data).unwrap();
+        assert_eq!(archive.read("stored.txt").unwrap(), b"as it stands");
+    }
+
+    #[test]
+    fn unsupported_files() {
+        let data = archive(&[("bzip2.txt", 12, b"BZh")]);
+        assert!(Archive::new(&data).unwrap().read("bzip2.txt").is_err());
+
+        // A compressed size of u32::MAX defers to the zip64 extra field.
+        let mut data = archive(&[("big.txt", 0, b"")]);
+        let at = find(&data, b"PK\x01\x02") + 20;
+        data[at..at + 4].fill(0xff);
+        assert!(Archive::new(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_archives() {
+        let data = sample();
+        for len in 0..data.len() {
+            assert!(Archive::new(&data[..len]).is_err(), "{len}");
+        }
+
+        // An archive whose directory is intact, but whose files have been cut away.
+        let directory = find(&data, b"PK\x01\x02");
+        let mut data = data[directory..].to_vec();
+        let len = data.len();
+        data[len - 6..len - 2].fill(0);
+        let archive = Archive::new(&data).unwrap();
+        assert!(archive.read("stored.txt").is_err());
+        assert!(archive.read("dir/deflated.txt").is_err());
+    }
+
+    #[test]
+    fn corrupt_archives() {
+        let mut data = sample();
+        let at = find(&data, b"dir/deflated.txt") + b"dir/deflated.txt".len();
+        data[at] = 0x07;
+        assert!(Archive::new(&data)
+            .unwrap()
+            .read("dir/deflated.txt")
+            .is_err());
+
+        // Whatever a damaged archive holds, reading it mustn't panic.
+        let data = sample();
+        for idx in 0..data.len() {
+            for byte in [0x00, 0xff, data[idx] ^ 0x80] {
+                let mut data = data.clone();
+                data[idx] = byte;
+                if let Ok(archive) = Archive::new(&data) {
+                    let _ = archive.read("stored.txt");
+                    let _ = archive.read("dir/deflated.txt");
+                }
+            }
+        }
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .unwrap()
+    }
+}